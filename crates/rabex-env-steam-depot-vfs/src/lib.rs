@@ -1,7 +1,7 @@
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use rabex_env::resolver::EnvResolver;
+use rabex_env::resolver::{EnvResolver, LazyPathIndex, PathIndex};
 use steam_depot_vfs::FileKind;
 use steam_depot_vfs::chunk_store::{ChunkStore, FsCacheStore};
 use steam_depot_vfs::fs::{DepotFileReader, DepotManifestStore};
@@ -14,6 +14,8 @@ pub struct SteamDepotGameFiles<C: ChunkStore = FsCacheStore> {
     data_dir: PathBuf,
     manifest_store: Arc<DepotManifestStore<C>>,
     handle: Handle,
+    /// Files of the manifest under the data dir, built on first listing
+    index: LazyPathIndex,
 }
 
 impl<C: ChunkStore> SteamDepotGameFiles<C> {
@@ -23,6 +25,7 @@ impl<C: ChunkStore> SteamDepotGameFiles<C> {
             data_dir,
             manifest_store,
             handle: Handle::current(),
+            index: LazyPathIndex::default(),
         })
     }
 
@@ -78,6 +81,25 @@ impl<C: ChunkStore> SteamDepotGameFiles<C> {
 
         self.data_dir.join(path)
     }
+
+    fn index(&self) -> Arc<PathIndex> {
+        // the manifest never changes, so the index is never invalidated
+        self.index
+            .get_or_try_build(|| {
+                Ok(PathIndex::new(
+                    self.manifest_store
+                        .manifest()
+                        .normal_paths()
+                        .filter_map(|p| {
+                            Path::new(p)
+                                .strip_prefix(&self.data_dir)
+                                .ok()
+                                .map(PathBuf::from)
+                        }),
+                ))
+            })
+            .expect("building the index is infallible")
+    }
 }
 
 fn format_depot_path(path: &Path) -> Result<String, std::io::Error> {
@@ -129,19 +151,16 @@ impl<C: ChunkStore> EnvResolver for SteamDepotGameFiles<C> {
         Ok(rabex_env::env::Data::InMemory(out))
     }
 
+    fn exists(&self, path: &Path) -> Result<bool, std::io::Error> {
+        let path = self.depot_path(path);
+        Ok(path
+            .strip_prefix(&self.data_dir)
+            .is_ok_and(|path| self.index().contains(path)))
+    }
+
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(skip_all))]
     fn all_files(&self) -> Result<Vec<PathBuf>, std::io::Error> {
-        Ok(self
-            .manifest_store
-            .manifest()
-            .normal_paths()
-            .filter_map(|p| {
-                Path::new(p)
-                    .strip_prefix(&self.data_dir)
-                    .ok()
-                    .map(PathBuf::from)
-            })
-            .collect())
+        Ok(self.index().files().map(Path::to_owned).collect())
     }
 
     #[cfg_attr(
@@ -149,15 +168,6 @@ impl<C: ChunkStore> EnvResolver for SteamDepotGameFiles<C> {
         tracing::instrument(skip_all, fields(prefix = %prefix.display()))
     )]
     fn list_under(&self, prefix: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
-        // PERF: O(n)
-        Ok(self
-            .manifest_store
-            .manifest()
-            .normal_paths()
-            .filter_map(|p| {
-                let rel = Path::new(p).strip_prefix(&self.data_dir).ok()?;
-                rel.starts_with(prefix).then(|| rel.to_owned())
-            })
-            .collect())
+        Ok(self.index().list_under(prefix))
     }
 }
@@ -7,6 +7,7 @@
 //! `SerializedFileHandle`.
 
 use std::io::Cursor;
+use std::path::PathBuf;
 
 use rabex_env::Environment;
 use rabex_env::handle::SerializedFileHandle;
@@ -18,9 +19,9 @@ use rabex_env::rabex::objects::{ClassId, PPtr, TypedPPtr};
 use rabex_env::rabex::tpk::TpkTypeTreeBlob;
 use rabex_env::rabex::typetree::TypeTreeProvider;
 use rabex_env::rabex::typetree::typetree_cache::sync::TypeTreeCache;
-use rabex_env::resolver::MemResolver;
+use rabex_env::resolver::{EnvResolver, MemResolver};
 use rabex_env::unity::types::{
-    ComponentPair, GameObject, MonoBehaviour, MonoScript, PreloadData, Transform,
+    AssetBundle, ComponentPair, GameObject, MonoBehaviour, MonoScript, PreloadData, Transform,
 };
 
 /// Unity version every fixture is built with. The embedded TPK has full coverage for it.
@@ -32,14 +33,37 @@ pub type Builder<'a> = SerializedFileBuilder<'a, TypeTreeCache<TpkTypeTreeBlob>>
 /// Assemble a `SerializedFile` through `f` and return its bytes. Owns the version / TPK / offset-map
 /// prelude for the duration of the callback so fixtures don't each repeat it.
 pub fn build_file(f: impl FnOnce(&mut Builder<'_>)) -> Vec<u8> {
+    build(true, f)
+}
+
+/// Like [`build_file`], but without embedded typetrees, as in most player builds.
+/// Readers fall back to the TPK, which only knows the `MonoBehaviour` header for scripts.
+pub fn build_stripped_file(f: impl FnOnce(&mut Builder<'_>)) -> Vec<u8> {
+    build(false, f)
+}
+
+fn build(enable_typetree: bool, f: impl FnOnce(&mut Builder<'_>)) -> Vec<u8> {
     let unity_version: UnityVersion = TEST_UNITY_VERSION.parse().unwrap();
     let tpk = TypeTreeCache::new(TpkTypeTreeBlob::embedded());
     let common = build_common_offset_map(&tpk.inner, &unity_version);
-    let mut sfb = SerializedFileBuilder::new(&unity_version, &tpk, &common, true);
+    let mut sfb = SerializedFileBuilder::new(&unity_version, &tpk, &common, enable_typetree);
     f(&mut sfb);
     sfb.write_vec().unwrap()
 }
 
+/// The [`Environment`] of [`mem_env`].
+pub type MemEnv = Environment<MemResolver, TypeTreeCache<TpkTypeTreeBlob>>;
+
+/// An [`Environment`] over in-memory `files`, with the embedded TPK as typetree provider.
+pub fn mem_env<P: Into<PathBuf>>(files: impl IntoIterator<Item = (P, Vec<u8>)>) -> MemEnv {
+    tpk_env(MemResolver::from_iter(files))
+}
+
+/// An [`Environment`] over `resolver`, with the embedded TPK as typetree provider.
+pub fn tpk_env<R: EnvResolver>(resolver: R) -> Environment<R, TypeTreeCache<TpkTypeTreeBlob>> {
+    Environment::new(resolver, TypeTreeCache::new(TpkTypeTreeBlob::embedded()))
+}
+
 /// Open scene bytes via a fresh [`Environment`] and hand the resulting handle to `f`. Closure-shaped
 /// so the env outlives the handle.
 pub fn with_handle<R>(
@@ -47,9 +71,7 @@ pub fn with_handle<R>(
     bytes: Vec<u8>,
     f: impl FnOnce(&SerializedFileHandle<'_, MemResolver, TypeTreeCache<TpkTypeTreeBlob>>) -> R,
 ) -> R {
-    let resolver = MemResolver::single(path, bytes);
-    let tpk = TypeTreeCache::new(TpkTypeTreeBlob::embedded());
-    let env = Environment::new(resolver, tpk);
+    let env = mem_env([(path, bytes)]);
     let handle = env.load_serialized(path).unwrap();
     f(&handle)
 }
@@ -242,14 +264,60 @@ pub fn file_referencing_external(external_path: &str) -> (Vec<u8>, FileId) {
 /// Wrap raw serialized-file bytes into a minimal uncompressed UnityFS bundle holding a single
 /// serialized entry named `entry_name`.
 pub fn bundle_with_serialized(entry_name: &str, serialized: &[u8]) -> Vec<u8> {
+    bundle_with_files(&[(entry_name, serialized)])
+}
+
+/// A minimal uncompressed UnityFS bundle holding `files` in order, e.g. a serialized file and its `.resS`.
+pub fn bundle_with_files(files: &[(&str, &[u8])]) -> Vec<u8> {
     use rabex_env::rabex::files::bundlefile::CompressionType;
     use rabex_env::rabex::files::bundlefile::builder::BundleFileBuilder;
 
     let unity_version: UnityVersion = TEST_UNITY_VERSION.parse().unwrap();
     let mut builder = BundleFileBuilder::unityfs(7, &unity_version);
-    builder.add_file(entry_name, serialized).unwrap();
+    for (name, data) in files {
+        builder.add_file(name, data).unwrap();
+    }
 
     let mut out = Cursor::new(Vec::new());
     builder.write(&mut out, CompressionType::None).unwrap();
     out.into_inner()
 }
+
+/// A bundle's main serialized file holding just an [`AssetBundle`] named `name`.
+pub fn asset_bundle_file(name: &str) -> Vec<u8> {
+    build_file(|sfb| {
+        sfb.add_object(&AssetBundle::asset_base(name)).unwrap();
+    })
+}
+
+/// Addressables `settings.json` for `build_target`, loading a single JSON catalog from
+/// `StreamingAssets/aa/<catalog>`.
+pub fn addressables_settings(build_target: &str, catalog: &str) -> Vec<u8> {
+    format!(
+        r#"{{
+    "m_buildTarget": "{build_target}",
+    "m_SettingsHash": "",
+    "m_CatalogLocations": [
+        {{
+            "m_Keys": ["AddressablesMainContentCatalog"],
+            "m_InternalId": "{{UnityEngine.AddressableAssets.Addressables.RuntimePath}}/{catalog}",
+            "m_Provider": "UnityEngine.AddressableAssets.ResourceProviders.ContentCatalogProvider",
+            "m_Dependencies": [],
+            "m_ResourceType": {{
+                "m_AssemblyName": "Unity.Addressables",
+                "m_ClassName": "UnityEngine.AddressableAssets.ResourceLocators.ContentCatalogData"
+            }}
+        }}
+    ],
+    "m_LogResourceManagerExceptions": true,
+    "m_ExtraInitializationData": [],
+    "m_DisableCatalogUpdateOnStart": false,
+    "m_IsLocalCatalogInBundle": false,
+    "m_CertificateHandlerType": {{ "m_AssemblyName": "", "m_ClassName": "" }},
+    "m_AddressablesVersion": "1.21.19",
+    "m_maxConcurrentWebRequests": 3,
+    "m_CatalogRequestsTimeout": 0
+}}"#
+    )
+    .into_bytes()
+}
@@ -52,7 +52,7 @@ impl SceneIndex {
                 }
             }
 
-            for catalog in aa.catalogs(&env.game_files)? {
+            for catalog in env.addressables_catalogs()? {
                 for loc in catalog.locations() {
                     if loc.provider_id.as_str() != resource_providers::BUNDLED_ASSET
                         || loc.type_.m_ClassName.as_str() != SCENE_INSTANCE_CLASS
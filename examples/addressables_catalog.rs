@@ -15,7 +15,7 @@ fn main() -> Result<()> {
     let mut bundle_names = BTreeMap::default();
     let mut bundled_asset_counts = BTreeMap::default();
 
-    for catalog in addressables.catalogs(&env.game_files)? {
+    for catalog in addressables.catalogs(&env)? {
         for loc in catalog.locations() {
             match loc.provider_id.as_str() {
                 resource_providers::ASSET_BUNDLE => {
@@ -1,22 +1,16 @@
 mod utils;
 
-use std::io::Cursor;
 use std::path::Path;
 use std::time::Instant;
 
 use anyhow::{Context as _, Result};
-use rabex::files::SerializedFile;
-use rabex::objects::pptr::PathId;
-use rabex_env::Environment;
 use rabex_env::addressables::{AddressablesData, ArchivePath};
-use rayon::iter::ParallelBridge as _;
-use rustc_hash::FxHashMap;
+use rabex_env::reference_index::ReferenceIndex;
 
 /// Find every object that references a given target object, whether the reference is local
 /// (same file) or external (from another bundle).
 ///
-/// First builds an externals index (which file lists which others in its `m_Externals`), so that
-/// only the files that can possibly reference the target have to be loaded and scanned.
+/// The [`ReferenceIndex`] is cached in `references.bin`, so only the first run has to scan the game.
 ///
 /// Usage: `cargo run --example find_references [<bundle>] [<path_id>]`
 fn main() -> Result<()> {
@@ -39,108 +33,40 @@ fn main() -> Result<()> {
         .to_string();
 
     let start = Instant::now();
-    let index = build_externals_index(&env)?;
+    let index_path = Path::new("references.bin");
+    let index = match std::fs::File::open(index_path) {
+        Ok(file) => ReferenceIndex::read_from(file)?,
+        Err(_) => {
+            let index = ReferenceIndex::build(&env)?;
+            index.write_to(std::fs::File::create(index_path)?)?;
+            index
+        }
+    };
     println!(
-        "built externals index ({} referenced files) in {:?}",
-        index.len(),
+        "loaded reference index {:?} in {:?}",
+        index,
         start.elapsed()
     );
 
-    let candidates = candidate_files(&index, &target_archive_path);
-    println!("scanning {} candidate file(s)", candidates.len());
-    let start = Instant::now();
-    let referrers = find_referrers(&env, candidates, &target_archive_path, target_path_id)?;
-    println!("scanned candidates in {:?}", start.elapsed());
-
+    let referrers: Vec<_> = index
+        .referrers(&target_archive_path, target_path_id)
+        .collect();
     println!(
         "\n{} reference(s) to {target_bundle}#{target_path_id}:",
         referrers.len()
     );
-    for (archive_path, path_id) in &referrers {
-        println!("- {} #{path_id}", bundle_of(addressables, archive_path)?);
+    for referrer in &referrers {
+        println!(
+            "- {} #{}",
+            bundle_of(addressables, referrer.file)?,
+            referrer.path_id
+        );
     }
 
     std::mem::forget(env);
     Ok(())
 }
 
-/// Build `external archive path -> [files that list it in their m_Externals]`.
-///
-/// Only each serialized file's header is parsed; no objects are deserialized and nothing is kept
-/// in the env cache, so this stays cheap and low-memory (and could be persisted for reuse).
-fn build_externals_index(env: &Environment) -> Result<FxHashMap<String, Vec<String>>> {
-    let pairs = rabex_env::utils::par_fold_reduce(
-        env.addressables_bundles()?.into_iter().par_bridge(),
-        |acc: &mut Vec<(String, String)>, bundle_path| {
-            let bundle = env.load_addressables_bundle(&bundle_path)?;
-            let bundle_id = bundle
-                .serialized_files()
-                .find_map(|f| {
-                    Path::new(&f.path)
-                        .extension()
-                        .is_none()
-                        .then(|| f.path.clone())
-                })
-                .context("bundle has no main serialized file")?;
-            for entry in bundle.serialized_files() {
-                let archive_path = ArchivePath::new(&bundle_id, &entry.path).to_string();
-                let data = bundle.read_at_entry(entry)?;
-                let file = SerializedFile::from_reader(&mut Cursor::new(data.as_slice()))?;
-                for external in file.externals_paths() {
-                    acc.push((external.to_owned(), archive_path.clone()));
-                }
-            }
-            Ok(())
-        },
-    )?;
-
-    let mut index: FxHashMap<String, Vec<String>> = FxHashMap::default();
-    for (external, referrer) in pairs {
-        index.entry(external).or_default().push(referrer);
-    }
-    Ok(index)
-}
-
-/// Files that can possibly reference the target: those listing it in their externals (for external
-/// references), plus the target file itself (for local references).
-fn candidate_files(
-    index: &FxHashMap<String, Vec<String>>,
-    target_archive_path: &str,
-) -> Vec<String> {
-    // Each serialized file appears at most once per external (m_Externals has no duplicates), and a
-    // file never lists itself, so these candidates are already unique.
-    let mut candidates = index.get(target_archive_path).cloned().unwrap_or_default();
-    candidates.push(target_archive_path.to_owned());
-    candidates
-}
-
-/// Load and scan only the candidate files, collecting every object whose PPtrs reach the target.
-fn find_referrers(
-    env: &Environment,
-    candidates: Vec<String>,
-    target_archive_path: &str,
-    target_path_id: PathId,
-) -> Result<Vec<(String, PathId)>> {
-    rabex_env::utils::par_fold_reduce(
-        candidates.into_iter().par_bridge(),
-        |acc: &mut Vec<(String, PathId)>, candidate| {
-            let file = env.load_serialized(&candidate)?;
-            for object in file.objects::<()>() {
-                for pptr in object.reachable_one()? {
-                    let referenced_file = match pptr.is_local() {
-                        true => candidate.as_str(),
-                        false => pptr.file_identifier(file.file).unwrap().pathName.as_str(),
-                    };
-                    if referenced_file == target_archive_path && pptr.m_PathID == target_path_id {
-                        acc.push((candidate.clone(), object.path_id()));
-                    }
-                }
-            }
-            Ok(())
-        },
-    )
-}
-
 /// Resolve an archive path back to its human-readable bundle name.
 fn bundle_of(addressables: &AddressablesData, archive_path: &str) -> Result<String> {
     Ok(match ArchivePath::try_parse(Path::new(archive_path))? {
@@ -4,14 +4,8 @@ use std::cmp::Reverse;
 use std::collections::BTreeMap;
 
 use anyhow::Result;
-use rabex_env::resolver::EnvResolver as _;
-use rabex_env::unity::types::MonoBehaviour;
-use rabex_env::utils::par_fold_reduce;
-
-enum UnityFile<T> {
-    Bundle(T),
-    SerializedFile(T),
-}
+use rabex::objects::ClassId;
+use rabex_env::scan::ObjectFilter;
 
 fn main() -> Result<()> {
     let game_filter = "";
@@ -24,31 +18,15 @@ fn main() -> Result<()> {
 
         println!("-- {name} --");
 
-        let mut files = env
-            .game_files
-            .serialized_files()?
-            .into_iter()
-            .map(UnityFile::SerializedFile)
-            .collect::<Vec<_>>();
-        files.extend(
-            env.addressables_bundles()?
-                .into_iter()
-                .map(UnityFile::Bundle),
-        );
-
-        let scripts = par_fold_reduce::<BTreeMap<String, usize>, _>(files, |scripts, path| {
-            let file = match path {
-                UnityFile::Bundle(bundle) => env.load_addressables_bundle_content(bundle)?,
-                UnityFile::SerializedFile(path) => env.load_serialized(&path)?,
-            };
-            for mb in file.objects_of::<MonoBehaviour>() {
+        let filter = ObjectFilter::class(ClassId::MonoBehaviour);
+        let scripts =
+            env.fold_objects::<BTreeMap<String, usize>>(&filter, true, |scripts, _path, mb| {
                 let Some(script) = mb.mono_script()? else {
-                    continue;
+                    return Ok(());
                 };
                 *scripts.entry(script.full_name().into_owned()).or_default() += 1;
-            }
-            Ok(())
-        })?;
+                Ok(())
+            })?;
 
         let mut scripts = scripts.into_iter().collect::<Vec<_>>();
         scripts.sort_by_key(|(_, count)| Reverse(*count));
@@ -6,57 +6,48 @@ use anyhow::{Context, Result, bail};
 use byteorder::LE;
 use rabex::objects::ClassId;
 use rabex::serde_typetree;
+use rabex_env::scan::ObjectFilter;
 
 fn main() -> Result<()> {
     let env = utils::find_game("silksong")?.unwrap();
 
     let start = Instant::now();
-    let result = rabex_env::utils::par_fold_reduce(
-        env.addressables_bundles()?,
-        |acc: &mut (usize, usize), bundle| {
-            let file = env.load_addressables_bundle_content(&bundle)?;
-            for item in file.objects::<serde_value::Value>() {
-                acc.0 += 1;
+    let result = env.fold_objects(
+        &ObjectFilter::all(),
+        true,
+        |acc: &mut (usize, usize), path, item| {
+            acc.0 += 1;
+
+            if item.class_id() == ClassId::AssetBundle {
+                return Ok(());
+            }
 
-                if item.class_id() == ClassId::AssetBundle {
-                    return Ok(());
+            let (class_id, path_id) = (item.class_id(), item.path_id());
+            let item = item.cast_owned::<serde_value::Value>();
+            (|| -> Result<()> {
+                let value = item.read().context("Failed to deserialize")?;
+
+                let tt = item.object.typetree()?;
+                let data = item.object_reader().into_inner();
+                let written = serde_typetree::to_vec::<_, LE>(&value, tt)
+                    .inspect_err(|_| {
+                        println!("Deserialized: {:#?}\nTypetree: {}", (), tt.dump_pretty())
+                    })
+                    .context("Failed to serialize")?;
+
+                if data != written {
+                    acc.1 += 1;
+                    bail!(
+                        "Roundtrip failed. Deserialized: {:#?}\nTypetree: {}",
+                        (),
+                        tt.dump_pretty()
+                    );
                 }
 
-                (|| -> Result<()> {
-                    let value = item.read().context("Failed to deserialize")?;
-
-                    let tt = item.object.typetree()?;
-                    let data = item.object_reader().into_inner();
-                    let written = serde_typetree::to_vec::<_, LE>(&value, tt)
-                        .inspect_err(|_| {
-                            println!("Deserialized: {:#?}\nTypetree: {}", (), tt.dump_pretty())
-                        })
-                        .context("Failed to serialize")?;
-
-                    if data != written {
-                        acc.1 += 1;
-                        bail!(
-                            "Roundtrip failed. Deserialized: {:#?}\nTypetree: {}",
-                            (),
-                            tt.dump_pretty()
-                        );
-                    }
-
-                    Ok(())
-                })()
-                .with_context(|| format!("At {:?} {}", item.class_id(), item.path_id()))
-                .with_context(|| {
-                    format!(
-                        "At bundle {}",
-                        bundle
-                            // .strip_prefix(env.game_files.game_dir.join(aa.build_folder()))
-                            // .unwrap()
-                            .to_string_lossy()
-                            .into_owned()
-                    )
-                })?;
-            }
-            Ok(())
+                Ok(())
+            })()
+            .with_context(|| format!("At {:?} {}", class_id, path_id))
+            .with_context(|| format!("At bundle {}", path.display()))
         },
     )?;
     dbg!(result);
@@ -153,7 +153,7 @@ impl<'a> Context<'a> {
                 nodes.push(tt);
             }
         }
-        let merged = MergedTypeTree::merge(nodes.iter().map(|tt| tt.as_ref()))?
+        let merged = MergedTypeTree::merge_lenient(nodes.iter().map(|tt| tt.as_ref()))
             .context("typetree not found")?;
         self.generate("Assembly-CSharp", merged)
     }
@@ -172,7 +172,7 @@ impl<'a> Context<'a> {
                 nodes.push(tt);
             }
         }
-        Ok(MergedTypeTree::merge(nodes)?)
+        Ok(MergedTypeTree::merge_lenient(nodes))
     }
 
     fn queue(&mut self, assembly: &str, tt: MergedTypeTree) {
@@ -243,7 +243,8 @@ impl<'a> Context<'a> {
 
         match found_assembly {
             Some(assembly) => {
-                let merged = MergedTypeTree::merge(variants)?.expect("variants is non-empty");
+                let merged =
+                    MergedTypeTree::merge_lenient(variants).expect("variants is non-empty");
                 Ok(Some((assembly, merged)))
             }
             None => Ok(None),
@@ -271,8 +272,21 @@ impl<'a> Context<'a> {
             if self.ignore_field(field) {
                 continue;
             }
-            let field_ty = self.field_type(field)?;
+            let field_ty = match field.changes.types.is_empty() {
+                true => self.field_type(field)?,
+                false => self.changed_field_type(tt, field)?,
+            };
             let (field_ty, comment) = split_trailing_comment(&field_ty);
+            // renamed fields deserialize from any of their names
+            let mut aliases: Vec<&str> = Vec::new();
+            for (_, name) in &field.changes.names {
+                if *name != field.m_Name && !aliases.contains(&name.as_str()) {
+                    aliases.push(name);
+                }
+            }
+            for alias in aliases {
+                writeln!(&mut f, "    #[serde(alias = \"{alias}\")]")?;
+            }
             // a field missing from some game that has the parent struct becomes optional, so a
             // single struct deserializes every version; annotate which games actually have it
             let field_ty = if field.present_in.len() == tt.present_in.len() {
@@ -351,27 +365,54 @@ impl<'a> Context<'a> {
         Ok(field_ty)
     }
 
+    /// The type of a field whose type differs between the games: an untagged enum
+    /// if all of its types are primitives, otherwise the type in the first game.
+    fn changed_field_type(
+        &mut self,
+        parent: &MergedTypeTree,
+        field: &MergedTypeTree,
+    ) -> Result<String> {
+        let mut types: Vec<&str> = Vec::new();
+        for (_, ty) in &field.changes.types {
+            if !types.contains(&ty.as_str()) {
+                types.push(ty);
+            }
+        }
+        let primitives: Option<Vec<&str>> = types.iter().map(|ty| primitive(ty)).collect();
+        let Some(primitives) = primitives else {
+            let field_ty = self.field_type(field)?;
+            return Ok(format!("{field_ty} /* differs: {} */", types.join(" vs ")));
+        };
+
+        let name = format!(
+            "{}{}",
+            self.escape_typename(parent),
+            upper_camel_case(&field.m_Name)
+        );
+        let mut f = String::new();
+        if let Some(derives) = &self.settings.derives {
+            writeln!(&mut f, "#[derive({})]", derives)?;
+        }
+        writeln!(&mut f, "#[serde(untagged)]")?;
+        writeln!(&mut f, "pub enum {name} {{")?;
+        for (ty, rust_ty) in types.iter().zip(primitives) {
+            writeln!(&mut f, "    {}({rust_ty}),", upper_camel_case(ty))?;
+        }
+        writeln!(&mut f, "}}")?;
+        self.generated_code.push(f);
+        Ok(name)
+    }
+
     fn classify<'tt>(&self, tt: &'tt MergedTypeTree) -> Classify<'tt> {
         if let Some(rest) = tt.m_Type.strip_prefix("PPtr<")
             && let Some(pptr) = rest.strip_suffix('>')
         {
             return Classify::PPtr(pptr.to_owned());
         }
+        if let Some(primitive) = primitive(&tt.m_Type) {
+            return Classify::Primitive(primitive);
+        }
         match tt.m_Type.as_str() {
-            "UInt8" => Classify::Primitive("u8"),
-            "UInt16" | "unsigned short" => Classify::Primitive("u16"),
-            "UInt32" | "unsigned int" | "Type*" => Classify::Primitive("u32"),
-            "UInt64" | "unsigned long long" | "FileSize" => Classify::Primitive("u64"),
-            "SInt8" => Classify::Primitive("i8"),
-            "SInt16" | "short" => Classify::Primitive("i16"),
-            "SInt32" | "int" => Classify::Primitive("i32"),
-            "SInt64" | "long long" => Classify::Primitive("i64"),
-            "float" => Classify::Primitive("f32"),
-            "double" => Classify::Primitive("f64"),
-            "char" => Classify::Primitive("char"),
-            "string" => Classify::Primitive("String"),
-            "bool" => Classify::Primitive("bool"),
-            "TypelessData" => Classify::Primitive("Vec<u8>"),
             "map" => {
                 let pair = &tt.children[0].children[1];
                 let key = &pair.children[0];
@@ -399,6 +440,38 @@ impl<'a> Context<'a> {
     }
 }
 
+fn primitive(ty: &str) -> Option<&'static str> {
+    Some(match ty {
+        "UInt8" => "u8",
+        "UInt16" | "unsigned short" => "u16",
+        "UInt32" | "unsigned int" | "Type*" => "u32",
+        "UInt64" | "unsigned long long" | "FileSize" => "u64",
+        "SInt8" => "i8",
+        "SInt16" | "short" => "i16",
+        "SInt32" | "int" => "i32",
+        "SInt64" | "long long" => "i64",
+        "float" => "f32",
+        "double" => "f64",
+        "char" => "char",
+        "string" => "String",
+        "bool" => "bool",
+        "TypelessData" => "Vec<u8>",
+        _ => return None,
+    })
+}
+
+/// `unsigned int` -> `UnsignedInt`, `moveSpeed` -> `MoveSpeed`
+fn upper_camel_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
 /// Split a generated type string into its type and an optional trailing `/* ... */` comment,
 /// so the field comma can be placed before the comment instead of after it.
 fn split_trailing_comment(field_ty: &str) -> (&str, String) {
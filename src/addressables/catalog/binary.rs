@@ -5,6 +5,8 @@ use std::sync::Arc;
 
 use rustc_hash::FxHashMap;
 
+mod writer;
+
 #[allow(non_snake_case)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct AssemblyClass {
@@ -45,7 +47,7 @@ impl AssemblyClass {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ObjectInitializationData {
     pub id: Arc<String>,
     pub object_type: AssemblyClass,
@@ -247,7 +249,7 @@ impl ResourceLocation {
         })
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AddressablesCatalog {
     pub locator_id: Arc<String>,
     pub build_result_hash: Arc<String>,
@@ -257,39 +259,73 @@ pub struct AddressablesCatalog {
     pub resources: HashMap<Arc<String>, Vec<Arc<ResourceLocation>>>,
 }
 
+/// Revision of the `catalog.bin` layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BinaryCatalogVersion {
+    /// Addressables 1.x. The header has no build result hash.
+    V1 = 1,
+    /// Addressables 2.x
+    V2 = 2,
+}
+impl BinaryCatalogVersion {
+    pub const LATEST: BinaryCatalogVersion = BinaryCatalogVersion::V2;
+
+    pub fn from_i32(version: i32) -> Option<Self> {
+        match version {
+            1 => Some(BinaryCatalogVersion::V1),
+            2 => Some(BinaryCatalogVersion::V2),
+            _ => None,
+        }
+    }
+
+    fn has_build_result_hash(self) -> bool {
+        self >= BinaryCatalogVersion::V2
+    }
+}
+
+const BINARY_CATALOG_MAGIC: i32 = 0xde38942;
+/// Set on string offsets whose string is stored as UTF-16
+const UNICODE_STRING_FLAG: u32 = 0x80000000;
+
 struct BinaryCatalogHeader {
+    version: BinaryCatalogVersion,
     keys_offset: u32,
     id_offset: u32,
     instance_provider_offset: u32,
     scene_provider_offset: u32,
     init_objects_array_offset: u32,
+    /// `u32::MAX` (the empty string) before [`BinaryCatalogVersion::V2`]
     build_result_hash_offset: u32,
 }
 impl BinaryCatalogHeader {
     fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self, std::io::Error> {
         let magic = read_i32(reader)?;
-        if magic != 0xde38942 {
+        if magic != BINARY_CATALOG_MAGIC {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
                 format!("Unexpected magic for addressables catalog: {}", magic),
             ));
         }
         let version = read_i32(reader)?;
-        if version != 2 {
-            return Err(std::io::Error::new(
+        let version = BinaryCatalogVersion::from_i32(version).ok_or_else(|| {
+            std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
-                format!("Unsuppported version {}", version),
-            ));
-        }
+                format!("Unsupported addressables catalog version {}", version),
+            )
+        })?;
 
         let keys_offset = read_u32(reader)?;
         let id_offset = read_u32(reader)?;
         let instance_provider_offset = read_u32(reader)?;
         let scene_provider_offset = read_u32(reader)?;
         let init_objects_array_offset = read_u32(reader)?;
-        let build_result_hash_offset = read_u32(reader)?; // v2 only
+        let build_result_hash_offset = match version.has_build_result_hash() {
+            true => read_u32(reader)?,
+            false => u32::MAX,
+        };
 
         Ok(BinaryCatalogHeader {
+            version,
             keys_offset,
             id_offset,
             instance_provider_offset,
@@ -316,6 +352,18 @@ impl AddressablesCatalog {
         BinaryCatalogReader::new(reader)?.read()
     }
 
+    /// Serializes the catalog as the latest `catalog.bin` revision.
+    pub fn write_binary(&self) -> Vec<u8> {
+        self.write_binary_version(BinaryCatalogVersion::LATEST)
+    }
+
+    /// Serializes the catalog as `catalog.bin`.
+    ///
+    /// [`BinaryCatalogVersion::V1`] has no room for the build result hash, so it is dropped.
+    pub fn write_binary_version(&self, version: BinaryCatalogVersion) -> Vec<u8> {
+        writer::write(self, version)
+    }
+
     pub fn locations(&self) -> impl Iterator<Item = &ResourceLocation> {
         self.resources
             .values()
@@ -344,6 +392,11 @@ impl<R: Read + Seek> BinaryCatalogReader<R> {
             cache,
         })
     }
+    /// The layout revision of the catalog
+    pub fn version(&self) -> BinaryCatalogVersion {
+        self.header.version
+    }
+
     fn read_encoded_string(&mut self, encoded_offset: u32) -> Result<Arc<String>, std::io::Error> {
         read_encoded_string(&mut self.reader, &mut self.cache, encoded_offset)
     }
@@ -552,7 +605,7 @@ fn read_encoded_string_sep<R: Read + Seek>(
         return Ok(Arc::new(String::new()));
     }
 
-    let unicode = (encoded_offset & 0x80000000) != 0;
+    let unicode = (encoded_offset & UNICODE_STRING_FLAG) != 0;
     let dynamic_string = (encoded_offset & 0x40000000) != 0 && dynamic_string_separator != '\0';
     let offset = encoded_offset & 0x3fffffff;
 
@@ -571,15 +624,25 @@ fn read_encoded_string_sep<R: Read + Seek>(
 fn read_basic_string<R: Read + Seek>(
     reader: &mut R,
     offset: u32,
-    _unicode: bool,
+    unicode: bool,
 ) -> Result<String, std::io::Error> {
     reader.seek(SeekFrom::Start(offset as u64 - 4))?;
     let length = read_i32(reader)?;
     let mut buf = vec![0; length as usize];
     reader.read_exact(&mut buf)?;
 
-    let str = String::from_utf8(buf)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let str = match unicode {
+        true => {
+            let utf16: Vec<u16> = buf
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16(&utf16)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
+        }
+        false => String::from_utf8(buf)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+    };
     Ok(str)
 }
 
@@ -640,6 +703,10 @@ impl Hash128 {
     pub fn from_u32s([a, b, c, d]: [u32; 4]) -> Self {
         Hash128(a, b, c, d)
     }
+
+    pub fn to_u32s(&self) -> [u32; 4] {
+        [self.0, self.1, self.2, self.3]
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -693,6 +760,17 @@ pub struct CommonInfo {
     pub flags: i32,
 }
 impl CommonInfo {
+    /// Load all assets of the bundle instead of only the requested one (`AssetLoadMode`)
+    pub const ASSET_LOAD_MODE_ALL: i32 = 1;
+    pub const CHUNKED_TRANSFER: i32 = 2;
+    pub const USE_CRC_FOR_CACHED_BUNDLES: i32 = 4;
+    pub const USE_UWR_FOR_LOCAL_BUNDLES: i32 = 8;
+    pub const CLEAR_OTHER_CACHED_VERSIONS_WHEN_LOADED: i32 = 16;
+
+    pub fn has_flag(&self, flag: i32) -> bool {
+        self.flags & flag != 0
+    }
+
     fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self, std::io::Error> {
         let timeout = read_i16(reader)?;
         let redirect_limit = read_u8(reader)?;
@@ -829,3 +907,142 @@ fn decode_v2<R: Read + Seek>(
         )),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Minimal `catalog.bin` writer. Strings and arrays are length-prefixed,
+    /// with offsets pointing after the prefix.
+    #[derive(Default)]
+    struct Builder {
+        data: Vec<u8>,
+    }
+    impl Builder {
+        fn here(&self) -> u32 {
+            self.data.len() as u32
+        }
+        fn u32(&mut self, value: u32) {
+            self.data.extend(value.to_le_bytes());
+        }
+        fn record(&mut self, fields: &[u32]) -> u32 {
+            let offset = self.here();
+            fields.iter().for_each(|&field| self.u32(field));
+            offset
+        }
+        fn string(&mut self, value: &str) -> u32 {
+            self.u32(value.len() as u32);
+            let offset = self.here();
+            self.data.extend(value.as_bytes());
+            offset
+        }
+        fn array(&mut self, items: &[u32]) -> u32 {
+            self.u32(items.len() as u32 * 4);
+            self.record(items)
+        }
+        fn assembly_class(&mut self, assembly: &str, class: &str) -> u32 {
+            let assembly = self.string(assembly);
+            let class = self.string(class);
+            self.record(&[assembly, class])
+        }
+        fn init_data(&mut self, id: &str, class: &str) -> u32 {
+            let id = self.string(id);
+            let object_type = self.assembly_class("Unity.ResourceManager, Version=0.0.0.0", class);
+            let data = self.string("");
+            self.record(&[id, object_type, data])
+        }
+        fn string_value(&mut self, value: &str) -> u32 {
+            let string_type = self.assembly_class("mscorlib, Version=4.0.0.0", "System.String");
+            let string = self.string(value);
+            let object = self.record(&[string]);
+            self.data.push(b'\0');
+            self.record(&[string_type, object])
+        }
+    }
+
+    /// A catalog with a single key pointing at a `BundledAssetProvider` location.
+    fn catalog(version: i32) -> Vec<u8> {
+        let header_fields = match version {
+            1 => 5,
+            _ => 6,
+        };
+        let mut b = Builder::default();
+        b.data.resize(8 + header_fields * 4, 0);
+
+        let key = b.string_value("Assets/A.prefab");
+        let primary_key = b.string("Assets/A.prefab");
+        let internal_id = b.string("Assets/A.prefab");
+        let provider_id = b.string(resource_providers::BUNDLED_ASSET);
+        let resource_type = b.assembly_class(
+            "UnityEngine.CoreModule, Version=0.0.0.0",
+            "UnityEngine.GameObject",
+        );
+        let location = b.record(&[
+            primary_key,
+            internal_id,
+            provider_id,
+            u32::MAX,
+            0,
+            u32::MAX,
+            resource_type,
+        ]);
+        let locations = b.array(&[location]);
+        let keys = b.array(&[key, locations]);
+
+        let id = b.string("AddressablesMainContentCatalog");
+        let instance_provider = b.init_data("InstanceProvider", "InstanceProvider");
+        let scene_provider = b.init_data("SceneProvider", "SceneProvider");
+        let init_objects = b.array(&[]);
+        let build_result_hash = b.string("0123abcd");
+
+        let mut header = Builder::default();
+        header.record(&[BINARY_CATALOG_MAGIC as u32, version as u32]);
+        header.record(&[keys, id, instance_provider, scene_provider, init_objects]);
+        if header_fields == 6 {
+            header.u32(build_result_hash);
+        }
+        b.data[..header.data.len()].copy_from_slice(&header.data);
+        b.data
+    }
+
+    fn check_location(catalog: &AddressablesCatalog) {
+        assert_eq!(*catalog.locator_id, "AddressablesMainContentCatalog");
+        assert_eq!(
+            *catalog.instance_provider_data.object_type.m_ClassName,
+            "InstanceProvider"
+        );
+        let locations = &catalog.resources[&"Assets/A.prefab".to_owned()];
+        assert_eq!(locations.len(), 1);
+        assert_eq!(*locations[0].internal_id, "Assets/A.prefab");
+        assert_eq!(locations[0].provider_name(), "BundledAssetProvider");
+        assert_eq!(locations[0].type_.class_name(), "GameObject");
+    }
+
+    #[test]
+    fn version_1() {
+        let data = catalog(1);
+        let mut reader = BinaryCatalogReader::new(Cursor::new(&data)).unwrap();
+        assert_eq!(reader.version(), BinaryCatalogVersion::V1);
+        let catalog = reader.read().unwrap();
+        check_location(&catalog);
+        assert_eq!(*catalog.build_result_hash, "");
+    }
+
+    #[test]
+    fn version_2() {
+        let data = catalog(2);
+        let mut reader = BinaryCatalogReader::new(Cursor::new(&data)).unwrap();
+        assert_eq!(reader.version(), BinaryCatalogVersion::V2);
+        let catalog = reader.read().unwrap();
+        check_location(&catalog);
+        assert_eq!(*catalog.build_result_hash, "0123abcd");
+    }
+
+    #[test]
+    fn unknown_version() {
+        let err = AddressablesCatalog::from_reader(Cursor::new(catalog(3))).unwrap_err();
+        assert!(err.to_string().contains("version 3"), "{err}");
+    }
+}
@@ -0,0 +1,221 @@
+//! Writer for `catalog.bin`, the inverse of [`BinaryCatalogReader`](super::BinaryCatalogReader).
+//!
+//! Strings are written once, as ASCII or UTF-16, and referenced by offset. Locations shared between keys
+//! (like the bundles other locations depend on) are written once as well.
+use std::sync::Arc;
+
+use rustc_hash::FxHashMap;
+
+use super::{
+    AddressablesCatalog, AssemblyClass, AssetBundleRequestOptions, BINARY_CATALOG_MAGIC,
+    BinaryCatalogVersion, ObjectInitializationData, ResourceLocation, UNICODE_STRING_FLAG,
+};
+
+const STRING_ASSEMBLY: &str =
+    "mscorlib, Version=4.0.0.0, Culture=neutral, PublicKeyToken=b77a5c561934e089";
+const STRING_CLASS: &str = "System.String";
+const ABRO_ASSEMBLY: &str =
+    "Unity.ResourceManager, Version=0.0.0.0, Culture=neutral, PublicKeyToken=null";
+const ABRO_CLASS: &str =
+    "UnityEngine.ResourceManagement.ResourceProviders.AssetBundleRequestOptions";
+
+pub(super) fn write(catalog: &AddressablesCatalog, version: BinaryCatalogVersion) -> Vec<u8> {
+    let header_fields = match version.has_build_result_hash() {
+        true => 8,
+        false => 7,
+    };
+    let mut writer = Writer::default();
+    writer.data.resize(header_fields * 4, 0);
+
+    let mut keys: Vec<_> = catalog.resources.iter().collect();
+    keys.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut key_locations = Vec::with_capacity(keys.len() * 2);
+    for (key, locations) in keys {
+        let key = writer.string_value(key);
+        let locations: Vec<_> = locations
+            .iter()
+            .map(|location| writer.location(location))
+            .collect();
+        key_locations.push(key);
+        key_locations.push(writer.array(&locations));
+    }
+    let keys_offset = writer.array(&key_locations);
+
+    let id_offset = writer.string(&catalog.locator_id);
+    let instance_provider_offset = writer.init_data(&catalog.instance_provider_data);
+    let scene_provider_offset = writer.init_data(&catalog.scene_provider_data);
+    let init_objects: Vec<_> = catalog
+        .resource_provider_data
+        .iter()
+        .map(|data| writer.init_data(data))
+        .collect();
+    let init_objects_array_offset = writer.array(&init_objects);
+    let build_result_hash_offset = writer.string(&catalog.build_result_hash);
+
+    let mut header = Vec::with_capacity(header_fields * 4);
+    header.extend(BINARY_CATALOG_MAGIC.to_le_bytes());
+    header.extend((version as i32).to_le_bytes());
+    for field in [
+        keys_offset,
+        id_offset,
+        instance_provider_offset,
+        scene_provider_offset,
+        init_objects_array_offset,
+    ] {
+        header.extend(field.to_le_bytes());
+    }
+    if version.has_build_result_hash() {
+        header.extend(build_result_hash_offset.to_le_bytes());
+    }
+    writer.data[..header.len()].copy_from_slice(&header);
+
+    writer.data
+}
+
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+    strings: FxHashMap<String, u32>,
+    assembly_classes: FxHashMap<AssemblyClass, u32>,
+    locations: FxHashMap<*const ResourceLocation, u32>,
+}
+
+impl Writer {
+    fn here(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    fn align(&mut self) {
+        self.data.resize(self.data.len().next_multiple_of(4), 0);
+    }
+
+    fn record(&mut self, fields: &[u32]) -> u32 {
+        self.align();
+        let offset = self.here();
+        for field in fields {
+            self.data.extend(field.to_le_bytes());
+        }
+        offset
+    }
+
+    /// Byte length-prefixed, the offset points after the length.
+    /// Non-ASCII strings are written as UTF-16, marked by the unicode flag on the offset.
+    fn string(&mut self, value: &str) -> u32 {
+        if let Some(&offset) = self.strings.get(value) {
+            return offset;
+        }
+        self.align();
+        let offset = match value.is_ascii() {
+            true => {
+                self.data.extend((value.len() as i32).to_le_bytes());
+                let offset = self.here();
+                self.data.extend(value.as_bytes());
+                offset
+            }
+            false => {
+                let utf16: Vec<u16> = value.encode_utf16().collect();
+                self.data.extend((utf16.len() as i32 * 2).to_le_bytes());
+                let offset = self.here() | UNICODE_STRING_FLAG;
+                self.data.extend(utf16.iter().flat_map(|c| c.to_le_bytes()));
+                offset
+            }
+        };
+        self.strings.insert(value.to_owned(), offset);
+        offset
+    }
+
+    /// Byte length-prefixed, the offset points after the length
+    fn array(&mut self, items: &[u32]) -> u32 {
+        self.align();
+        self.data.extend((items.len() as i32 * 4).to_le_bytes());
+        self.record(items)
+    }
+
+    fn assembly_class(&mut self, class: &AssemblyClass) -> u32 {
+        if let Some(&offset) = self.assembly_classes.get(class) {
+            return offset;
+        }
+        let assembly = self.string(&class.m_AssemblyName);
+        let class_name = self.string(&class.m_ClassName);
+        let offset = self.record(&[assembly, class_name]);
+        self.assembly_classes.insert(class.clone(), offset);
+        offset
+    }
+
+    fn assembly_class_str(&mut self, assembly: &str, class: &str) -> u32 {
+        self.assembly_class(&AssemblyClass {
+            m_AssemblyName: Arc::new(assembly.to_owned()),
+            m_ClassName: Arc::new(class.to_owned()),
+        })
+    }
+
+    fn init_data(&mut self, data: &ObjectInitializationData) -> u32 {
+        let id = self.string(&data.id);
+        let object_type = self.assembly_class(&data.object_type);
+        let data = self.string(&data.data);
+        self.record(&[id, object_type, data])
+    }
+
+    /// A `System.String` value, as used for keys
+    fn string_value(&mut self, value: &str) -> u32 {
+        let type_name = self.assembly_class_str(STRING_ASSEMBLY, STRING_CLASS);
+        let string = self.string(value);
+        let object = self.record(&[string]);
+        // no dynamic string separator
+        self.data.push(0);
+        self.record(&[type_name, object])
+    }
+
+    fn abro_value(&mut self, abro: &AssetBundleRequestOptions) -> u32 {
+        let type_name = self.assembly_class_str(ABRO_ASSEMBLY, ABRO_CLASS);
+
+        let hash = self.record(&abro.hash.to_u32s());
+        let bundle_name = self.string(&abro.bundle_name);
+        self.align();
+        let common_info = self.here();
+        let info = &abro.common_info;
+        self.data.extend(info.timeout.to_le_bytes());
+        self.data.push(info.redirect_limit);
+        self.data.push(info.retry_count);
+        self.data.extend(info.flags.to_le_bytes());
+
+        let object = self.record(&[hash, bundle_name, abro.crc, abro.bundle_size, common_info]);
+        self.record(&[type_name, object])
+    }
+
+    fn location(&mut self, location: &Arc<ResourceLocation>) -> u32 {
+        if let Some(&offset) = self.locations.get(&Arc::as_ptr(location)) {
+            return offset;
+        }
+
+        let dependencies: Vec<_> = location
+            .dependencies
+            .iter()
+            .map(|dependency| self.location(dependency))
+            .collect();
+        let dependencies = match dependencies.is_empty() {
+            true => u32::MAX,
+            false => self.array(&dependencies),
+        };
+        let primary_key = self.string(&location.primary_key);
+        let internal_id = self.string(&location.internal_id);
+        let provider_id = self.string(&location.provider_id);
+        let data = match &location.data {
+            Some(abro) => self.abro_value(abro),
+            None => u32::MAX,
+        };
+        let type_ = self.assembly_class(&location.type_);
+
+        let offset = self.record(&[
+            primary_key,
+            internal_id,
+            provider_id,
+            dependencies,
+            location.dependency_hash_code as u32,
+            data,
+            type_,
+        ]);
+        self.locations.insert(Arc::as_ptr(location), offset);
+        offset
+    }
+}
@@ -6,15 +6,19 @@ use std::sync::Arc;
 use anyhow::{Context as _, Result, bail};
 use base64::Engine as _;
 use base64::engine::general_purpose::STANDARD as BASE64;
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 
 use crate::addressables::catalog::{
     AddressablesCatalog, AssemblyClass, AssetBundleRequestOptions, CommonInfo, Hash128,
     ObjectInitializationData, ResourceLocation,
 };
 
+mod writer;
+
+pub(super) use writer::write;
+
 #[allow(non_snake_case)]
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 struct JsonCatalog {
     m_LocatorId: String,
     #[serde(default)]
@@ -34,7 +38,7 @@ struct JsonCatalog {
 }
 
 #[allow(non_snake_case)]
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 struct JsonObjectInitData {
     m_Id: String,
     m_ObjectType: JsonSerializedType,
@@ -42,7 +46,7 @@ struct JsonObjectInitData {
 }
 
 #[allow(non_snake_case)]
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 struct JsonSerializedType {
     m_AssemblyName: String,
     m_ClassName: String,
@@ -265,13 +269,22 @@ struct AbroJson {
     m_BundleName: String,
     #[serde(default)]
     m_BundleSize: u64,
+    #[serde(default)]
+    m_ChunkedTransfer: bool,
+    #[serde(default)]
+    m_AssetLoadMode: i32,
+    #[serde(default)]
+    m_UseCrcForCachedBundles: bool,
+    #[serde(default)]
+    m_UseUWRForLocalBundles: bool,
+    #[serde(default)]
+    m_ClearOtherCachedVersionsWhenLoaded: bool,
 }
 
 /// Read an `AssetBundleRequestOptions` from extra data (a JSON-object blob).
 ///
 /// The binary catalog packs several booleans (`ChunkedTransfer`, `AssetLoadMode`,
-/// …) into `CommonInfo::flags`; the JSON form keeps them as separate fields that
-/// no consumer here reads, so `flags` is left `0`.
+/// …) into `CommonInfo::flags`; the JSON form keeps them as separate fields.
 fn read_abro(extra_data: &[u8], offset: usize) -> Result<Option<AssetBundleRequestOptions>> {
     let CatalogObject::Json { json, .. } = read_object(extra_data, offset)? else {
         // Only AssetBundleProvider locations carry ABRO data; anything else has
@@ -280,6 +293,27 @@ fn read_abro(extra_data: &[u8], offset: usize) -> Result<Option<AssetBundleReque
     };
     let abro: AbroJson =
         serde_json::from_str(&json).context("parsing AssetBundleRequestOptions")?;
+    let mut flags = 0;
+    for (set, flag) in [
+        (abro.m_AssetLoadMode != 0, CommonInfo::ASSET_LOAD_MODE_ALL),
+        (abro.m_ChunkedTransfer, CommonInfo::CHUNKED_TRANSFER),
+        (
+            abro.m_UseCrcForCachedBundles,
+            CommonInfo::USE_CRC_FOR_CACHED_BUNDLES,
+        ),
+        (
+            abro.m_UseUWRForLocalBundles,
+            CommonInfo::USE_UWR_FOR_LOCAL_BUNDLES,
+        ),
+        (
+            abro.m_ClearOtherCachedVersionsWhenLoaded,
+            CommonInfo::CLEAR_OTHER_CACHED_VERSIONS_WHEN_LOADED,
+        ),
+    ] {
+        if set {
+            flags |= flag;
+        }
+    }
     Ok(Some(AssetBundleRequestOptions {
         hash: Hash128::from_u32s(parse_hash128(&abro.m_Hash)),
         crc: abro.m_Crc,
@@ -287,7 +321,7 @@ fn read_abro(extra_data: &[u8], offset: usize) -> Result<Option<AssetBundleReque
             timeout: abro.m_Timeout,
             redirect_limit: abro.m_RedirectLimit as u8,
             retry_count: abro.m_RetryCount as u8,
-            flags: 0,
+            flags,
         },
         bundle_name: Arc::new(abro.m_BundleName),
         bundle_size: abro.m_BundleSize as u32,
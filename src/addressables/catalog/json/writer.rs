@@ -0,0 +1,301 @@
+//! Writer for `catalog.json`, the inverse of [`parse`](super::parse).
+//!
+//! Unlike `catalog.bin`, JSON catalogs reference dependencies by the key whose bucket contains them.
+//! Dependency lists which aren't already the locations of a key get a new key named after their
+//! `dependency_hash_code`, just like primary keys which aren't used as a key get an empty bucket.
+use std::sync::Arc;
+
+use anyhow::Result;
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use rustc_hash::FxHashMap;
+use serde_derive::Serialize;
+
+use super::{JsonCatalog, JsonObjectInitData, JsonSerializedType, object_type};
+use crate::addressables::catalog::{
+    AddressablesCatalog, AssemblyClass, AssetBundleRequestOptions, CommonInfo,
+    ObjectInitializationData, ResourceLocation,
+};
+
+const ABRO_ASSEMBLY: &str =
+    "Unity.ResourceManager, Version=0.0.0.0, Culture=neutral, PublicKeyToken=null";
+const ABRO_CLASS: &str =
+    "UnityEngine.ResourceManagement.ResourceProviders.AssetBundleRequestOptions";
+
+/// `AssetBundleRequestOptions`, JSON-serialized inside `m_ExtraDataString`.
+///
+/// The JSON form spells out the binary catalog's `CommonInfo::flags` as separate fields.
+#[allow(non_snake_case)]
+#[derive(Serialize)]
+struct AbroJson<'a> {
+    m_Hash: String,
+    m_Crc: u32,
+    m_Timeout: i16,
+    m_ChunkedTransfer: bool,
+    m_RedirectLimit: i32,
+    m_RetryCount: i32,
+    m_BundleName: &'a str,
+    m_AssetLoadMode: i32,
+    m_BundleSize: u64,
+    m_UseCrcForCachedBundles: bool,
+    m_UseUWRForLocalBundles: bool,
+    m_ClearOtherCachedVersionsWhenLoaded: bool,
+}
+
+pub(in crate::addressables::catalog) fn write(catalog: &AddressablesCatalog) -> Result<Vec<u8>> {
+    let mut keys: Vec<_> = catalog.resources.iter().collect();
+    keys.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut writer = Writer::default();
+    for (key, locations) in keys {
+        writer.add_bucket(key.as_str(), locations.clone());
+    }
+    for bucket in 0..writer.buckets.len() {
+        for location in writer.buckets[bucket].1.clone() {
+            writer.add_entry(&location);
+        }
+    }
+
+    let mut entries = Vec::with_capacity(writer.entries.len());
+    for location in writer.entries.clone() {
+        entries.push(writer.raw_entry(&location)?);
+    }
+
+    let mut key_data = Vec::new();
+    key_data.extend((writer.buckets.len() as i32).to_le_bytes());
+    let mut bucket_data = Vec::new();
+    bucket_data.extend((writer.buckets.len() as i32).to_le_bytes());
+    for (key, locations) in &writer.buckets {
+        bucket_data.extend((key_data.len() as i32).to_le_bytes());
+        write_string_object(&mut key_data, key);
+
+        bucket_data.extend((locations.len() as i32).to_le_bytes());
+        for location in locations {
+            let index = writer.entry_indices[&Arc::as_ptr(location)];
+            bucket_data.extend((index as i32).to_le_bytes());
+        }
+    }
+
+    let mut entry_data = Vec::with_capacity(4 + entries.len() * 7 * 4);
+    entry_data.extend((entries.len() as i32).to_le_bytes());
+    for entry in entries {
+        for field in entry {
+            entry_data.extend(field.to_le_bytes());
+        }
+    }
+
+    let json = JsonCatalog {
+        m_LocatorId: catalog.locator_id.to_string(),
+        m_BuildResultHash: catalog.build_result_hash.to_string(),
+        m_InstanceProviderData: init_data(&catalog.instance_provider_data),
+        m_SceneProviderData: init_data(&catalog.scene_provider_data),
+        m_ResourceProviderData: catalog
+            .resource_provider_data
+            .iter()
+            .map(init_data)
+            .collect(),
+        m_ProviderIds: writer.provider_ids.items,
+        m_InternalIds: writer.internal_ids.items,
+        m_KeyDataString: BASE64.encode(key_data),
+        m_BucketDataString: BASE64.encode(bucket_data),
+        m_EntryDataString: BASE64.encode(entry_data),
+        m_ExtraDataString: BASE64.encode(writer.extra_data),
+        m_resourceTypes: writer
+            .resource_types
+            .items
+            .into_iter()
+            .map(serialized_type)
+            .collect(),
+        m_InternalIdPrefixes: Vec::new(),
+    };
+    Ok(serde_json::to_vec(&json)?)
+}
+
+/// Deduplicated list of values, referenced by index
+struct Table<T> {
+    items: Vec<T>,
+    indices: FxHashMap<T, usize>,
+}
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Table {
+            items: Vec::new(),
+            indices: FxHashMap::default(),
+        }
+    }
+}
+impl<T: Clone + Eq + std::hash::Hash> Table<T> {
+    fn index(&mut self, value: &T) -> usize {
+        if let Some(&index) = self.indices.get(value) {
+            return index;
+        }
+        let index = self.items.len();
+        self.items.push(value.clone());
+        self.indices.insert(value.clone(), index);
+        index
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    buckets: Vec<(String, Vec<Arc<ResourceLocation>>)>,
+    bucket_by_key: FxHashMap<String, usize>,
+    bucket_by_locations: FxHashMap<Vec<*const ResourceLocation>, usize>,
+
+    entries: Vec<Arc<ResourceLocation>>,
+    entry_indices: FxHashMap<*const ResourceLocation, usize>,
+
+    internal_ids: Table<String>,
+    provider_ids: Table<String>,
+    resource_types: Table<AssemblyClass>,
+    extra_data: Vec<u8>,
+}
+
+impl Writer {
+    fn add_bucket(&mut self, key: &str, locations: Vec<Arc<ResourceLocation>>) -> usize {
+        let index = self.buckets.len();
+        let pointers = locations.iter().map(Arc::as_ptr).collect();
+        self.bucket_by_locations.entry(pointers).or_insert(index);
+        self.bucket_by_key.insert(key.to_owned(), index);
+        self.buckets.push((key.to_owned(), locations));
+        index
+    }
+
+    fn add_entry(&mut self, location: &Arc<ResourceLocation>) {
+        if self.entry_indices.contains_key(&Arc::as_ptr(location)) {
+            return;
+        }
+        self.entry_indices
+            .insert(Arc::as_ptr(location), self.entries.len());
+        self.entries.push(Arc::clone(location));
+        for dependency in &location.dependencies {
+            self.add_entry(dependency);
+        }
+    }
+
+    fn dependency_bucket(&mut self, location: &ResourceLocation) -> usize {
+        let pointers: Vec<_> = location.dependencies.iter().map(Arc::as_ptr).collect();
+        if let Some(&index) = self.bucket_by_locations.get(&pointers) {
+            return index;
+        }
+        let mut key = location.dependency_hash_code.to_string();
+        let mut suffix = 0;
+        while self.bucket_by_key.contains_key(&key) {
+            suffix += 1;
+            key = format!("{}_{suffix}", location.dependency_hash_code);
+        }
+        self.add_bucket(&key, location.dependencies.clone())
+    }
+
+    fn key_bucket(&mut self, key: &str) -> usize {
+        match self.bucket_by_key.get(key) {
+            Some(&index) => index,
+            None => self.add_bucket(key, Vec::new()),
+        }
+    }
+
+    /// `internal_id, provider, dependency_key, dependency_hash, data, primary_key, resource_type`
+    fn raw_entry(&mut self, location: &ResourceLocation) -> Result<[i32; 7]> {
+        let internal_id = self.internal_ids.index(&location.internal_id) as i32;
+        let provider = self.provider_ids.index(&location.provider_id) as i32;
+        let dependency_key = match location.dependencies.is_empty() {
+            true => -1,
+            false => self.dependency_bucket(location) as i32,
+        };
+        let data = match &location.data {
+            Some(abro) => self.write_abro(abro)? as i32,
+            None => -1,
+        };
+        let primary_key = match location.primary_key.is_empty() {
+            true => -1,
+            false => self.key_bucket(&location.primary_key) as i32,
+        };
+        let resource_type = match location.type_.m_AssemblyName.is_empty()
+            && location.type_.m_ClassName.is_empty()
+        {
+            true => -1,
+            false => self.resource_types.index(&location.type_) as i32,
+        };
+        Ok([
+            internal_id,
+            provider,
+            dependency_key,
+            location.dependency_hash_code,
+            data,
+            primary_key,
+            resource_type,
+        ])
+    }
+
+    fn write_abro(&mut self, abro: &AssetBundleRequestOptions) -> Result<usize> {
+        let info = &abro.common_info;
+        let json = serde_json::to_string(&AbroJson {
+            m_Hash: hash_hex(abro.hash.to_u32s()),
+            m_Crc: abro.crc,
+            m_Timeout: info.timeout,
+            m_ChunkedTransfer: info.has_flag(CommonInfo::CHUNKED_TRANSFER),
+            m_RedirectLimit: info.redirect_limit as i32,
+            m_RetryCount: info.retry_count as i32,
+            m_BundleName: &abro.bundle_name,
+            m_AssetLoadMode: info.flags & CommonInfo::ASSET_LOAD_MODE_ALL,
+            m_BundleSize: abro.bundle_size as u64,
+            m_UseCrcForCachedBundles: info.has_flag(CommonInfo::USE_CRC_FOR_CACHED_BUNDLES),
+            m_UseUWRForLocalBundles: info.has_flag(CommonInfo::USE_UWR_FOR_LOCAL_BUNDLES),
+            m_ClearOtherCachedVersionsWhenLoaded: info
+                .has_flag(CommonInfo::CLEAR_OTHER_CACHED_VERSIONS_WHEN_LOADED),
+        })?;
+
+        let offset = self.extra_data.len();
+        let data = &mut self.extra_data;
+        data.push(object_type::JSON_OBJECT);
+        data.push(ABRO_ASSEMBLY.len() as u8);
+        data.extend(ABRO_ASSEMBLY.as_bytes());
+        data.push(ABRO_CLASS.len() as u8);
+        data.extend(ABRO_CLASS.as_bytes());
+        write_utf16(data, &json);
+        Ok(offset)
+    }
+}
+
+fn write_string_object(data: &mut Vec<u8>, value: &str) {
+    if value.is_ascii() {
+        data.push(object_type::ASCII_STRING);
+        data.extend((value.len() as i32).to_le_bytes());
+        data.extend(value.as_bytes());
+    } else {
+        data.push(object_type::UNICODE_STRING);
+        write_utf16(data, value);
+    }
+}
+
+/// Byte length-prefixed UTF-16
+fn write_utf16(data: &mut Vec<u8>, value: &str) {
+    let units: Vec<u16> = value.encode_utf16().collect();
+    data.extend((units.len() as i32 * 2).to_le_bytes());
+    for unit in units {
+        data.extend(unit.to_le_bytes());
+    }
+}
+
+/// Inverse of `parse_hash128`
+fn hash_hex(hash: [u32; 4]) -> String {
+    hash.iter()
+        .flat_map(|value| value.to_le_bytes())
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn serialized_type(class: AssemblyClass) -> JsonSerializedType {
+    JsonSerializedType {
+        m_AssemblyName: class.m_AssemblyName.to_string(),
+        m_ClassName: class.m_ClassName.to_string(),
+    }
+}
+
+fn init_data(data: &ObjectInitializationData) -> JsonObjectInitData {
+    JsonObjectInitData {
+        m_Id: data.id.to_string(),
+        m_ObjectType: serialized_type(data.object_type.clone()),
+        m_Data: data.data.to_string(),
+    }
+}
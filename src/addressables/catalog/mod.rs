@@ -8,7 +8,7 @@ use std::io::Cursor;
 pub use binary::*;
 
 /// Parse an addressables catalog, either as `catalog.json` or `catalog.bin`
-pub(crate) fn parse(data: &[u8]) -> Result<AddressablesCatalog> {
+pub fn parse(data: &[u8]) -> Result<AddressablesCatalog> {
     let is_json = data
         .iter()
         .find(|b| !b.is_ascii_whitespace())
@@ -19,3 +19,207 @@ pub(crate) fn parse(data: &[u8]) -> Result<AddressablesCatalog> {
         Ok(AddressablesCatalog::from_reader(Cursor::new(data))?)
     }
 }
+
+impl AddressablesCatalog {
+    /// Serializes the catalog as `catalog.json`.
+    ///
+    /// JSON catalogs reference dependencies through keys, so dependency lists which aren't
+    /// the locations of any key get a new key named after their `dependency_hash_code`.
+    pub fn write_json(&self) -> Result<Vec<u8>> {
+        json::write(self)
+    }
+
+    /// Merges the keys of `other` into this catalog.
+    ///
+    /// Unity queries the resource locators of all loaded catalogs in order, so locations from `other`
+    /// are appended after the existing locations of the same key. Equal locations are only kept once.
+    /// The locator id and provider data of `self` are kept, except for resource providers
+    /// which only `other` uses.
+    pub fn merge(&mut self, other: AddressablesCatalog) {
+        for (key, locations) in other.resources {
+            let existing = self.resources.entry(key).or_default();
+            for location in locations {
+                if !existing.iter().any(|known| **known == *location) {
+                    existing.push(location);
+                }
+            }
+        }
+        for provider in other.resource_provider_data {
+            if !self
+                .resource_provider_data
+                .iter()
+                .any(|known| known.id == provider.id)
+            {
+                self.resource_provider_data.push(provider);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn class(assembly: &str, class: &str) -> AssemblyClass {
+        AssemblyClass {
+            m_AssemblyName: Arc::new(assembly.to_owned()),
+            m_ClassName: Arc::new(class.to_owned()),
+        }
+    }
+
+    fn init_data(id: &str) -> ObjectInitializationData {
+        ObjectInitializationData {
+            id: Arc::new(id.to_owned()),
+            object_type: class(
+                "Unity.ResourceManager, Version=0.0.0.0, Culture=neutral, PublicKeyToken=null",
+                id,
+            ),
+            data: Arc::new(String::new()),
+        }
+    }
+
+    /// An asset location depending on the bundle containing it, both with their own key.
+    fn catalog(build_result_hash: &str) -> AddressablesCatalog {
+        let bundle = Arc::new(ResourceLocation {
+            internal_id: Arc::new(
+                "{UnityEngine.AddressableAssets.Addressables.RuntimePath}/StandaloneWindows64/prefabs_assets_all.bundle"
+                    .to_owned(),
+            ),
+            provider_id: Arc::new(resource_providers::ASSET_BUNDLE.to_owned()),
+            dependencies: Vec::new(),
+            data: Some(AssetBundleRequestOptions {
+                hash: Hash128::from_u32s([1, 2, 3, 0xdeadbeef]),
+                crc: 1234,
+                common_info: CommonInfo {
+                    timeout: 0,
+                    redirect_limit: 32,
+                    retry_count: 0,
+                    flags: CommonInfo::CHUNKED_TRANSFER
+                        | CommonInfo::USE_CRC_FOR_CACHED_BUNDLES
+                        | CommonInfo::CLEAR_OTHER_CACHED_VERSIONS_WHEN_LOADED,
+                },
+                bundle_name: Arc::new("prefabs_assets_all".to_owned()),
+                bundle_size: 4096,
+            }),
+            dependency_hash_code: 0,
+            primary_key: Arc::new("prefabs_assets_all.bundle".to_owned()),
+            type_: class(
+                "Unity.ResourceManager, Version=0.0.0.0, Culture=neutral, PublicKeyToken=null",
+                "UnityEngine.ResourceManagement.ResourceProviders.IAssetBundleResource",
+            ),
+        });
+        let asset = Arc::new(ResourceLocation {
+            internal_id: Arc::new("Assets/Prefabs/Enemy.prefab".to_owned()),
+            provider_id: Arc::new(resource_providers::BUNDLED_ASSET.to_owned()),
+            dependencies: vec![Arc::clone(&bundle)],
+            data: None,
+            dependency_hash_code: 1337,
+            primary_key: Arc::new("Assets/Prefabs/Enemy.prefab".to_owned()),
+            type_: class(
+                "UnityEngine.CoreModule, Version=0.0.0.0, Culture=neutral, PublicKeyToken=null",
+                "UnityEngine.GameObject",
+            ),
+        });
+
+        AddressablesCatalog {
+            locator_id: Arc::new("AddressablesMainContentCatalog".to_owned()),
+            build_result_hash: Arc::new(build_result_hash.to_owned()),
+            instance_provider_data: init_data(
+                "UnityEngine.ResourceManagement.ResourceProviders.InstanceProvider",
+            ),
+            scene_provider_data: init_data(
+                "UnityEngine.ResourceManagement.ResourceProviders.SceneProvider",
+            ),
+            resource_provider_data: vec![
+                init_data(resource_providers::ASSET_BUNDLE),
+                init_data(resource_providers::BUNDLED_ASSET),
+            ],
+            resources: HashMap::from([
+                (Arc::clone(&asset.primary_key), vec![Arc::clone(&asset)]),
+                (Arc::clone(&bundle.primary_key), vec![Arc::clone(&bundle)]),
+            ]),
+        }
+    }
+
+    #[test]
+    fn roundtrip_binary() {
+        let mut catalog = catalog("0123456789abcdef");
+        // non-ASCII strings are stored as UTF-16
+        let asset = Arc::clone(&catalog.resources[&"Assets/Prefabs/Enemy.prefab".to_owned()][0]);
+        catalog
+            .resources
+            .insert(Arc::new("Gegner/Fähre 敵".to_owned()), vec![asset]);
+        assert_eq!(parse(&catalog.write_binary()).unwrap(), catalog);
+    }
+
+    #[test]
+    fn roundtrip_binary_v1() {
+        let catalog = catalog("");
+        let data = catalog.write_binary_version(BinaryCatalogVersion::V1);
+        let mut reader = BinaryCatalogReader::new(Cursor::new(&data)).unwrap();
+        assert_eq!(reader.version(), BinaryCatalogVersion::V1);
+        assert_eq!(reader.read().unwrap(), catalog);
+    }
+
+    #[test]
+    fn roundtrip_json() {
+        let catalog = catalog("0123456789abcdef");
+        let data = catalog.write_json().unwrap();
+        assert_eq!(parse(&data).unwrap(), catalog);
+    }
+
+    #[test]
+    fn json_adds_missing_dependency_keys() {
+        let mut catalog = catalog("");
+        catalog
+            .resources
+            .retain(|key, _| key.as_str() == "Assets/Prefabs/Enemy.prefab");
+        let bundle_key = Arc::new("prefabs_assets_all.bundle".to_owned());
+
+        let parsed = parse(&catalog.write_json().unwrap()).unwrap();
+        assert_eq!(
+            parsed.resources[&"Assets/Prefabs/Enemy.prefab".to_owned()],
+            catalog.resources[&"Assets/Prefabs/Enemy.prefab".to_owned()]
+        );
+        // the dependency list and the bundle's primary key need a bucket in the JSON catalog
+        assert_eq!(parsed.resources.len(), 3);
+        assert!(parsed.resources[&bundle_key].is_empty());
+        assert_eq!(parsed.resources[&"1337".to_owned()].len(), 1);
+    }
+
+    #[test]
+    fn merge() {
+        let mut merged = catalog("");
+        let mut other = catalog("");
+        let asset = Arc::clone(&other.resources[&"Assets/Prefabs/Enemy.prefab".to_owned()][0]);
+        let patched = Arc::new(ResourceLocation {
+            internal_id: Arc::new("Assets/Prefabs/EnemyPatched.prefab".to_owned()),
+            provider_id: Arc::clone(&asset.provider_id),
+            dependencies: asset.dependencies.clone(),
+            data: None,
+            dependency_hash_code: asset.dependency_hash_code,
+            primary_key: Arc::clone(&asset.primary_key),
+            type_: asset.type_.clone(),
+        });
+        other
+            .resources
+            .insert(Arc::clone(&patched.primary_key), vec![Arc::clone(&patched)]);
+        other
+            .resource_provider_data
+            .push(init_data("Mod.CustomProvider"));
+
+        merged.merge(other);
+        let enemy = &merged.resources[&"Assets/Prefabs/Enemy.prefab".to_owned()];
+        assert_eq!(enemy.len(), 2);
+        assert_eq!(*enemy[0].internal_id, "Assets/Prefabs/Enemy.prefab");
+        assert_eq!(enemy[1], patched);
+        assert_eq!(
+            merged.resources[&"prefabs_assets_all.bundle".to_owned()].len(),
+            1
+        );
+        assert_eq!(merged.resource_provider_data.len(), 3);
+    }
+}
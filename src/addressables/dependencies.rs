@@ -0,0 +1,359 @@
+//! Dependency graph between addressables bundles.
+//!
+//! ```no_run
+//! # use rabex_env::Environment;
+//! # use rabex_env::addressables::dependencies::BundleDependencies;
+//! # fn run(env: &Environment) -> anyhow::Result<()> {
+//! let dependencies = BundleDependencies::build(env)?;
+//! for bundle in dependencies.bundles_for_key("Assets/Prefabs/Enemy.prefab") {
+//!     println!("load {}", bundle.display());
+//! }
+//! for cycle in dependencies.cycles() {
+//!     println!("cycle: {cycle:?}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use rabex::files::SerializedFile;
+use rabex::typetree::TypeTreeProvider;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::Environment;
+use crate::addressables::catalog::{ResourceLocation, resource_providers};
+use crate::addressables::{AddressablesData, ArchivePath};
+use crate::resolver::EnvResolver;
+use crate::utils;
+
+/// Dependencies between addressables bundles, merged from the catalog dependencies
+/// of each location and the `m_Externals` of the serialized files inside the bundles.
+///
+/// Bundles are identified by their path relative to the build folder, like in [`AddressablesData::bundle_to_cab`].
+/// Catalog dependencies which can't be resolved to a bundle in the build folder, e.g. remote bundles,
+/// keep their evaluated internal id and are listed in [`BundleDependencies::missing`].
+/// Externals referencing a CAB not contained in any bundle are listed in [`BundleDependencies::missing_cabs`].
+#[derive(Debug, Default)]
+pub struct BundleDependencies {
+    /// Direct dependencies of each bundle
+    pub dependencies: BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+    /// Bundles directly containing the locations of each catalog key
+    pub key_bundles: FxHashMap<String, BTreeSet<PathBuf>>,
+    /// Dependencies that aren't a bundle in the build folder
+    pub missing: BTreeSet<PathBuf>,
+    /// Serialized files referenced in `m_Externals` which aren't in any bundle, e.g. `CAB-abcd`
+    pub missing_cabs: BTreeSet<String>,
+}
+
+impl BundleDependencies {
+    /// Reads all catalogs and the externals of every bundle. Bundles are read in parallel.
+    pub fn build<R: EnvResolver, P: TypeTreeProvider + Sync>(
+        env: &Environment<R, P>,
+    ) -> Result<BundleDependencies> {
+        let addressables = env
+            .addressables()?
+            .context("no addressables settings found")?;
+
+        let mut graph = BundleDependencies::default();
+        for bundle in addressables.bundle_paths() {
+            graph.dependencies.entry(bundle.to_owned()).or_default();
+        }
+
+        for catalog in env.addressables_catalogs()? {
+            for (key, locations) in &catalog.resources {
+                for location in locations {
+                    graph.add_location(addressables, key, location);
+                }
+            }
+        }
+
+        let bundles: Vec<_> = addressables.bundle_paths().collect();
+        let (external_dependencies, missing_cabs) = utils::par_fold_reduce(
+            bundles,
+            |acc: &mut (BTreeMap<PathBuf, BTreeSet<PathBuf>>, BTreeSet<String>), bundle| {
+                let dependencies = external_dependencies(env, addressables, bundle, &mut acc.1)
+                    .with_context(|| format!("Failed to read externals of {}", bundle.display()))?;
+                acc.0.insert(bundle.to_owned(), dependencies);
+                Ok(())
+            },
+        )?;
+        graph.missing_cabs = missing_cabs;
+        for (bundle, dependencies) in external_dependencies {
+            graph
+                .dependencies
+                .entry(bundle)
+                .or_default()
+                .extend(dependencies);
+        }
+
+        graph.missing = graph
+            .dependencies
+            .values()
+            .flatten()
+            .filter(|dependency| !addressables.bundle_to_cab.contains_key(*dependency))
+            .cloned()
+            .collect();
+
+        Ok(graph)
+    }
+
+    fn add_location(
+        &mut self,
+        addressables: &AddressablesData,
+        key: &str,
+        location: &ResourceLocation,
+    ) {
+        let bundles: Vec<_> = location
+            .dependencies
+            .iter()
+            .filter(|dependency| *dependency.provider_id == resource_providers::ASSET_BUNDLE)
+            .map(|dependency| addressables.bundle_path(&dependency.internal_id))
+            .collect();
+        // The first dependency is the bundle containing the location, the rest are its dependencies
+        let Some((bundle, dependencies)) = bundles.split_first() else {
+            return;
+        };
+
+        self.key_bundles
+            .entry(key.to_owned())
+            .or_default()
+            .insert(bundle.clone());
+        self.dependencies.entry(bundle.clone()).or_default().extend(
+            dependencies
+                .iter()
+                .filter(|&dependency| dependency != bundle)
+                .cloned(),
+        );
+    }
+
+    /// Direct dependencies of `bundle`
+    pub fn dependencies_of(&self, bundle: &Path) -> impl Iterator<Item = &Path> {
+        self.dependencies
+            .get(bundle)
+            .into_iter()
+            .flatten()
+            .map(PathBuf::as_path)
+    }
+
+    /// All bundles needed to load `roots`, including the roots themselves.
+    ///
+    /// Dependencies come before the bundles depending on them. Bundles in a cycle are ordered arbitrarily.
+    pub fn load_order<'a>(&self, roots: impl IntoIterator<Item = &'a Path>) -> Vec<PathBuf> {
+        let mut visited = FxHashSet::default();
+        let mut order = Vec::new();
+        for root in roots {
+            self.visit_post_order(root, &mut visited, &mut order);
+        }
+        order
+    }
+
+    fn visit_post_order<'a>(
+        &'a self,
+        bundle: &'a Path,
+        visited: &mut FxHashSet<&'a Path>,
+        order: &mut Vec<PathBuf>,
+    ) {
+        if !visited.insert(bundle) {
+            return;
+        }
+        for dependency in self.dependencies_of(bundle) {
+            self.visit_post_order(dependency, visited, order);
+        }
+        order.push(bundle.to_owned());
+    }
+
+    /// All bundles needed to load the asset or scene with the catalog key `key`, in load order.
+    pub fn bundles_for_key(&self, key: &str) -> Vec<PathBuf> {
+        let roots = self.key_bundles.get(key).into_iter().flatten();
+        self.load_order(roots.map(PathBuf::as_path))
+    }
+
+    /// Groups of bundles depending on each other, including bundles depending on themselves.
+    pub fn cycles(&self) -> Vec<Vec<PathBuf>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: 0,
+            indices: FxHashMap::default(),
+            stack: Vec::new(),
+            on_stack: FxHashSet::default(),
+            components: Vec::new(),
+        };
+        for bundle in self.dependencies.keys() {
+            if !tarjan.indices.contains_key(bundle.as_path()) {
+                tarjan.strong_connect(bundle);
+            }
+        }
+
+        tarjan
+            .components
+            .into_iter()
+            .filter(|component| match component.as_slice() {
+                [single] => self.dependencies_of(single).any(|dep| dep == single),
+                _ => true,
+            })
+            .map(|mut component| {
+                component.sort();
+                component.into_iter().map(Path::to_owned).collect()
+            })
+            .collect()
+    }
+}
+
+/// Tarjan's strongly connected components algorithm
+struct Tarjan<'a> {
+    graph: &'a BundleDependencies,
+    index: usize,
+    /// (index, lowlink) of visited bundles
+    indices: FxHashMap<&'a Path, (usize, usize)>,
+    stack: Vec<&'a Path>,
+    on_stack: FxHashSet<&'a Path>,
+    components: Vec<Vec<&'a Path>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn strong_connect(&mut self, bundle: &'a Path) {
+        let index = self.index;
+        self.index += 1;
+        self.indices.insert(bundle, (index, index));
+        self.stack.push(bundle);
+        self.on_stack.insert(bundle);
+
+        for dependency in self.graph.dependencies_of(bundle) {
+            let lowlink = match self.indices.get(dependency) {
+                None => {
+                    self.strong_connect(dependency);
+                    self.indices[dependency].1
+                }
+                Some(&(dependency_index, _)) if self.on_stack.contains(dependency) => {
+                    dependency_index
+                }
+                Some(_) => continue,
+            };
+            let entry = self.indices.get_mut(bundle).unwrap();
+            entry.1 = entry.1.min(lowlink);
+        }
+
+        if self.indices[bundle].1 == index {
+            let mut component = Vec::new();
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack.remove(member);
+                component.push(member);
+                if member == bundle {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+/// Bundles referenced in the `m_Externals` of the serialized files of `bundle`.
+///
+/// Referenced CABs which aren't in any bundle are added to `missing_cabs`.
+fn external_dependencies<R: EnvResolver, P: TypeTreeProvider>(
+    env: &Environment<R, P>,
+    addressables: &AddressablesData,
+    bundle: &Path,
+    missing_cabs: &mut BTreeSet<String>,
+) -> Result<BTreeSet<PathBuf>> {
+    let reader = env.load_addressables_bundle(bundle)?;
+
+    let mut dependencies = BTreeSet::new();
+    for entry in reader.serialized_files() {
+        let data = reader
+            .read_at(&entry.path)?
+            .with_context(|| format!("missing {} in bundle", entry.path))?;
+        let file = SerializedFile::from_reader(&mut Cursor::new(data.as_slice()))?;
+        for external in &file.m_Externals {
+            let Some(archive_path) = ArchivePath::try_parse(Path::new(&external.pathName))? else {
+                // built-in files like `resources.assets`
+                continue;
+            };
+            match addressables.cab_to_bundle.get(archive_path.bundle) {
+                Some(dependency) if dependency != bundle => {
+                    dependencies.insert(dependency.clone());
+                }
+                Some(_) => {}
+                None => {
+                    missing_cabs.insert(archive_path.bundle.to_owned());
+                }
+            }
+        }
+    }
+
+    Ok(dependencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &[&str])]) -> BundleDependencies {
+        let dependencies = edges
+            .iter()
+            .map(|&(bundle, dependencies)| {
+                let dependencies = dependencies.iter().map(PathBuf::from).collect();
+                (PathBuf::from(bundle), dependencies)
+            })
+            .collect();
+        BundleDependencies {
+            dependencies,
+            ..Default::default()
+        }
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn load_order_puts_dependencies_first() {
+        let graph = graph(&[
+            ("scene", &["prefabs", "shaders"]),
+            ("prefabs", &["textures", "shaders"]),
+            ("textures", &[]),
+            ("shaders", &[]),
+            ("unrelated", &["shaders"]),
+        ]);
+        assert_eq!(
+            graph.load_order([Path::new("scene")]),
+            paths(&["textures", "shaders", "prefabs", "scene"])
+        );
+        assert!(graph.cycles().is_empty());
+    }
+
+    #[test]
+    fn bundles_for_key() {
+        let mut graph = graph(&[("a", &["b"]), ("b", &[]), ("c", &[])]);
+        graph.key_bundles.insert(
+            "Assets/A.prefab".to_owned(),
+            BTreeSet::from([PathBuf::from("a")]),
+        );
+
+        assert_eq!(graph.bundles_for_key("Assets/A.prefab"), paths(&["b", "a"]));
+        assert!(graph.bundles_for_key("Assets/Missing.prefab").is_empty());
+    }
+
+    #[test]
+    fn cycles_terminate_and_are_reported() {
+        let graph = graph(&[
+            ("a", &["b"]),
+            ("b", &["c"]),
+            ("c", &["a", "d"]),
+            ("d", &[]),
+            ("self", &["self"]),
+        ]);
+        assert_eq!(
+            graph.load_order([Path::new("a")]),
+            paths(&["d", "c", "b", "a"])
+        );
+
+        let mut cycles = graph.cycles();
+        cycles.sort();
+        assert_eq!(cycles, [paths(&["a", "b", "c"]), paths(&["self"])]);
+    }
+}
@@ -0,0 +1,56 @@
+//! Fetching remote content catalogs and their `.hash` files, see [`CatalogFetcher`].
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+/// Downloads the remote files referenced by the addressables settings.
+///
+/// rabex-env doesn't do any networking itself, so remote catalogs are skipped
+/// unless a fetcher is set in [`AddressablesData::catalog_fetcher`](super::AddressablesData::catalog_fetcher).
+///
+/// ```
+/// # use rabex_env::addressables::fetch::CatalogFetcher;
+/// let fetcher = |url: &str| -> anyhow::Result<Option<Vec<u8>>> {
+///     Ok(url.ends_with(".hash").then(|| b"0123456789abcdef".to_vec()))
+/// };
+/// assert!(fetcher.fetch("https://cdn.example.com/catalog.json").unwrap().is_none());
+/// ```
+pub trait CatalogFetcher: Send + Sync {
+    /// The contents of `url`, or `None` if the server doesn't have it.
+    fn fetch(&self, url: &str) -> Result<Option<Vec<u8>>>;
+}
+
+impl<F: Fn(&str) -> Result<Option<Vec<u8>>> + Send + Sync> CatalogFetcher for F {
+    fn fetch(&self, url: &str) -> Result<Option<Vec<u8>>> {
+        self(url)
+    }
+}
+
+/// Serves URLs starting with `base_url` from a local directory, e.g. a mirror of the game's CDN.
+#[derive(Debug, Clone)]
+pub struct DirFetcher {
+    base_url: String,
+    dir: PathBuf,
+}
+
+impl DirFetcher {
+    pub fn new(base_url: impl Into<String>, dir: impl Into<PathBuf>) -> Self {
+        DirFetcher {
+            base_url: base_url.into(),
+            dir: dir.into(),
+        }
+    }
+}
+
+impl CatalogFetcher for DirFetcher {
+    fn fetch(&self, url: &str) -> Result<Option<Vec<u8>>> {
+        let Some(path) = url.strip_prefix(&self.base_url) else {
+            return Ok(None);
+        };
+        match std::fs::read(self.dir.join(path.trim_start_matches('/'))) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -1,21 +1,35 @@
 //! Types for interacting with the [Addressables](https://docs.unity3d.com/Packages/com.unity.addressables@3.1/manual/index.html) unity package
 mod archive_path;
 pub mod catalog;
+pub mod dependencies;
+pub mod fetch;
+pub mod placeholders;
 pub mod settings;
+pub mod verify;
 
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{Context as _, Result};
-use rabex::UnityVersion;
-use rabex::files::bundlefile::{BundleFileReader, ExtractionConfig};
+use anyhow::{Context as _, Result, bail};
+use rabex::files::SerializedFile;
 use rabex::typetree::TypeTreeProvider;
 use rustc_hash::FxHashMap;
 
 use crate::Environment;
-use crate::addressables::catalog::{AddressablesCatalog, ResourceLocation};
-use crate::addressables::settings::AddressablesSettings;
+use crate::addressables::catalog::{AddressablesCatalog, ResourceLocation, resource_providers};
+use crate::addressables::fetch::CatalogFetcher;
+use crate::addressables::placeholders::{LocationKind, PlaceholderEvaluator};
+use crate::addressables::settings::{AddressablesSettings, CatalogLocation};
+use crate::bundles::BundleIndex;
+use crate::handle::SerializedFileHandle;
 use crate::resolver::EnvResolver;
+use crate::unity::types::TextAsset;
+
+const CONTENT_CATALOG_PROVIDER: &str =
+    "UnityEngine.AddressableAssets.ResourceProviders.ContentCatalogProvider";
+const TEXT_DATA_PROVIDER: &str =
+    "UnityEngine.ResourceManagement.ResourceProviders.TextDataProvider";
 
 pub use archive_path::ArchivePath;
 
@@ -25,6 +39,10 @@ pub struct AddressablesData {
     pub settings: AddressablesSettings,
     pub cab_to_bundle: FxHashMap<String, PathBuf>,
     pub bundle_to_cab: FxHashMap<PathBuf, Vec<String>>,
+    /// Expands the placeholders in internal ids, see [`Environment::addressables_mut`] for adding overrides
+    pub placeholders: PlaceholderEvaluator,
+    /// Downloads remote catalogs, see [`AddressablesData::catalogs`]
+    pub catalog_fetcher: Option<Box<dyn CatalogFetcher>>,
 }
 
 impl std::fmt::Debug for AddressablesData {
@@ -32,6 +50,8 @@ impl std::fmt::Debug for AddressablesData {
         f.debug_struct("AddressablesData")
             .field("settings", &self.settings)
             .field("bundle_to_cab", &self.bundle_to_cab)
+            .field("placeholders", &self.placeholders)
+            .field("catalog_fetcher", &self.catalog_fetcher.is_some())
             .finish()
     }
 }
@@ -51,28 +71,150 @@ impl AddressablesData {
         self.bundle_to_cab.keys().map(AsRef::as_ref)
     }
 
-    pub fn catalogs(&self, env: &impl EnvResolver) -> Result<Vec<AddressablesCatalog>> {
-        self.settings
-            .m_CatalogLocations
-            .iter()
-            .filter_map(|catalog| {
-                if catalog.m_Provider
-                    != "UnityEngine.AddressableAssets.ResourceProviders.ContentCatalogProvider"
-                {
-                    tracing::warn!("Unsupported catalog provider: '{}'", catalog.m_Provider);
-                    return None;
+    /// Loads the content catalogs listed in the settings, in order.
+    ///
+    /// Local catalogs may be stored as `catalog.json`/`catalog.bin`, or as a TextAsset inside a `catalog.bundle`
+    /// when `m_IsLocalCatalogInBundle` is set. Remote catalogs are downloaded through the [`catalog_fetcher`](Self::catalog_fetcher),
+    /// or skipped if there is none. Like unity, a local catalog with a remote `.hash` dependency is replaced
+    /// by the remote catalog if that hash differs from the local `.hash` sidecar.
+    ///
+    /// See [`Environment::addressables_catalog`] for all catalogs merged into one.
+    pub fn catalogs<R: EnvResolver, P: TypeTreeProvider>(
+        &self,
+        env: &Environment<R, P>,
+    ) -> Result<Vec<AddressablesCatalog>> {
+        let mut catalogs = Vec::new();
+        for location in &self.settings.m_CatalogLocations {
+            match location.m_Provider.as_str() {
+                CONTENT_CATALOG_PROVIDER => {}
+                // `.hash` files, loaded as dependencies of their catalog
+                TEXT_DATA_PROVIDER if self.is_catalog_dependency(location) => continue,
+                provider => {
+                    tracing::warn!("Unsupported catalog provider: '{provider}'");
+                    continue;
                 }
-                let path = self.evaluate_string(&catalog.m_InternalId);
+            }
 
-                Some((|| {
-                    let data = env.read_path(Path::new(&path))?;
-                    catalog::parse(data.as_ref())
-                })())
-            })
-            .collect()
+            let catalog = self
+                .load_catalog(env, location)
+                .with_context(|| format!("Failed to load catalog '{}'", location.m_InternalId))?;
+            catalogs.extend(catalog);
+        }
+        Ok(catalogs)
+    }
+
+    fn is_catalog_dependency(&self, location: &CatalogLocation) -> bool {
+        self.settings.m_CatalogLocations.iter().any(|catalog| {
+            catalog
+                .m_Dependencies
+                .iter()
+                .any(|key| location.m_Keys.contains(key))
+        })
     }
 
-    pub fn resource_locations(&self, env: &impl EnvResolver) -> Result<Vec<Arc<ResourceLocation>>> {
+    fn load_catalog<R: EnvResolver, P: TypeTreeProvider>(
+        &self,
+        env: &Environment<R, P>,
+        location: &CatalogLocation,
+    ) -> Result<Option<AddressablesCatalog>> {
+        let local = self.placeholders.classify(&location.m_InternalId);
+        if let Some(updated) = self.fetch_updated_catalog(env, location, &local)? {
+            return Ok(Some(updated));
+        }
+
+        match local {
+            LocationKind::Local(path) => {
+                let is_bundle = path.extension().is_some_and(|ext| ext == "bundle");
+                if is_bundle || self.settings.m_IsLocalCatalogInBundle {
+                    return read_bundled_catalog(env, &path.with_extension("bundle")).map(Some);
+                }
+                let data = env.game_files.read_path(&path)?;
+                catalog::parse(data.as_ref()).map(Some)
+            }
+            LocationKind::Remote(url) => match self.fetch(&url)? {
+                Some(data) => catalog::parse(&data).map(Some),
+                None => Ok(None),
+            },
+            LocationKind::Unresolvable {
+                internal_id,
+                placeholders,
+            } => bail!("Unresolved placeholders {placeholders:?} in '{internal_id}'"),
+        }
+    }
+
+    /// Unity's `ContentCatalogProvider` treats the first dependency as the remote hash and the second
+    /// one as the cached hash, and only downloads the remote catalog next to the remote hash if that changed.
+    /// There is no cache here, so the remote hash is compared to the `.hash` sidecar of the local catalog.
+    fn fetch_updated_catalog<R: EnvResolver, P: TypeTreeProvider>(
+        &self,
+        env: &Environment<R, P>,
+        location: &CatalogLocation,
+        local: &LocationKind,
+    ) -> Result<Option<AddressablesCatalog>> {
+        if self.settings.m_DisableCatalogUpdateOnStart || self.catalog_fetcher.is_none() {
+            return Ok(None);
+        }
+        let Some(remote_hash) = location
+            .m_Dependencies
+            .first()
+            .and_then(|key| self.settings.catalog_location(key))
+        else {
+            return Ok(None);
+        };
+        let LocationKind::Remote(hash_url) = self.placeholders.classify(&remote_hash.m_InternalId)
+        else {
+            return Ok(None);
+        };
+        let Some(hash) = self.fetch(&hash_url)? else {
+            return Ok(None);
+        };
+        let hash = String::from_utf8_lossy(&hash);
+
+        let mut extension = "json";
+        if let LocationKind::Local(path) = local {
+            let local_hash = env.game_files.read_path(&path.with_extension("hash")).ok();
+            let local_hash = local_hash
+                .as_ref()
+                .map(|data| String::from_utf8_lossy(data.as_ref()));
+            if local_hash.is_some_and(|local_hash| local_hash.trim() == hash.trim()) {
+                return Ok(None);
+            }
+            if path.extension().is_some_and(|ext| ext == "bin") {
+                extension = "bin";
+            }
+        }
+
+        let base_url = hash_url.strip_suffix(".hash").unwrap_or(&hash_url);
+        let catalog_url = format!("{base_url}.{extension}");
+        match self.fetch(&catalog_url)? {
+            Some(data) => catalog::parse(&data)
+                .with_context(|| format!("Failed to parse remote catalog '{catalog_url}'"))
+                .map(Some),
+            None => {
+                tracing::warn!("Remote catalog '{catalog_url}' not found, using the local one");
+                Ok(None)
+            }
+        }
+    }
+
+    fn fetch(&self, url: &str) -> Result<Option<Vec<u8>>> {
+        let Some(fetcher) = &self.catalog_fetcher else {
+            tracing::warn!("Skipping remote catalog '{url}' without a catalog fetcher");
+            return Ok(None);
+        };
+        let data = fetcher
+            .fetch(url)
+            .with_context(|| format!("Failed to fetch '{url}'"))?;
+        if data.is_none() {
+            tracing::warn!("Remote catalog file '{url}' not found");
+        }
+        Ok(data)
+    }
+
+    pub fn resource_locations<R: EnvResolver, P: TypeTreeProvider>(
+        &self,
+        env: &Environment<R, P>,
+    ) -> Result<Vec<Arc<ResourceLocation>>> {
         let mut all = Vec::new();
         for catalog in self.catalogs(env)? {
             for (_key, locations) in catalog.resources {
@@ -84,11 +226,53 @@ impl AddressablesData {
         Ok(all)
     }
 
+    /// Expands the placeholders in `str`. Unknown placeholders are kept as they are, and logged.
     pub fn evaluate_string(&self, str: &str) -> String {
-        str.replace(
-            "{UnityEngine.AddressableAssets.Addressables.RuntimePath}",
-            "StreamingAssets/aa",
-        )
+        match self.placeholders.evaluate(str) {
+            Ok(value) => value,
+            Err(unresolved) => {
+                tracing::warn!("Unresolved addressables placeholders {unresolved:?} in '{str}'");
+                self.placeholders.evaluate_lossy(str)
+            }
+        }
+    }
+
+    /// Whether `location` is loaded from the game files or from a remote server.
+    ///
+    /// Locations inside a bundle are classified by the internal id of their bundle.
+    pub fn classify_location(&self, location: &ResourceLocation) -> LocationKind {
+        let bundle = location
+            .dependencies
+            .iter()
+            .find(|dependency| *dependency.provider_id == resource_providers::ASSET_BUNDLE);
+        let internal_id = match bundle {
+            Some(bundle) if *location.provider_id != resource_providers::ASSET_BUNDLE => {
+                &bundle.internal_id
+            }
+            _ => &location.internal_id,
+        };
+        self.placeholders.classify(internal_id)
+    }
+
+    /// The bundle path relative to the build folder for the internal id of an `AssetBundleProvider` location.
+    ///
+    /// Internal ids outside of the build folder, e.g. remote bundles, are returned as evaluated.
+    pub fn bundle_path(&self, internal_id: &str) -> PathBuf {
+        let path = PathBuf::from(self.evaluate_string(internal_id));
+        match path.strip_prefix(self.build_folder()) {
+            Ok(relative) => relative.to_owned(),
+            Err(_) => path,
+        }
+    }
+
+    /// The bundle containing a `BundledAssetProvider` or `SceneProvider` location,
+    /// i.e. its first `AssetBundleProvider` dependency.
+    pub fn location_bundle(&self, location: &ResourceLocation) -> Option<PathBuf> {
+        location
+            .dependencies
+            .iter()
+            .find(|dependency| *dependency.provider_id == resource_providers::ASSET_BUNDLE)
+            .map(|dependency| self.bundle_path(&dependency.internal_id))
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(skip_all))]
@@ -104,67 +288,69 @@ impl AddressablesData {
         let settings: AddressablesSettings = serde_json::from_slice(settings_bytes.as_ref())?;
 
         let aa_build = Path::new("StreamingAssets/aa").join(&settings.m_buildTarget);
-        let mut lookup =
-            addressables_bundle_lookup(&env.game_files, &aa_build, env.unity_version()?)
-                .context("could not determine CAB locations")?;
-        lookup.1.values_mut().for_each(|files| files.sort());
+        let files = env.game_files.list_under(&aa_build)?;
+        let lookup = BundleIndex::build(&env.game_files, files, &aa_build, env.unity_version()?)
+            .context("could not determine CAB locations")?;
+        let placeholders = PlaceholderEvaluator::new(&settings.m_buildTarget);
         let data = AddressablesData {
+            placeholders,
+            catalog_fetcher: None,
             settings,
-            cab_to_bundle: lookup.0,
-            bundle_to_cab: lookup.1,
+            cab_to_bundle: lookup.cab_to_bundle,
+            bundle_to_cab: lookup.bundle_to_cab,
         };
         Ok(Some(data))
     }
 }
 
-#[allow(clippy::type_complexity)]
-#[cfg_attr(feature = "tracing-instrument", tracing::instrument(skip_all))]
-fn addressables_bundle_lookup<R: EnvResolver>(
-    env: &R,
-    aa_build: &Path,
-    unity_version: &UnityVersion,
-) -> Result<(FxHashMap<String, PathBuf>, FxHashMap<PathBuf, Vec<String>>)> {
-    use rayon::prelude::*;
-
-    #[cfg(feature = "tracing-instrument")]
-    let parent_span = tracing::Span::current();
-
-    let files = env.list_under(aa_build)?;
-    files
-        .into_par_iter()
-        .try_fold(
-            <(FxHashMap<String, PathBuf>, FxHashMap<PathBuf, Vec<String>>)>::default,
-            |mut acc, path| -> Result<_> {
-                #[cfg(feature = "tracing-instrument")]
-                let _parent = parent_span.enter();
-
-                let relative = path.strip_prefix(aa_build).unwrap();
-
-                let bundle_to_cab_files: &mut Vec<_> =
-                    acc.1.entry(relative.to_owned()).or_default();
-
-                #[cfg(feature = "tracing-instrument")]
-                let _span = tracing::info_span!("read_bundle").entered();
-
-                // Only the header is read, so mmap'ing or reading the whole
-                // file would be a waste.
-                let reader = env.open_path(&path)?;
-                let bundle = BundleFileReader::from_reader(
-                    reader,
-                    &ExtractionConfig::default().with_fallback_unity_version(unity_version.clone()),
-                )?;
-
-                for file in bundle.files() {
-                    acc.0.insert(file.path.clone(), relative.to_owned());
-                    bundle_to_cab_files.push(file.path.clone());
-                }
+/// Reads a catalog stored as the `TextAsset` inside `catalog.bundle`.
+fn read_bundled_catalog<R: EnvResolver, P: TypeTreeProvider>(
+    env: &Environment<R, P>,
+    path: &Path,
+) -> Result<AddressablesCatalog> {
+    let bundle = env.load_bundle(path)?;
+    let entry = bundle
+        .main_serializedfile()
+        .context("no non-resource serializedfile in catalog bundle")?;
+    let data = bundle.read_at_entry(entry)?;
+    let mut file = SerializedFile::from_reader(&mut Cursor::new(data.as_slice()))?;
+    file.m_UnityVersion
+        .get_or_insert(env.unity_version()?.clone());
+    let text_asset = SerializedFileHandle::new(env, &file, &data)
+        .find_object_of::<TextAsset>()?
+        .context("no TextAsset in catalog bundle")?;
+    catalog::parse(text_asset.m_Script.as_bytes())
+}
 
-                Ok(acc)
-            },
-        )
-        .try_reduce(Default::default, |mut acc, item| {
-            acc.0.extend(item.0);
-            acc.1.extend(item.1);
-            Ok(acc)
-        })
+/// Splits an internal id like `Assets/Sprites/atlas.png[icon]` into the asset path and the sub-object name.
+pub fn split_sub_object(internal_id: &str) -> (&str, Option<&str>) {
+    internal_id
+        .strip_suffix(']')
+        .and_then(|rest| rest.rsplit_once('['))
+        .map_or((internal_id, None), |(path, sub)| (path, Some(sub)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_sub_object;
+
+    #[test]
+    fn sub_objects() {
+        assert_eq!(
+            split_sub_object("Assets/Prefabs/Enemy.prefab"),
+            ("Assets/Prefabs/Enemy.prefab", None)
+        );
+        assert_eq!(
+            split_sub_object("Assets/Sprites/atlas.png[icon_0]"),
+            ("Assets/Sprites/atlas.png", Some("icon_0"))
+        );
+        assert_eq!(
+            split_sub_object("Assets/[Folder]/a.asset"),
+            ("Assets/[Folder]/a.asset", None)
+        );
+        assert_eq!(
+            split_sub_object("Assets/[Folder]/a.png[b]"),
+            ("Assets/[Folder]/a.png", Some("b"))
+        );
+    }
 }
@@ -0,0 +1,264 @@
+//! Expansion of the placeholders in addressables internal ids.
+//!
+//! Internal ids may contain runtime properties like `{UnityEngine.AddressableAssets.Addressables.RuntimePath}`,
+//! which unity evaluates through reflection, and profile variables like `[BuildTarget]`.
+//!
+//! ```
+//! # use rabex_env::addressables::placeholders::{LocationKind, PlaceholderEvaluator};
+//! let mut evaluator = PlaceholderEvaluator::new("StandaloneWindows64");
+//! evaluator.set("MyMod.Paths.Remote", "https://cdn.example.com");
+//!
+//! assert_eq!(
+//!     evaluator.classify("{UnityEngine.AddressableAssets.Addressables.RuntimePath}/[BuildTarget]/a.bundle"),
+//!     LocationKind::Local("StreamingAssets/aa/StandaloneWindows64/a.bundle".into()),
+//! );
+//! assert_eq!(
+//!     evaluator.classify("{MyMod.Paths.Remote}/b.bundle"),
+//!     LocationKind::Remote("https://cdn.example.com/b.bundle".into()),
+//! );
+//! ```
+use std::path::PathBuf;
+
+use rustc_hash::FxHashMap;
+
+/// Supplies values for placeholders the [`PlaceholderEvaluator`] doesn't know.
+pub trait PlaceholderResolver: Send + Sync {
+    /// The value of `placeholder`, which is the name without the surrounding `{}` or `[]`.
+    fn resolve(&self, placeholder: &str) -> Option<String>;
+}
+
+impl<F: Fn(&str) -> Option<String> + Send + Sync> PlaceholderResolver for F {
+    fn resolve(&self, placeholder: &str) -> Option<String> {
+        self(placeholder)
+    }
+}
+
+/// Where an internal id points to, after expanding its placeholders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocationKind {
+    /// A path relative to the game data directory
+    Local(PathBuf),
+    /// A URL
+    Remote(String),
+    /// The internal id contains placeholders without a known value
+    Unresolvable {
+        internal_id: String,
+        placeholders: Vec<String>,
+    },
+}
+
+/// Expands `{Runtime.Property}` and `[Variable]` placeholders.
+///
+/// Placeholders are looked up in the overrides set via [`PlaceholderEvaluator::set`] first,
+/// then in the [`PlaceholderResolver`]s in the order they were added, and finally in the defaults:
+/// - `UnityEngine.AddressableAssets.Addressables.RuntimePath`: `StreamingAssets/aa`
+/// - `UnityEngine.Application.streamingAssetsPath`: `StreamingAssets`
+/// - `UnityEngine.Application.platform`: the `RuntimePlatform` of the build target, e.g. `WindowsPlayer`
+/// - `BuildTarget`: the build target from the addressables settings, e.g. `StandaloneWindows64`
+pub struct PlaceholderEvaluator {
+    build_target: String,
+    overrides: FxHashMap<String, String>,
+    resolvers: Vec<Box<dyn PlaceholderResolver>>,
+}
+
+impl std::fmt::Debug for PlaceholderEvaluator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PlaceholderEvaluator")
+            .field("build_target", &self.build_target)
+            .field("overrides", &self.overrides)
+            .field("resolvers", &self.resolvers.len())
+            .finish()
+    }
+}
+
+impl PlaceholderEvaluator {
+    pub fn new(build_target: &str) -> Self {
+        PlaceholderEvaluator {
+            build_target: build_target.to_owned(),
+            overrides: FxHashMap::default(),
+            resolvers: Vec::new(),
+        }
+    }
+
+    /// Override the value of `placeholder`, e.g. `MyGame.Config.CdnUrl` or `BuildTarget`.
+    pub fn set(&mut self, placeholder: &str, value: &str) -> &mut Self {
+        self.overrides
+            .insert(placeholder.to_owned(), value.to_owned());
+        self
+    }
+
+    pub fn add_resolver(&mut self, resolver: impl PlaceholderResolver + 'static) -> &mut Self {
+        self.resolvers.push(Box::new(resolver));
+        self
+    }
+
+    /// The value of a single placeholder, without the surrounding `{}` or `[]`.
+    pub fn resolve(&self, placeholder: &str) -> Option<String> {
+        if let Some(value) = self.overrides.get(placeholder) {
+            return Some(value.clone());
+        }
+        if let Some(value) = self
+            .resolvers
+            .iter()
+            .find_map(|resolver| resolver.resolve(placeholder))
+        {
+            return Some(value);
+        }
+
+        let value = match placeholder {
+            "UnityEngine.AddressableAssets.Addressables.RuntimePath" => "StreamingAssets/aa",
+            "UnityEngine.Application.streamingAssetsPath" => "StreamingAssets",
+            "UnityEngine.Application.platform" => runtime_platform(&self.build_target)?,
+            "BuildTarget" => self.build_target.as_str(),
+            _ => return None,
+        };
+        Some(value.to_owned())
+    }
+
+    /// Expands all placeholders in `input`.
+    ///
+    /// Returns the names of the placeholders without a value as the error.
+    pub fn evaluate(&self, input: &str) -> Result<String, Vec<String>> {
+        let mut unresolved = Vec::new();
+        let expanded = self.expand(input, '{', '}', &mut unresolved);
+        let expanded = self.expand(&expanded, '[', ']', &mut unresolved);
+        match unresolved.is_empty() {
+            true => Ok(expanded),
+            false => Err(unresolved),
+        }
+    }
+
+    /// Expands all placeholders in `input`, leaving the unknown ones as they are.
+    pub fn evaluate_lossy(&self, input: &str) -> String {
+        let mut unresolved = Vec::new();
+        let expanded = self.expand(input, '{', '}', &mut unresolved);
+        self.expand(&expanded, '[', ']', &mut unresolved)
+    }
+
+    /// Expands the placeholders in `internal_id` and determines whether it is a local path or a URL.
+    pub fn classify(&self, internal_id: &str) -> LocationKind {
+        let expanded = match self.evaluate(internal_id) {
+            Ok(expanded) => expanded,
+            Err(placeholders) => {
+                return LocationKind::Unresolvable {
+                    internal_id: internal_id.to_owned(),
+                    placeholders,
+                };
+            }
+        };
+
+        if let Some(path) = expanded.strip_prefix("file://") {
+            LocationKind::Local(PathBuf::from(path.replace('\\', "/")))
+        } else if is_url(&expanded) {
+            LocationKind::Remote(expanded)
+        } else {
+            LocationKind::Local(PathBuf::from(expanded.replace('\\', "/")))
+        }
+    }
+
+    fn expand(&self, input: &str, open: char, close: char, unresolved: &mut Vec<String>) -> String {
+        let mut out = String::with_capacity(input.len());
+        let mut rest = input;
+        while let Some(start) = rest.find(open) {
+            let Some(len) = rest[start + 1..].find(close) else {
+                break;
+            };
+            let name = &rest[start + 1..start + 1 + len];
+            out.push_str(&rest[..start]);
+            match self.resolve(name) {
+                Some(value) => out.push_str(&value),
+                None => {
+                    unresolved.push(name.to_owned());
+                    out.push_str(&rest[start..start + len + 2]);
+                }
+            }
+            rest = &rest[start + len + 2..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+fn is_url(path: &str) -> bool {
+    // `scheme.len() > 1` excludes windows drive letters
+    path.split_once("://").is_some_and(|(scheme, _)| {
+        scheme.len() > 1 && scheme.chars().all(|c| c.is_ascii_alphabetic())
+    })
+}
+
+/// `UnityEngine.RuntimePlatform` for a `BuildTarget`
+fn runtime_platform(build_target: &str) -> Option<&'static str> {
+    Some(match build_target {
+        "StandaloneWindows" | "StandaloneWindows64" => "WindowsPlayer",
+        "StandaloneLinux64" => "LinuxPlayer",
+        "StandaloneOSX" => "OSXPlayer",
+        "Android" => "Android",
+        "iOS" => "IPhonePlayer",
+        "WebGL" => "WebGLPlayer",
+        "Switch" => "Switch",
+        "PS4" => "PS4",
+        "PS5" => "PS5",
+        "XboxOne" => "XboxOne",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        let evaluator = PlaceholderEvaluator::new("StandaloneLinux64");
+        assert_eq!(
+            evaluator.evaluate(
+                "{UnityEngine.AddressableAssets.Addressables.RuntimePath}/[BuildTarget]/a.bundle"
+            ),
+            Ok("StreamingAssets/aa/StandaloneLinux64/a.bundle".to_owned())
+        );
+        assert_eq!(
+            evaluator.evaluate(
+                "{UnityEngine.Application.streamingAssetsPath}/{UnityEngine.Application.platform}"
+            ),
+            Ok("StreamingAssets/LinuxPlayer".to_owned())
+        );
+    }
+
+    #[test]
+    fn overrides_and_resolvers() {
+        let mut evaluator = PlaceholderEvaluator::new("StandaloneWindows64");
+        evaluator
+            .set("BuildTarget", "Custom")
+            .add_resolver(|name: &str| (name == "Game.Cdn").then(|| "https://cdn".to_owned()));
+
+        assert_eq!(evaluator.evaluate("[BuildTarget]"), Ok("Custom".to_owned()));
+        assert_eq!(
+            evaluator.classify("{Game.Cdn}\\a.bundle"),
+            LocationKind::Remote("https://cdn\\a.bundle".to_owned())
+        );
+    }
+
+    #[test]
+    fn classify() {
+        let evaluator = PlaceholderEvaluator::new("StandaloneWindows64");
+        assert_eq!(
+            evaluator.classify("{UnityEngine.AddressableAssets.Addressables.RuntimePath}\\StandaloneWindows64\\a.bundle"),
+            LocationKind::Local(PathBuf::from("StreamingAssets/aa/StandaloneWindows64/a.bundle"))
+        );
+        assert_eq!(
+            evaluator.classify("http://localhost:8080/a.bundle"),
+            LocationKind::Remote("http://localhost:8080/a.bundle".to_owned())
+        );
+        assert_eq!(
+            evaluator.classify("{Game.Paths.Mods}/[Unknown]/a.bundle"),
+            LocationKind::Unresolvable {
+                internal_id: "{Game.Paths.Mods}/[Unknown]/a.bundle".to_owned(),
+                placeholders: vec!["Game.Paths.Mods".to_owned(), "Unknown".to_owned()],
+            }
+        );
+        assert_eq!(
+            evaluator.evaluate_lossy("{Game.Paths.Mods}/[BuildTarget]"),
+            "{Game.Paths.Mods}/StandaloneWindows64"
+        );
+    }
+}
@@ -22,6 +22,13 @@ impl AddressablesSettings {
     pub fn build_folder(&self) -> PathBuf {
         Path::new("StreamingAssets/aa").join(&self.m_buildTarget)
     }
+
+    /// The catalog location with the key `key`, e.g. `AddressablesMainContentCatalogRemoteHash`
+    pub fn catalog_location(&self, key: &str) -> Option<&CatalogLocation> {
+        self.m_CatalogLocations
+            .iter()
+            .find(|location| location.m_Keys.iter().any(|k| k == key))
+    }
 }
 
 #[allow(non_snake_case)]
@@ -30,7 +37,9 @@ pub struct CatalogLocation {
     pub m_Keys: Vec<String>,
     pub m_InternalId: String,
     pub m_Provider: String,
-    pub m_Dependencies: Vec<()>,
+    /// Keys of other catalog locations. For content catalogs with remote updates, these are
+    /// the remote `.hash` file followed by the cached one.
+    pub m_Dependencies: Vec<String>,
     pub m_ResourceType: AssemblyClass,
 }
 
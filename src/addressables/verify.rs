@@ -0,0 +1,186 @@
+//! Check the addressables bundles on disk against the catalog, see [`AddressablesData::verify`].
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use rabex::files::SerializedFile;
+use rabex::files::bundlefile::{BundleFileReader, ExtractionConfig};
+use rabex::typetree::TypeTreeProvider;
+
+use crate::Environment;
+use crate::addressables::AddressablesData;
+use crate::addressables::catalog::{AssetBundleRequestOptions, resource_providers};
+use crate::addressables::placeholders::LocationKind;
+use crate::env::Data;
+use crate::handle::SerializedFileHandle;
+use crate::resolver::EnvResolver;
+use crate::unity::types::AssetBundle;
+use crate::utils;
+
+/// Result of [`AddressablesData::verify`]
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Number of bundles referenced by the catalogs and checked
+    pub checked: usize,
+    /// Bundles outside of the build folder, like remote bundles or ones with unresolvable placeholders, which were not checked
+    pub skipped: Vec<PathBuf>,
+    /// Problems found, sorted by bundle path
+    pub problems: Vec<(PathBuf, BundleProblem)>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleProblem {
+    /// The bundle doesn't exist
+    Missing,
+    /// The bundle could not be read or parsed
+    Unreadable(String),
+    /// The CRC32 of the uncompressed bundle contents differs from the catalog
+    CrcMismatch { expected: u32, actual: u32 },
+    /// The `AssetBundle.m_Name` differs from the catalog's bundle name
+    NameMismatch { expected: String, actual: String },
+}
+
+impl std::fmt::Display for BundleProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BundleProblem::Missing => write!(f, "missing"),
+            BundleProblem::Unreadable(error) => write!(f, "unreadable: {error}"),
+            BundleProblem::CrcMismatch { expected, actual } => {
+                write!(f, "CRC mismatch: expected {expected:08x}, got {actual:08x}")
+            }
+            BundleProblem::NameMismatch { expected, actual } => {
+                write!(f, "name mismatch: expected '{expected}', got '{actual}'")
+            }
+        }
+    }
+}
+
+impl AddressablesData {
+    /// Checks every bundle referenced by an `AssetBundleProvider` location in the catalogs.
+    ///
+    /// Bundles must exist, match the CRC from their `AssetBundleRequestOptions` (unless it is `0`, which
+    /// disables the check in unity as well), and contain an `AssetBundle` named like the options' `bundle_name`.
+    /// The `hash` is only used for caching downloaded bundles and can't be checked.
+    ///
+    /// Bundles are checked in parallel.
+    pub fn verify<R: EnvResolver, P: TypeTreeProvider + Sync>(
+        &self,
+        env: &Environment<R, P>,
+    ) -> Result<VerifyReport> {
+        let build_folder = self.build_folder();
+
+        let mut report = VerifyReport::default();
+        let mut bundles = BTreeMap::new();
+        for catalog in env.addressables_catalogs()? {
+            for location in catalog.locations_of_provider(resource_providers::ASSET_BUNDLE) {
+                let Some(options) = &location.data else {
+                    continue;
+                };
+                let path = match self.placeholders.classify(&location.internal_id) {
+                    LocationKind::Local(path) => path,
+                    LocationKind::Remote(url) => PathBuf::from(url),
+                    LocationKind::Unresolvable { internal_id, .. } => PathBuf::from(internal_id),
+                };
+                match path.strip_prefix(&build_folder) {
+                    Ok(bundle) => {
+                        bundles.entry(bundle.to_owned()).or_insert(options);
+                    }
+                    Err(_) => report.skipped.push(path),
+                }
+            }
+        }
+        report.skipped.sort();
+        report.skipped.dedup();
+        report.checked = bundles.len();
+
+        report.problems =
+            utils::par_fold_reduce(bundles, |acc: &mut Vec<_>, (bundle, options)| {
+                let path = build_folder.join(&bundle);
+                if let Some(problem) = verify_bundle(env, &path, options) {
+                    acc.push((bundle, problem));
+                }
+                Ok(())
+            })?;
+        report.problems.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(report)
+    }
+}
+
+fn verify_bundle<R: EnvResolver, P: TypeTreeProvider>(
+    env: &Environment<R, P>,
+    path: &Path,
+    options: &AssetBundleRequestOptions,
+) -> Option<BundleProblem> {
+    let data = match env.game_files.read_path(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Some(BundleProblem::Missing),
+        Err(e) => return Some(BundleProblem::Unreadable(e.to_string())),
+    };
+
+    let check = || -> Result<Option<BundleProblem>> {
+        let reader = BundleFileReader::from_reader(
+            Cursor::new(data),
+            &ExtractionConfig::default().with_fallback_unity_version(env.unity_version()?.clone()),
+        )?;
+
+        if options.crc != 0 {
+            let actual = bundle_crc(&reader)?;
+            if actual != options.crc {
+                return Ok(Some(BundleProblem::CrcMismatch {
+                    expected: options.crc,
+                    actual,
+                }));
+            }
+        }
+
+        let entry = reader
+            .main_serializedfile()
+            .context("no non-resource serializedfile in bundle")?;
+        let data = reader.read_at_entry(entry)?;
+        let mut file = SerializedFile::from_reader(&mut Cursor::new(data.as_slice()))?;
+        file.m_UnityVersion
+            .get_or_insert(env.unity_version()?.clone());
+        let asset_bundle = SerializedFileHandle::new(env, &file, &data)
+            .find_object_of::<AssetBundle>()?
+            .context("no AssetBundle object in bundle")?;
+        if asset_bundle.m_Name != *options.bundle_name {
+            return Ok(Some(BundleProblem::NameMismatch {
+                expected: options.bundle_name.to_string(),
+                actual: asset_bundle.m_Name,
+            }));
+        }
+
+        Ok(None)
+    };
+    check().unwrap_or_else(|e| Some(BundleProblem::Unreadable(format!("{e:#}"))))
+}
+
+/// CRC32 of the uncompressed data of the bundle, like `BuildPipeline` computes it.
+///
+/// That is the decompressed blocks in storage order, which is the order of the file offsets, not of the directory.
+/// Gaps between files are alignment padding and hashed as zeros.
+fn bundle_crc(reader: &BundleFileReader<Cursor<Data>>) -> Result<u32> {
+    let mut entries: Vec<_> = reader.files().into_iter().collect();
+    entries.sort_by_key(|entry| entry.offset);
+
+    let mut hasher = crc32fast::Hasher::new();
+    let mut position = 0;
+    for entry in entries {
+        let offset = entry.offset as u64;
+        if offset > position {
+            hasher.update(&vec![0; (offset - position) as usize]);
+        }
+        let data = reader.read_at_entry(entry)?;
+        hasher.update(&data);
+        position = position.max(offset + data.len() as u64);
+    }
+    Ok(hasher.finalize())
+}
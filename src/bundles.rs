@@ -0,0 +1,121 @@
+//! Lookup of assetbundles and the serialized files (CABs) they contain
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use rabex::UnityVersion;
+use rabex::files::bundlefile::{BundleFileReader, ExtractionConfig};
+use rustc_hash::FxHashMap;
+
+use crate::resolver::EnvResolver;
+
+/// Maps the files inside of assetbundles to the bundle containing them, and back.
+///
+/// Bundle paths are relative to the root the index was built for.
+#[derive(Debug, Default)]
+pub struct BundleIndex {
+    pub cab_to_bundle: FxHashMap<String, PathBuf>,
+    pub bundle_to_cab: FxHashMap<PathBuf, Vec<String>>,
+}
+
+impl BundleIndex {
+    /// Reads the headers of `files` in parallel, storing their paths relative to `root`.
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(skip_all))]
+    pub(crate) fn build<R: EnvResolver>(
+        env: &R,
+        files: Vec<PathBuf>,
+        root: &Path,
+        unity_version: &UnityVersion,
+    ) -> Result<BundleIndex> {
+        use rayon::prelude::*;
+
+        #[cfg(feature = "tracing-instrument")]
+        let parent_span = tracing::Span::current();
+
+        let (cab_to_bundle, mut bundle_to_cab) = files
+            .into_par_iter()
+            .try_fold(
+                <(FxHashMap<String, PathBuf>, FxHashMap<PathBuf, Vec<String>>)>::default,
+                |mut acc, path| -> Result<_> {
+                    #[cfg(feature = "tracing-instrument")]
+                    let _parent = parent_span.enter();
+
+                    let relative = path.strip_prefix(root).unwrap();
+
+                    let bundle_to_cab_files: &mut Vec<_> =
+                        acc.1.entry(relative.to_owned()).or_default();
+
+                    #[cfg(feature = "tracing-instrument")]
+                    let _span = tracing::info_span!("read_bundle").entered();
+
+                    // Only the header is read, so mmap'ing or reading the whole
+                    // file would be a waste.
+                    let reader = env.open_path(&path)?;
+                    let bundle = BundleFileReader::from_reader(
+                        reader,
+                        &ExtractionConfig::default()
+                            .with_fallback_unity_version(unity_version.clone()),
+                    )?;
+
+                    for file in bundle.files() {
+                        acc.0.insert(file.path.clone(), relative.to_owned());
+                        bundle_to_cab_files.push(file.path.clone());
+                    }
+
+                    Ok(acc)
+                },
+            )
+            .try_reduce(Default::default, |mut acc, item| {
+                acc.0.extend(item.0);
+                acc.1.extend(item.1);
+                Ok(acc)
+            })?;
+        bundle_to_cab.values_mut().for_each(|files| files.sort());
+
+        Ok(BundleIndex {
+            cab_to_bundle,
+            bundle_to_cab,
+        })
+    }
+
+    pub fn bundle_paths(&self) -> impl Iterator<Item = &Path> {
+        self.bundle_to_cab.keys().map(AsRef::as_ref)
+    }
+
+    /// The bundle containing the serialized file `cab`, e.g. `CAB-abcd`
+    pub fn bundle_of(&self, cab: &str) -> Option<&Path> {
+        self.cab_to_bundle.get(cab).map(AsRef::as_ref)
+    }
+}
+
+/// Checks for the signature of an assetbundle (`UnityFS`, or the legacy `UnityWeb`/`UnityRaw`).
+///
+/// Only the first 7 bytes are read.
+pub fn is_bundle(mut reader: impl Read) -> Result<bool, std::io::Error> {
+    let mut signature = [0; 7];
+    match reader.read_exact(&mut signature) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+        Err(e) => return Err(e),
+    }
+    Ok(matches!(&signature, b"UnityFS" | b"UnityWe" | b"UnityRa"))
+}
+
+/// Directories of the data directory which never contain assetbundles
+const NON_BUNDLE_DIRS: &[&str] = &["Managed", "Plugins", "MonoBleedingEdge", "il2cpp_data"];
+/// Extensions of files next to assetbundles, which can be skipped without reading them
+const NON_BUNDLE_EXTENSIONS: &[&str] = &[
+    "assets", "bank", "bin", "config", "dll", "hash", "info", "json", "manifest", "mp4", "pdb",
+    "png", "resource", "resS", "so", "txt", "wav", "xml",
+];
+
+/// Whether the file at `path` could be an assetbundle, judging only by its path.
+pub(crate) fn may_be_bundle(path: &Path) -> bool {
+    if NON_BUNDLE_DIRS.iter().any(|dir| path.starts_with(dir)) {
+        return false;
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => !NON_BUNDLE_EXTENSIONS.contains(&ext),
+        None => true,
+    }
+}
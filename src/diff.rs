@@ -0,0 +1,651 @@
+//! Object-level diff of two versions of a game.
+//!
+//! PathIds aren't stable across builds, so objects are matched by an [`ObjectKey`] derived from what they are:
+//! their [`ComponentPath`] in the scene hierarchy, their addressables key or container path,
+//! or their class and name. PPtrs in the compared values are replaced by the key of their target for the same reason.
+//!
+//! ```no_run
+//! # use rabex_env::Environment;
+//! # use rabex_env::diff::{self, ObjectChange};
+//! # fn run(old: &Environment, new: &Environment) -> anyhow::Result<()> {
+//! let diff = diff::diff_environments(old, new)?;
+//! for file in &diff.files {
+//!     for change in &file.changes {
+//!         if let ObjectChange::Changed { id, fields, .. } = change {
+//!             for field in fields {
+//!                 println!("{}: {id} {}: {:?} -> {:?}", file.file, field.path, field.old, field.new);
+//!             }
+//!         }
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rabex::objects::pptr::PathId;
+use rabex::objects::{ClassId, PPtr, TypedPPtr};
+use rabex::typetree::TypeTreeProvider;
+use rustc_hash::FxHashMap;
+use serde_json::Value;
+
+use crate::Environment;
+use crate::addressables::catalog::resource_providers;
+use crate::addressables::split_sub_object;
+use crate::component_path::{ComponentId, ComponentPath};
+use crate::handle::SerializedFileHandle;
+use crate::qualify::Qualifier;
+use crate::resolver::EnvResolver;
+use crate::unity::types::{AssetBundle, Named};
+use crate::utils;
+
+/// What identifies an object across versions, from most to least stable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ObjectKey {
+    /// A GameObject or a component in the scene hierarchy
+    Component(ComponentPath),
+    /// An asset loadable through addressables, by its primary key
+    Addressable { key: String, class: ClassId },
+    /// An asset in the `m_Container` of an `AssetBundle` or the `ResourceManager`, by its lowercase path
+    Container { path: String, class: ClassId },
+    /// Any other object with an `m_Name`
+    Named { name: String, class: ClassId },
+    /// Objects without anything better to match by
+    PathId { path_id: PathId, class: ClassId },
+}
+
+impl fmt::Display for ObjectKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let class_label = |class: &ClassId| ComponentId::Class(*class).label();
+        match self {
+            ObjectKey::Component(path) => write!(f, "{path}"),
+            ObjectKey::Addressable { key, class } => write!(f, "key:{key}@{}", class_label(class)),
+            ObjectKey::Container { path, class } => {
+                write!(f, "container:{path}@{}", class_label(class))
+            }
+            ObjectKey::Named { name, class } => write!(f, "{}:{name}", class_label(class)),
+            ObjectKey::PathId { path_id, class } => write!(f, "{}#{path_id}", class_label(class)),
+        }
+    }
+}
+
+/// An [`ObjectKey`] with the position among the objects of the file with the same key, in PathId order.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ObjectId {
+    pub key: ObjectKey,
+    pub index: usize,
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.key)?;
+        if self.index > 0 {
+            write!(f, "~{}", self.index)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectChange {
+    Added {
+        id: ObjectId,
+        path_id: PathId,
+    },
+    Removed {
+        id: ObjectId,
+        path_id: PathId,
+    },
+    Changed {
+        id: ObjectId,
+        old_path_id: PathId,
+        new_path_id: PathId,
+        fields: Vec<FieldChange>,
+    },
+}
+
+impl ObjectChange {
+    pub fn id(&self) -> &ObjectId {
+        match self {
+            ObjectChange::Added { id, .. }
+            | ObjectChange::Removed { id, .. }
+            | ObjectChange::Changed { id, .. } => id,
+        }
+    }
+}
+
+/// A changed value inside an object.
+///
+/// Fields which were added or removed have no `old` or `new` value.
+/// Objects which can't be read as a typetree are compared by their raw data, which is reported
+/// as a single change with an empty path and no values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    /// Like `m_LocalPosition.x` or `m_Children[2]`
+    pub path: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// Changes to the objects of a serialized file present in both versions.
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    /// Path relative to the game data directory, see [`diff_environments`]
+    pub file: String,
+    /// Sorted by [`ObjectId`] string
+    pub changes: Vec<ObjectChange>,
+}
+
+/// Result of [`diff_environments`]
+#[derive(Debug, Clone, Default)]
+pub struct GameDiff {
+    pub added_files: Vec<String>,
+    pub removed_files: Vec<String>,
+    /// Files with at least one changed object, sorted by path
+    pub files: Vec<FileDiff>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileSource {
+    Serialized,
+    Bundle,
+    AddressablesBundle,
+}
+
+/// Diffs all serialized files of two game versions in parallel.
+///
+/// Built-in files are matched by path, assetbundles by the path of the bundle, so only the main
+/// serialized file of each bundle is compared. Bundles whose file name changed between versions show up
+/// as an added and a removed file.
+pub fn diff_environments<R1, P1, R2, P2>(
+    old: &Environment<R1, P1>,
+    new: &Environment<R2, P2>,
+) -> Result<GameDiff>
+where
+    R1: EnvResolver,
+    P1: TypeTreeProvider + Sync,
+    R2: EnvResolver,
+    P2: TypeTreeProvider + Sync,
+{
+    let old_files = diffable_files(old)?;
+    let new_files = diffable_files(new)?;
+
+    let mut diff = GameDiff::default();
+    let mut common = Vec::new();
+    for (path, &source) in &old_files {
+        match new_files.get(path) {
+            Some(&new_source) if new_source == source => common.push((path.clone(), source)),
+            Some(_) => {
+                diff.removed_files.push(path.clone());
+                diff.added_files.push(path.clone());
+            }
+            None => diff.removed_files.push(path.clone()),
+        }
+    }
+    diff.added_files.extend(
+        new_files
+            .keys()
+            .filter(|path| !old_files.contains_key(*path))
+            .cloned(),
+    );
+    diff.added_files.sort();
+
+    let old_lookup = KeyLookup::build(old)?;
+    let new_lookup = KeyLookup::build(new)?;
+    diff.files = utils::par_fold_reduce(common, |acc: &mut Vec<_>, (path, source)| {
+        let old_file = load(old, &path, source)?;
+        let new_file = load(new, &path, source)?;
+        let changes = diff_files_with(&path, &old_file, &old_lookup, &new_file, &new_lookup)
+            .with_context(|| format!("Failed to diff {path}"))?;
+        if !changes.is_empty() {
+            acc.push(FileDiff {
+                file: path,
+                changes,
+            });
+        }
+        Ok(())
+    })?;
+    diff.files.sort_by(|a, b| a.file.cmp(&b.file));
+
+    Ok(diff)
+}
+
+/// Diffs the objects of two versions of the serialized file at `path`, relative to the data directory.
+///
+/// The path is only used to find the file's assets in the `ResourceManager`.
+pub fn diff_files<R1, P1, R2, P2>(
+    path: &str,
+    old: &SerializedFileHandle<'_, R1, P1>,
+    new: &SerializedFileHandle<'_, R2, P2>,
+) -> Result<Vec<ObjectChange>>
+where
+    R1: EnvResolver,
+    P1: TypeTreeProvider,
+    R2: EnvResolver,
+    P2: TypeTreeProvider,
+{
+    let old_lookup = KeyLookup::build(old.env)?;
+    let new_lookup = KeyLookup::build(new.env)?;
+    diff_files_with(path, old, &old_lookup, new, &new_lookup)
+}
+
+fn diff_files_with<R1, P1, R2, P2>(
+    path: &str,
+    old: &SerializedFileHandle<'_, R1, P1>,
+    old_lookup: &KeyLookup,
+    new: &SerializedFileHandle<'_, R2, P2>,
+    new_lookup: &KeyLookup,
+) -> Result<Vec<ObjectChange>>
+where
+    R1: EnvResolver,
+    P1: TypeTreeProvider,
+    R2: EnvResolver,
+    P2: TypeTreeProvider,
+{
+    let mut old = FileIndex::build(old, path, old_lookup)?;
+    let mut new = FileIndex::build(new, path, new_lookup)?;
+
+    let mut changes = Vec::new();
+    for (id, &old_path_id) in &old.path_ids {
+        let Some(&new_path_id) = new.path_ids.get(id) else {
+            changes.push(ObjectChange::Removed {
+                id: id.clone(),
+                path_id: old_path_id,
+            });
+            continue;
+        };
+
+        let fields = match (old.value(old_path_id), new.value(new_path_id)) {
+            (Some(old_value), Some(new_value)) => {
+                let mut fields = Vec::new();
+                diff_values(&mut String::new(), &old_value, &new_value, &mut fields);
+                fields
+            }
+            _ => match old.raw_data(old_path_id)? == new.raw_data(new_path_id)? {
+                true => Vec::new(),
+                false => vec![FieldChange {
+                    path: String::new(),
+                    old: None,
+                    new: None,
+                }],
+            },
+        };
+        if !fields.is_empty() {
+            changes.push(ObjectChange::Changed {
+                id: id.clone(),
+                old_path_id,
+                new_path_id,
+                fields,
+            });
+        }
+    }
+    for (id, &path_id) in &new.path_ids {
+        if !old.path_ids.contains_key(id) {
+            changes.push(ObjectChange::Added {
+                id: id.clone(),
+                path_id,
+            });
+        }
+    }
+
+    changes.sort_by_cached_key(|change| change.id().to_string());
+    Ok(changes)
+}
+
+/// Serialized files and bundles by their path relative to the data directory
+fn diffable_files<R: EnvResolver, P: TypeTreeProvider>(
+    env: &Environment<R, P>,
+) -> Result<BTreeMap<String, FileSource>> {
+    let mut files = BTreeMap::new();
+    for path in env.game_files.serialized_files()? {
+        files.insert(path.display().to_string(), FileSource::Serialized);
+    }
+    for path in env.bundle_paths()? {
+        files.insert(path.display().to_string(), FileSource::Bundle);
+    }
+    if let Some(addressables) = env.addressables()? {
+        let build_folder = addressables.build_folder();
+        for bundle in addressables.bundle_paths() {
+            files.insert(
+                build_folder.join(bundle).display().to_string(),
+                FileSource::AddressablesBundle,
+            );
+        }
+    }
+    Ok(files)
+}
+
+fn load<'a, R: EnvResolver, P: TypeTreeProvider>(
+    env: &'a Environment<R, P>,
+    path: &str,
+    source: FileSource,
+) -> Result<SerializedFileHandle<'a, R, P>> {
+    match source {
+        FileSource::Serialized => env.load_serialized(path),
+        FileSource::Bundle => env.load_bundle_content(path),
+        FileSource::AddressablesBundle => {
+            let build_folder = env
+                .addressables_build_folder()?
+                .context("no addressables settings found")?;
+            let bundle = Path::new(path)
+                .strip_prefix(&build_folder)
+                .context("addressables bundle outside of the build folder")?;
+            env.load_addressables_bundle_content(bundle)
+        }
+    }
+}
+
+/// Environment-wide lookups for [`ObjectKey`]s
+#[derive(Default)]
+struct KeyLookup {
+    /// Lowercase asset path to the smallest addressables primary key loading it
+    addressables: FxHashMap<String, String>,
+    /// `ResourceManager` container paths by file name and path id
+    resources: FxHashMap<(String, PathId), String>,
+}
+
+impl KeyLookup {
+    fn build<R: EnvResolver, P: TypeTreeProvider>(env: &Environment<R, P>) -> Result<Self> {
+        let mut lookup = KeyLookup::default();
+
+        if let Some(catalog) = env.addressables_catalog()? {
+            for location in catalog.locations_of_provider(resource_providers::BUNDLED_ASSET) {
+                let (path, _) = split_sub_object(&location.internal_id);
+                let key = lookup.addressables.entry(path.to_lowercase()).or_default();
+                if key.is_empty() || **location.primary_key < **key {
+                    *key = location.primary_key.to_string();
+                }
+            }
+        }
+
+        // Not every game has a ResourceManager, e.g. when everything is in bundles
+        if let Ok(resource_manager) = env.resource_manager() {
+            let ggm = env.globalgamemanagers()?;
+            for (path, pptr) in resource_manager.m_Container {
+                let Some(external) = pptr.file_identifier(ggm.file) else {
+                    continue;
+                };
+                let Some(file_name) = file_name(&external.pathName) else {
+                    continue;
+                };
+                lookup.resources.insert((file_name, pptr.m_PathID), path);
+            }
+        }
+
+        Ok(lookup)
+    }
+}
+
+fn file_name(path: &str) -> Option<String> {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// The [`ObjectId`]s of one file
+struct FileIndex<'a, R, P> {
+    file: SerializedFileHandle<'a, R, P>,
+    qualifier: Qualifier<'a, R, P>,
+    path_ids: FxHashMap<ObjectId, PathId>,
+    ids: FxHashMap<PathId, ObjectId>,
+}
+
+impl<'a, R: EnvResolver, P: TypeTreeProvider> FileIndex<'a, R, P> {
+    fn build(
+        file: &SerializedFileHandle<'a, R, P>,
+        path: &str,
+        lookup: &KeyLookup,
+    ) -> Result<Self> {
+        let mut qualifier = Qualifier::new(file);
+
+        let mut containers = FxHashMap::default();
+        if let Some(asset_bundle) = file.find_object_of::<AssetBundle>()? {
+            for (path, info) in asset_bundle.m_Container {
+                if info.asset.is_local() {
+                    containers.insert(info.asset.m_PathID, path.to_lowercase());
+                }
+            }
+        }
+        let own_name = file_name(path).unwrap_or_default();
+
+        let mut sorted: Vec<_> = file.file.objects().map(|object| object.m_PathID).collect();
+        sorted.sort_unstable();
+
+        let mut counts = FxHashMap::default();
+        let mut path_ids = FxHashMap::default();
+        let mut ids = FxHashMap::default();
+        for path_id in sorted {
+            let class = file.object_at::<()>(path_id)?.class_id();
+            let container = containers
+                .get(&path_id)
+                .or_else(|| lookup.resources.get(&(own_name.clone(), path_id)));
+
+            let key = if let Some(path) = qualifier.qualify_local(path_id) {
+                ObjectKey::Component(path)
+            } else if let Some(key) = container.and_then(|path| lookup.addressables.get(path)) {
+                ObjectKey::Addressable {
+                    key: key.clone(),
+                    class,
+                }
+            } else if let Some(path) = container {
+                ObjectKey::Container {
+                    path: path.clone(),
+                    class,
+                }
+            } else if let Some(name) = name_of(file, path_id) {
+                ObjectKey::Named { name, class }
+            } else {
+                ObjectKey::PathId { path_id, class }
+            };
+
+            let count = counts.entry(key.clone()).or_insert(0);
+            let id = ObjectId { key, index: *count };
+            *count += 1;
+            path_ids.insert(id.clone(), path_id);
+            ids.insert(path_id, id);
+        }
+
+        Ok(FileIndex {
+            file: file.reborrow(),
+            qualifier,
+            path_ids,
+            ids,
+        })
+    }
+
+    /// The object as JSON with normalized PPtrs, or `None` if it can't be read
+    fn value(&mut self, path_id: PathId) -> Option<Value> {
+        let mut value = self
+            .file
+            .object_at::<Value>(path_id)
+            .and_then(|object| object.read())
+            .ok()?;
+        normalize_pptrs(&mut value, &mut |pptr| self.pptr_label(pptr));
+        Some(value)
+    }
+
+    fn raw_data(&self, path_id: PathId) -> Result<&'a [u8]> {
+        Ok(self.file.object_at::<()>(path_id)?.data())
+    }
+
+    fn pptr_label(&mut self, pptr: PPtr) -> Value {
+        let Some(pptr) = pptr.optional() else {
+            return Value::Null;
+        };
+        if pptr.is_local() {
+            return Value::String(match self.ids.get(&pptr.m_PathID) {
+                Some(id) => id.to_string(),
+                None => format!("#{}", pptr.m_PathID),
+            });
+        }
+
+        let qualified = self.qualifier.qualify(pptr);
+        let target = match (qualified.path, qualified.name) {
+            (Some(path), _) => path.to_string(),
+            (None, Some(name)) => name,
+            (None, None) => format!("#{}", pptr.m_PathID),
+        };
+        Value::String(format!("{}:{target}", qualified.file.unwrap_or_default()))
+    }
+}
+
+fn name_of<R: EnvResolver, P: TypeTreeProvider>(
+    file: &SerializedFileHandle<'_, R, P>,
+    path_id: PathId,
+) -> Option<String> {
+    file.deref_read(TypedPPtr::<Named>::local(path_id))
+        .ok()
+        .map(|named| named.m_Name)
+        .filter(|name| !name.is_empty())
+}
+
+/// Replaces every `{ m_FileID, m_PathID }` object in `value` by `label(pptr)`
+fn normalize_pptrs(value: &mut Value, label: &mut impl FnMut(PPtr) -> Value) {
+    if let Some(pptr) = as_pptr(value) {
+        *value = label(pptr);
+        return;
+    }
+    match value {
+        Value::Object(fields) => {
+            for field in fields.values_mut() {
+                normalize_pptrs(field, label);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                normalize_pptrs(item, label);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn as_pptr(value: &Value) -> Option<PPtr> {
+    let Value::Object(fields) = value else {
+        return None;
+    };
+    if fields.len() != 2 || !fields.contains_key("m_FileID") || !fields.contains_key("m_PathID") {
+        return None;
+    }
+    serde_json::from_value(value.clone()).ok()
+}
+
+/// Appends the differences between `old` and `new` at `path` to `out`.
+///
+/// Arrays of plain values like vertex or pixel data are compared as a whole.
+fn diff_values(path: &mut String, old: &Value, new: &Value, out: &mut Vec<FieldChange>) {
+    let changed = |path: &str, old: Option<&Value>, new: Option<&Value>| FieldChange {
+        path: path.to_owned(),
+        old: old.cloned(),
+        new: new.cloned(),
+    };
+    let is_scalar = |value: &Value| !matches!(value, Value::Array(_) | Value::Object(_));
+
+    match (old, new) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            let added = new_fields
+                .keys()
+                .filter(|key| !old_fields.contains_key(*key));
+            for key in old_fields.keys().chain(added) {
+                let len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+                match (old_fields.get(key), new_fields.get(key)) {
+                    (Some(old), Some(new)) => diff_values(path, old, new, out),
+                    (old, new) => out.push(changed(path, old, new)),
+                }
+                path.truncate(len);
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items))
+            if !(old_items.iter().all(is_scalar) && new_items.iter().all(is_scalar)) =>
+        {
+            for i in 0..old_items.len().max(new_items.len()) {
+                let len = path.len();
+                path.push_str(&format!("[{i}]"));
+                match (old_items.get(i), new_items.get(i)) {
+                    (Some(old), Some(new)) => diff_values(path, old, new, out),
+                    (old, new) => out.push(changed(path, old, new)),
+                }
+                path.truncate(len);
+            }
+        }
+        _ if old != new => out.push(changed(path, Some(old), Some(new))),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn diff(old: Value, new: Value) -> Vec<(String, Option<Value>, Option<Value>)> {
+        let mut out = Vec::new();
+        diff_values(&mut String::new(), &old, &new, &mut out);
+        out.into_iter()
+            .map(|change| (change.path, change.old, change.new))
+            .collect()
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(
+            diff(
+                json!({ "m_Name": "a", "m_Pos": { "x": 1, "y": 2 }, "m_Removed": 0 }),
+                json!({ "m_Name": "a", "m_Pos": { "x": 1, "y": 3 }, "m_Added": [1] }),
+            ),
+            [
+                ("m_Pos.y".to_owned(), Some(json!(2)), Some(json!(3))),
+                ("m_Removed".to_owned(), Some(json!(0)), None),
+                ("m_Added".to_owned(), None, Some(json!([1]))),
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(
+            diff(
+                json!({ "data": [1, 2, 3], "items": [{ "a": 1 }] }),
+                json!({ "data": [1, 2, 4], "items": [{ "a": 2 }, { "a": 3 }] }),
+            ),
+            [
+                (
+                    "data".to_owned(),
+                    Some(json!([1, 2, 3])),
+                    Some(json!([1, 2, 4]))
+                ),
+                ("items[0].a".to_owned(), Some(json!(1)), Some(json!(2))),
+                ("items[1]".to_owned(), None, Some(json!({ "a": 3 }))),
+            ]
+        );
+    }
+
+    #[test]
+    fn pptrs() {
+        let mut value = json!({
+            "m_GameObject": { "m_FileID": 0, "m_PathID": 5 },
+            "m_Children": [{ "m_FileID": 0, "m_PathID": 0 }],
+            "m_Other": { "m_FileID": 0, "m_PathID": 1, "extra": 2 },
+        });
+        normalize_pptrs(&mut value, &mut |pptr| match pptr.optional() {
+            Some(pptr) => json!(format!("#{}", pptr.m_PathID)),
+            None => Value::Null,
+        });
+        assert_eq!(
+            value,
+            json!({
+                "m_GameObject": "#5",
+                "m_Children": [null],
+                "m_Other": { "m_FileID": 0, "m_PathID": 1, "extra": 2 },
+            })
+        );
+    }
+}
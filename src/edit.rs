@@ -0,0 +1,265 @@
+//! Write modified serialized files.
+//!
+//! ```no_run
+//! # use rabex_env::Environment;
+//! # use rabex_env::unity::types::GameObject;
+//! # fn run(env: &Environment) -> anyhow::Result<()> {
+//! let file = env.load_serialized("level1")?;
+//! let mut edit = file.edit()?;
+//!
+//! let go = file.object_at::<GameObject>(1)?;
+//! let mut data = go.read()?;
+//! data.m_IsActive = false;
+//! edit.replace(go.path_id(), &data)?;
+//! edit.delete(2);
+//!
+//! std::fs::write("level1", edit.write()?)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The new file is written by rabex's [`SerializedFileBuilder`], keeping the types, script types and
+//! metadata of the original file.
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result, bail};
+use rabex::files::serializedfile::builder::SerializedFileBuilder;
+use rabex::files::serializedfile::{FileIdentifier, ObjectInfo, build_common_offset_map};
+use rabex::objects::ClassId;
+use rabex::objects::pptr::{FileId, PathId};
+use rabex::serde_typetree;
+use rabex::tpk::TpkTypeTreeBlob;
+use rabex::typetree::TypeTreeProvider;
+use rabex::typetree::typetree_cache::sync::TypeTreeCache;
+use serde::Serialize;
+
+use crate::handle::SerializedFileHandle;
+use crate::resolver::{EnvResolver, GameFiles};
+
+/// A set of edits to a [`SerializedFile`](rabex::files::SerializedFile), created by [`SerializedFileHandle::edit`].
+///
+/// Objects can be replaced, deleted and added, either as raw bytes or as values serialized
+/// with the typetree of the object they replace or are modelled after.
+/// Nothing is modified until [`SerializedFileEdit::write`] produces the new file.
+pub struct SerializedFileEdit<'a, R = GameFiles, P = TypeTreeCache<TpkTypeTreeBlob>> {
+    file: SerializedFileHandle<'a, R, P>,
+    objects: BTreeMap<PathId, EditedObject<'a>>,
+    externals: Vec<FileIdentifier>,
+    next_path_id: PathId,
+}
+
+struct EditedObject<'a> {
+    /// The object info of `like`, written with the new path id.
+    info: ObjectInfo,
+    /// The original object whose type this object has.
+    like: PathId,
+    data: Cow<'a, [u8]>,
+}
+
+impl<'a, R, P> SerializedFileHandle<'a, R, P> {
+    /// Starts an editing session for this file.
+    pub fn edit(&self) -> Result<SerializedFileEdit<'a, R, P>> {
+        let objects = self
+            .file
+            .objects()
+            .map(|info| {
+                let start = info.m_Offset as usize;
+                let data = self
+                    .data
+                    .get(start..start + info.m_Size as usize)
+                    .with_context(|| {
+                        format!("Data of object {} is out of bounds", info.m_PathID)
+                    })?;
+                let object = EditedObject {
+                    info: info.clone(),
+                    like: info.m_PathID,
+                    data: Cow::Borrowed(data),
+                };
+                Ok((info.m_PathID, object))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        let next_path_id = objects.last_key_value().map_or(1, |(&id, _)| id + 1);
+
+        Ok(SerializedFileEdit {
+            file: self.reborrow(),
+            externals: self.file.m_Externals.clone(),
+            objects,
+            next_path_id,
+        })
+    }
+}
+
+impl<'a, R, P> SerializedFileEdit<'a, R, P> {
+    pub fn contains(&self, path_id: PathId) -> bool {
+        self.objects.contains_key(&path_id)
+    }
+
+    /// Path ids of all objects after the edits so far, in ascending order.
+    pub fn path_ids(&self) -> impl Iterator<Item = PathId> + '_ {
+        self.objects.keys().copied()
+    }
+
+    /// Replaces the data of an existing object.
+    pub fn replace_raw(&mut self, path_id: PathId, data: Vec<u8>) -> Result<()> {
+        let Some(object) = self.objects.get_mut(&path_id) else {
+            bail!("Cannot replace nonexistent object {path_id}");
+        };
+        object.data = Cow::Owned(data);
+        Ok(())
+    }
+
+    /// Replaces the data of multiple objects, e.g. the `replacements` computed by [`crate::reachable::prune`].
+    pub fn replace_all_raw(
+        &mut self,
+        replacements: impl IntoIterator<Item = (PathId, Vec<u8>)>,
+    ) -> Result<()> {
+        for (path_id, data) in replacements {
+            self.replace_raw(path_id, data)?;
+        }
+        Ok(())
+    }
+
+    /// Removes an object. Returns whether it existed.
+    ///
+    /// References to the object are left as they are.
+    pub fn delete(&mut self, path_id: PathId) -> bool {
+        self.objects.remove(&path_id).is_some()
+    }
+
+    /// Keeps only the objects for which `f` returns true.
+    pub fn retain(&mut self, mut f: impl FnMut(PathId) -> bool) {
+        self.objects.retain(|&path_id, _| f(path_id));
+    }
+
+    /// Adds a new object with the same type as the existing object `like`, returning its path id.
+    pub fn add_raw(&mut self, like: PathId, data: Vec<u8>) -> Result<PathId> {
+        let path_id = self.next_path_id;
+        self.add_raw_at(path_id, like, data)?;
+        Ok(path_id)
+    }
+
+    /// Adds a new object at `path_id` with the same type as the existing object `like`.
+    pub fn add_raw_at(&mut self, path_id: PathId, like: PathId, data: Vec<u8>) -> Result<()> {
+        if self.objects.contains_key(&path_id) {
+            bail!("Object {path_id} already exists");
+        }
+        let template = self.template(like)?;
+        let object = EditedObject {
+            info: template.clone(),
+            like,
+            data: Cow::Owned(data),
+        };
+        self.objects.insert(path_id, object);
+        self.next_path_id = self.next_path_id.max(path_id + 1);
+        Ok(())
+    }
+
+    /// Adds `path_name` to the externals if it isn't referenced yet, and returns its file id for use in a [`PPtr`](rabex::objects::PPtr).
+    pub fn add_external(&mut self, path_name: &str) -> FileId {
+        let index = match self
+            .externals
+            .iter()
+            .position(|external| external.pathName == path_name)
+        {
+            Some(index) => index,
+            None => {
+                self.externals
+                    .push(FileIdentifier::new(path_name.to_owned()));
+                self.externals.len() - 1
+            }
+        };
+        FileId::from(index as i32 + 1)
+    }
+
+    fn template(&self, like: PathId) -> Result<&ObjectInfo> {
+        self.file
+            .file
+            .objects()
+            .find(|info| info.m_PathID == like)
+            .with_context(|| format!("Template object {like} does not exist in the original file"))
+    }
+}
+
+impl<'a, R> SerializedFileEdit<'a, R> {
+    /// Produces the bytes of the modified serialized file.
+    pub fn write(&self) -> Result<Vec<u8>> {
+        let file = self.file.file;
+        let unity_version = file
+            .m_UnityVersion
+            .as_ref()
+            .context("Serialized file has no unity version")?;
+        // rabex can't write the types of managed references yet
+        if file
+            .m_RefTypes
+            .as_ref()
+            .is_some_and(|ref_types| !ref_types.is_empty())
+        {
+            bail!("Writing files with managed reference types is not supported");
+        }
+        let tpk = &self.file.env.tpk;
+        let common_offset_map = build_common_offset_map(&tpk.inner, unity_version);
+
+        let mut builder = SerializedFileBuilder::from_serialized(
+            unity_version,
+            file,
+            self.file.data,
+            tpk,
+            &common_offset_map,
+            std::iter::empty(),
+        );
+        builder.serialized.m_Externals = self.externals.clone();
+        builder.objects = self
+            .objects
+            .iter()
+            .map(|(&path_id, object)| {
+                let info = ObjectInfo {
+                    m_PathID: path_id,
+                    ..object.info.clone()
+                };
+                (path_id, (info, Cow::Borrowed(object.data.as_ref())))
+            })
+            .collect();
+
+        Ok(builder.write_vec()?)
+    }
+}
+
+impl<'a, R: EnvResolver, P: TypeTreeProvider> SerializedFileEdit<'a, R, P> {
+    /// Replaces an existing object by `value`, serialized with the object's typetree.
+    pub fn replace<T: Serialize>(&mut self, path_id: PathId, value: &T) -> Result<()> {
+        let like = match self.objects.get(&path_id) {
+            Some(object) => object.like,
+            None => bail!("Cannot replace nonexistent object {path_id}"),
+        };
+        let data = self.serialize(like, value)?;
+        self.replace_raw(path_id, data)
+    }
+
+    /// Adds `value` as a new object with the same type as the existing object `like`, returning its path id.
+    pub fn add<T: Serialize>(&mut self, like: PathId, value: &T) -> Result<PathId> {
+        let data = self.serialize(like, value)?;
+        self.add_raw(like, data)
+    }
+
+    fn serialize<T: Serialize>(&self, like: PathId, value: &T) -> Result<Vec<u8>> {
+        let endianness = self.file.file.m_Header.m_Endianess;
+        let object = self.file.object_at::<()>(like)?;
+        let tt = object.typetree()?;
+
+        // Like `ObjectRefHandle::read`, use the full typetree of the script instead of the bare header
+        if object.class_id() == ClassId::MonoBehaviour && tt.m_Type == "MonoBehaviour" {
+            let script = object
+                .mono_script()?
+                .with_context(|| format!("MonoBehaviour {like} has no MonoScript"))?;
+            let tt = self
+                .file
+                .env
+                .generate_typetree(&script.assembly_name(), &script.full_name())?
+                .with_context(|| format!("no type tree for {}", script.full_name()))?;
+            return Ok(serde_typetree::to_vec_endianed(value, tt, endianness)?);
+        }
+
+        Ok(serde_typetree::to_vec_endianed(value, tt, endianness)?)
+    }
+}
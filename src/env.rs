@@ -1,13 +1,15 @@
 //! Home for the [`Environment`] abstraction and associated types.
-use std::collections::BTreeMap;
+mod cache;
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
 use std::io::{Cursor, Read, Seek};
-use std::ops::Deref;
+use std::ops::{Deref, Range};
 use std::path::{Path, PathBuf};
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 
 use anyhow::{Context, Result};
-use elsa::sync::FrozenMap;
 use rabex::UnityVersion;
 use rabex::files::SerializedFile;
 use rabex::files::bundlefile::{BundleFileReader, ExtractionConfig};
@@ -16,23 +18,34 @@ use rabex::tpk::TpkTypeTreeBlob;
 use rabex::typetree::typetree_cache::sync::TypeTreeCache;
 use rabex::typetree::{TypeTreeNode, TypeTreeProvider};
 
+use self::cache::SerializedFileCache;
+use crate::addressables::catalog::{AddressablesCatalog, resource_providers};
 use crate::addressables::settings::AddressablesSettings;
-use crate::addressables::{AddressablesData, ArchivePath};
-use crate::handle::SerializedFileHandle;
+use crate::addressables::{AddressablesData, ArchivePath, split_sub_object};
+use crate::bundles::{BundleIndex, is_bundle, may_be_bundle};
+use crate::handle::{ObjectRefHandle, SerializedFileHandle};
 use crate::resolver::{EnvResolver, GameFiles};
+use crate::scan;
+use crate::trace_pptr::ManagedReferenceResolver;
 use crate::typetree_generator_cache::TypeTreeGeneratorCache;
-use crate::unity::types::{BuildSettings, MonoManager, MonoScript, ResourceManager};
+use crate::unity::types::{
+    AssetBundle, BuildSettings, MonoManager, MonoScript, Named, ResourceManager,
+};
+use crate::utils;
 
-/// Owned or mmap-backed bytes
+/// Owned, mmap-backed or shared bytes
 pub enum Data {
     InMemory(Vec<u8>),
     Mmap(memmap2::Mmap),
+    /// A range of a buffer shared between files, e.g. an entry of an archive held in memory
+    Shared(Arc<Vec<u8>>, Range<usize>),
 }
 impl AsRef<[u8]> for Data {
     fn as_ref(&self) -> &[u8] {
         match self {
             Data::InMemory(data) => data.as_slice(),
             Data::Mmap(mmap) => mmap.as_ref(),
+            Data::Shared(data, range) => &data[range.clone()],
         }
     }
 }
@@ -92,13 +105,22 @@ impl From<Vec<u8>> for Data {
 ///     Ok(())
 /// }
 /// ```
+///
+/// # Memory
+/// Every loaded serialized file is cached for the lifetime of the environment.
+/// For batch jobs over a whole game, set a [cache budget](Self::set_cache_budget)
+/// and call [`Environment::trim_cache`] between units of work.
+/// Eviction requires `&mut self`, so it can never invalidate a live [`SerializedFileHandle`].
 pub struct Environment<R = GameFiles, P = TypeTreeCache<TpkTypeTreeBlob>> {
     pub game_files: R,
     pub tpk: P,
     pub typetree_generator: TypeTreeGeneratorCache,
-    serialized_files: FrozenMap<PathBuf, Box<(SerializedFile, Data)>>,
+    serialized_files: SerializedFileCache,
     unity_version: OnceLock<UnityVersion>,
     addressables: OnceLock<Option<AddressablesData>>,
+    addressables_catalogs: OnceLock<Vec<AddressablesCatalog>>,
+    addressables_catalog: OnceLock<AddressablesCatalog>,
+    bundles: OnceLock<BundleIndex>,
 }
 
 impl<R: Debug, P> std::fmt::Debug for Environment<R, P> {
@@ -124,6 +146,9 @@ impl<R, P> Environment<R, P> {
             typetree_generator: TypeTreeGeneratorCache::empty(),
             unity_version: OnceLock::new(),
             addressables: OnceLock::new(),
+            addressables_catalogs: OnceLock::new(),
+            addressables_catalog: OnceLock::new(),
+            bundles: OnceLock::new(),
         }
     }
 }
@@ -147,6 +172,9 @@ impl<P: TypeTreeProvider> Environment<GameFiles, P> {
             typetree_generator: TypeTreeGeneratorCache::empty(),
             unity_version: OnceLock::new(),
             addressables: OnceLock::new(),
+            addressables_catalogs: OnceLock::new(),
+            addressables_catalog: OnceLock::new(),
+            bundles: OnceLock::new(),
         })
     }
 }
@@ -261,7 +289,7 @@ impl<R: EnvResolver, P: TypeTreeProvider> Environment<R, P> {
         file: SerializedFile,
         data: Data,
     ) -> SerializedFileHandle<'_, R, P> {
-        let file = self.serialized_files.insert(path, Box::new((file, data)));
+        let file = self.serialized_files.insert(path, file, data);
         SerializedFileHandle::new(self, &file.0, file.1.as_ref())
     }
 
@@ -281,14 +309,19 @@ impl<R: EnvResolver, P: TypeTreeProvider> Environment<R, P> {
             },
             None => {
                 if let Some(cab) = ArchivePath::try_parse(path_name)? {
-                    let aa = self.addressables()?.context(
-                        "Can't use archive:/ external files without addressables in the game",
-                    )?;
-                    let cab_bundle = aa
-                        .cab_to_bundle
-                        .get(cab.bundle)
-                        .with_context(|| format!("CAB {} doesn't exist", cab))?;
-                    let bundle = self.load_addressables_bundle(cab_bundle)?;
+                    let addressables_bundle = self
+                        .addressables()?
+                        .and_then(|aa| aa.cab_to_bundle.get(cab.bundle));
+                    let bundle = match addressables_bundle {
+                        Some(bundle) => self.load_addressables_bundle(bundle)?,
+                        None => {
+                            let bundle = self
+                                .bundle_index()?
+                                .bundle_of(cab.bundle)
+                                .with_context(|| format!("CAB {} doesn't exist", cab))?;
+                            self.load_bundle(bundle)?
+                        }
+                    };
                     let cab_data = bundle
                         .read_at(cab.file)?
                         .expect("cab unexpectedly not present");
@@ -300,7 +333,8 @@ impl<R: EnvResolver, P: TypeTreeProvider> Environment<R, P> {
                         .get_or_insert(self.unity_version()?.clone());
                     let file = self.serialized_files.insert(
                         path_name.to_owned(),
-                        Box::new((serialized, Data::InMemory(cab_data))),
+                        serialized,
+                        Data::InMemory(cab_data),
                     );
                     return Ok(SerializedFileHandle::new(self, &file.0, file.1.as_ref()));
                 }
@@ -314,7 +348,7 @@ impl<R: EnvResolver, P: TypeTreeProvider> Environment<R, P> {
                 let serialized = SerializedFile::from_reader(&mut Cursor::new(data.as_ref()))?;
                 let file = self
                     .serialized_files
-                    .insert(path_name.to_owned(), Box::new((serialized, data)));
+                    .insert(path_name.to_owned(), serialized, data);
                 SerializedFileHandle::new(self, &file.0, file.1.as_ref())
             }
         })
@@ -354,50 +388,232 @@ impl<R: EnvResolver, P: TypeTreeProvider> Environment<R, P> {
     }
 
     pub fn loaded_files(&mut self) -> impl Iterator<Item = &Path> {
-        self.serialized_files.as_mut().keys().map(Deref::deref)
+        self.serialized_files.paths().map(Deref::deref)
+    }
+}
+
+/// # Cache
+impl<R, P> Environment<R, P> {
+    /// Total size in bytes of the serialized files currently held in the cache.
+    pub fn cache_size(&self) -> usize {
+        self.serialized_files.total_bytes()
+    }
+
+    /// The byte budget set by [`Environment::set_cache_budget`], if any.
+    pub fn cache_budget(&self) -> Option<usize> {
+        self.serialized_files.budget()
+    }
+
+    /// Sets the number of bytes of serialized file data the cache should hold on to.
+    ///
+    /// The budget is only enforced in [`Environment::trim_cache`]. Loading files never fails because of it.
+    pub fn set_cache_budget(&mut self, budget: Option<usize>) {
+        self.serialized_files.set_budget(budget);
+    }
+
+    /// Builder-style version of [`Environment::set_cache_budget`].
+    pub fn with_cache_budget(mut self, budget: usize) -> Self {
+        self.set_cache_budget(Some(budget));
+        self
+    }
+
+    /// Evicts the least recently used serialized files until the cache fits into the budget.
+    ///
+    /// Returns the number of evicted files.
+    pub fn trim_cache(&mut self) -> usize {
+        self.serialized_files.trim()
+    }
+
+    /// Removes a single file from the cache, returning whether it was loaded.
+    pub fn evict(&mut self, path: impl AsRef<Path>) -> bool {
+        self.serialized_files.remove(path.as_ref())
+    }
+
+    /// Drops all cached serialized files.
+    pub fn clear_cache(&mut self) {
+        self.serialized_files.clear();
     }
 }
 
 impl<R: EnvResolver, P: TypeTreeProvider + Sync> Environment<R, P> {
-    // TODO: non-addressables
+    /// Loads every serialized file of the game in parallel.
+    ///
+    /// This includes the built-in files (`levelN`, `sharedassetsN.assets`, `resources.assets`, `globalgamemanagers`, ...),
+    /// the contents of plain assetbundles in `StreamingAssets` and of all addressables bundles.
+    ///
+    /// Built-in files are keyed by their path relative to the data directory, bundle contents by their
+    /// [`ArchivePath`], matching how they are referenced in `m_Externals`.
     pub fn load_all_serialized_files(
         &self,
     ) -> Result<BTreeMap<String, SerializedFileHandle<'_, R, P>>> {
-        let Some(addressables) = self.addressables()? else {
-            return Ok(Default::default());
+        let builtin = self.game_files.serialized_files()?;
+        let mut all = utils::par_fold_reduce(builtin, |acc: &mut Vec<_>, path: PathBuf| {
+            let file = self
+                .load_serialized(&path)
+                .with_context(|| format!("Failed to load {}", path.display()))?;
+            acc.push((path.display().to_string(), file));
+            Ok(())
+        })?;
+
+        // every bundle is read in full anyway, so the index would only read their headers twice
+        let bundles = self.bundle_paths()?;
+        all.extend(utils::par_fold_reduce(
+            bundles,
+            |acc: &mut Vec<_>, path| {
+                let bundle = self.load_bundle(path)?;
+                self.insert_bundle_files(&bundle, acc)
+            },
+        )?);
+
+        if let Some(addressables) = self.addressables()? {
+            let bundles = addressables.bundle_paths().collect::<Vec<_>>();
+            all.extend(utils::par_fold_reduce(
+                bundles,
+                |acc: &mut Vec<_>, bundle_path| {
+                    let bundle = self.load_addressables_bundle(bundle_path)?;
+                    self.insert_bundle_files(&bundle, acc)
+                },
+            )?);
+        }
+
+        Ok(all.into_iter().collect())
+    }
+
+    fn insert_bundle_files<'a>(
+        &'a self,
+        bundle: &BundleFileReader<Cursor<Data>>,
+        acc: &mut Vec<(String, SerializedFileHandle<'a, R, P>)>,
+    ) -> Result<()> {
+        let Some(bundle_identifier) = bundle
+            .serialized_files()
+            .map(|file| &file.path)
+            .find(|path| scan::is_bundle_identifier(path))
+        else {
+            return Ok(());
         };
 
-        use rayon::iter::{ParallelBridge as _, ParallelIterator as _};
-
-        addressables
-            .bundle_paths()
-            .par_bridge()
-            .try_fold(BTreeMap::default, |mut acc, bundle_path| {
-                let bundle = self.load_addressables_bundle(bundle_path)?;
-                let bundle_identifier = bundle
-                    .serialized_files()
-                    .find_map(|file| match Path::new(&file.path).extension().is_some() {
-                        true => None,
-                        false => Some(&file.path),
-                    })
-                    .unwrap();
-
-                for entry in bundle.serialized_files() {
-                    let archive_path = ArchivePath::new(bundle_identifier, &entry.path);
-                    let data = bundle.read_at_entry(entry)?;
-                    let file = SerializedFile::from_reader(&mut Cursor::new(data.as_slice()))?;
-                    let file = self.insert_cache(archive_path.into(), file, data.into());
-
-                    acc.insert(archive_path.to_string(), file);
-                }
-                Ok(acc)
-            })
-            .try_reduce(Default::default, |mut acc, item| {
-                for other in item {
-                    acc.insert(other.0, other.1);
-                }
-                Ok(acc)
-            })
+        for entry in bundle.serialized_files() {
+            let archive_path = ArchivePath::new(bundle_identifier, &entry.path);
+            let data = bundle.read_at_entry(entry)?;
+            let mut file = SerializedFile::from_reader(&mut Cursor::new(data.as_slice()))?;
+            file.m_UnityVersion
+                .get_or_insert(self.unity_version()?.clone());
+            let file = self.insert_cache(archive_path.into(), file, data.into());
+
+            acc.push((archive_path.to_string(), file));
+        }
+        Ok(())
+    }
+}
+
+/// # Assetbundles
+impl<R: EnvResolver, P: TypeTreeProvider> Environment<R, P> {
+    /// Opens the assetbundle at `path`, relative to the game data directory.
+    pub fn load_bundle(&self, path: impl AsRef<Path>) -> Result<BundleFileReader<Cursor<Data>>> {
+        let path = path.as_ref();
+        let data = self
+            .game_files
+            .read_path(path)
+            .with_context(|| format!("read bundle {}", path.display()))?;
+        let reader = BundleFileReader::from_reader(
+            Cursor::new(data),
+            &ExtractionConfig::default().with_fallback_unity_version(self.unity_version()?.clone()),
+        )?;
+        Ok(reader)
+    }
+
+    /// Loads the main `SerializedFile` from the assetbundle at `path`, relative to the game data directory.
+    ///
+    /// The file is cached under its [`ArchivePath`], so PPtrs from other bundles into it are resolved as well.
+    pub fn load_bundle_content(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<SerializedFileHandle<'_, R, P>> {
+        let path = path.as_ref();
+
+        // Avoid building the whole index just to find the cache key
+        let known_identifier = self
+            .bundles
+            .get()
+            .and_then(|index| index.bundle_to_cab.get(path))
+            .and_then(|files| files.iter().find(|file| scan::is_bundle_identifier(file)));
+        if let Some(identifier) = known_identifier
+            && let Some(cached) = self
+                .serialized_files
+                .get(Path::new(&ArchivePath::same(identifier).to_string()))
+        {
+            return Ok(SerializedFileHandle::new(
+                self,
+                &cached.0,
+                cached.1.as_ref(),
+            ));
+        }
+
+        let bundle = self
+            .load_bundle(path)
+            .with_context(|| format!("Failed to load bundle '{}'", path.display()))?;
+        let entry = bundle
+            .main_serializedfile()
+            .context("no non-resource serializedfile in bundle")?;
+        let data = bundle.read_at(&entry.path)?.unwrap();
+        let mut file = SerializedFile::from_reader(&mut Cursor::new(data.as_slice()))?;
+        file.m_UnityVersion
+            .get_or_insert(self.unity_version()?.clone());
+
+        let archive_path = ArchivePath::same(&entry.path);
+        Ok(self.insert_cache(archive_path.into(), file, Data::InMemory(data)))
+    }
+
+    /// Paths of all assetbundles in the game which are not managed by addressables.
+    ///
+    /// Files are recognized by their `UnityFS` signature, not by their extension.
+    /// Only the first bytes of files which could be bundles are read, skipping e.g. assemblies and built-in serialized files.
+    pub fn bundle_paths(&self) -> Result<Vec<PathBuf>> {
+        let files = match self.game_files.list_under(Path::new("")) {
+            Ok(files) => files,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let builtin: BTreeSet<_> = self.game_files.serialized_files()?.into_iter().collect();
+        let aa_build = self.addressables_build_folder()?;
+
+        let mut bundles = Vec::new();
+        for path in files {
+            if path == Path::new("data.unity3d")
+                || builtin.contains(&path)
+                || aa_build
+                    .as_ref()
+                    .is_some_and(|build| path.starts_with(build))
+                || !may_be_bundle(&path)
+            {
+                continue;
+            }
+            if is_bundle(self.game_files.open_path(&path)?)? {
+                bundles.push(path);
+            }
+        }
+        Ok(bundles)
+    }
+
+    /// Index of all assetbundles in the game which are not managed by addressables, see [`Environment::bundle_paths`].
+    ///
+    /// The index is built on first use, reading the header of every bundle.
+    pub fn bundle_index(&self) -> Result<&BundleIndex> {
+        if let Some(index) = self.bundles.get() {
+            return Ok(index);
+        }
+
+        let bundles = self.bundle_paths()?;
+        let index = match bundles.is_empty() {
+            true => BundleIndex::default(),
+            false => BundleIndex::build(
+                &self.game_files,
+                bundles,
+                Path::new(""),
+                self.unity_version()?,
+            )?,
+        };
+        Ok(self.bundles.get_or_init(|| index))
     }
 }
 
@@ -413,16 +629,7 @@ impl<R: EnvResolver, P: TypeTreeProvider> Environment<R, P> {
         let aa_build = self
             .addressables_build_folder()?
             .context("no addressables settings found")?;
-        let bundle_path = aa_build.join(bundle);
-        let data = self
-            .game_files
-            .read_path(&bundle_path)
-            .with_context(|| format!("read bundle {}", bundle_path.display()))?;
-        let reader = BundleFileReader::from_reader(
-            Cursor::new(data),
-            &ExtractionConfig::default().with_fallback_unity_version(self.unity_version()?.clone()),
-        )?;
-        Ok(reader)
+        self.load_bundle(aa_build.join(bundle))
     }
 
     /// Loads the main `SerializedFile` from an assetbundle.
@@ -516,6 +723,96 @@ impl<R: EnvResolver, P: TypeTreeProvider> Environment<R, P> {
             }
         }
     }
+
+    /// Mutable access to the addressables data, e.g. to set placeholder overrides with
+    /// [`PlaceholderEvaluator::set`](crate::addressables::placeholders::PlaceholderEvaluator::set)
+    /// or a [`catalog_fetcher`](AddressablesData::catalog_fetcher) for remote catalogs.
+    ///
+    /// Catalogs which were already loaded are discarded, and loaded again with the new settings.
+    pub fn addressables_mut(&mut self) -> Result<Option<&mut AddressablesData>> {
+        self.addressables()?;
+        self.addressables_catalogs.take();
+        self.addressables_catalog.take();
+        Ok(self.addressables.get_mut().and_then(Option::as_mut))
+    }
+
+    /// The parsed addressables catalogs. Empty if the game doesn't use addressables.
+    pub fn addressables_catalogs(&self) -> Result<&[AddressablesCatalog]> {
+        if let Some(catalogs) = self.addressables_catalogs.get() {
+            return Ok(catalogs);
+        }
+        let catalogs = match self.addressables()? {
+            Some(addressables) => addressables.catalogs(self)?,
+            None => Vec::new(),
+        };
+        Ok(self.addressables_catalogs.get_or_init(|| catalogs))
+    }
+
+    /// All addressables catalogs merged into one key space, see [`AddressablesCatalog::merge`].
+    ///
+    /// `None` if the game doesn't use addressables or has no loadable catalog.
+    pub fn addressables_catalog(&self) -> Result<Option<&AddressablesCatalog>> {
+        if let Some(catalog) = self.addressables_catalog.get() {
+            return Ok(Some(catalog));
+        }
+        let mut catalogs = self.addressables_catalogs()?.iter();
+        let Some(first) = catalogs.next() else {
+            return Ok(None);
+        };
+        let mut merged = first.clone();
+        for catalog in catalogs {
+            merged.merge(catalog.clone());
+        }
+        Ok(Some(self.addressables_catalog.get_or_init(|| merged)))
+    }
+
+    /// Loads the asset with the addressables key `key`, like `Addressables.LoadAssetAsync<T>(key)`.
+    ///
+    /// The key's `BundledAssetProvider` location determines the bundle, whose `AssetBundle.m_Container`
+    /// then points at the object. Keys of sub-objects like `Assets/atlas.png[icon]` select the object by name.
+    pub fn load_addressable<T>(&self, key: &str) -> Result<ObjectRefHandle<'_, T, R, P>>
+    where
+        T: for<'de> serde::Deserialize<'de>,
+    {
+        let addressables = self
+            .addressables()?
+            .context("no addressables settings found")?;
+
+        let location = self
+            .addressables_catalog()?
+            .and_then(|catalog| catalog.resources.get(&key.to_owned()))
+            .into_iter()
+            .flatten()
+            .find(|location| *location.provider_id == resource_providers::BUNDLED_ASSET)
+            .with_context(|| format!("No asset with the addressables key '{key}'"))?;
+        let bundle = addressables
+            .location_bundle(location)
+            .with_context(|| format!("Location '{}' has no bundle", location.internal_id))?;
+
+        let file = self.load_addressables_bundle_content(&bundle)?;
+        let asset_bundle = file
+            .find_object_of::<AssetBundle>()?
+            .with_context(|| format!("No AssetBundle object in '{}'", bundle.display()))?;
+
+        let (path, sub_object) = split_sub_object(&location.internal_id);
+        let mut entries = asset_bundle.container_entries(path);
+        let entry = match sub_object {
+            None => entries.next(),
+            Some(name) => entries.find(|entry| {
+                file.deref_read(entry.asset.typed::<Named>())
+                    .is_ok_and(|named| named.m_Name == name)
+            }),
+        }
+        .with_context(|| {
+            format!(
+                "'{}' is not in the container of '{}'",
+                location.internal_id,
+                bundle.display()
+            )
+        })?;
+
+        file.deref(entry.asset.typed())
+    }
 }
 
 impl<R: EnvResolver, P: TypeTreeProvider> Environment<R, P> {
@@ -531,4 +828,35 @@ impl<R: EnvResolver, P: TypeTreeProvider> Environment<R, P> {
             .backend(self)?
             .generate(assembly, full_name)
     }
+
+    /// Generate the type tree of a plain serializable class, as stored in `[SerializeReference]` fields.
+    /// Unlike [`Environment::generate_typetree`], this doesn't include the MonoBehaviour header.
+    pub fn generate_managed_reference_typetree(
+        &self,
+        assembly: &str,
+        full_name: &str,
+    ) -> Result<Option<&TypeTreeNode>> {
+        self.typetree_generator
+            .backend(self)?
+            .generate_managed_reference(assembly, full_name)
+    }
+}
+
+impl<R: EnvResolver, P: TypeTreeProvider> ManagedReferenceResolver for Environment<R, P> {
+    fn resolve(
+        &self,
+        assembly: &str,
+        namespace: &str,
+        class: &str,
+    ) -> Result<Option<&TypeTreeNode>> {
+        let assembly = match assembly.ends_with(".dll") {
+            true => Cow::Borrowed(assembly),
+            false => Cow::Owned(format!("{assembly}.dll")),
+        };
+        let full_name = match namespace.is_empty() {
+            true => Cow::Borrowed(class),
+            false => Cow::Owned(format!("{namespace}.{class}")),
+        };
+        self.generate_managed_reference_typetree(&assembly, &full_name)
+    }
 }
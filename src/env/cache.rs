@@ -0,0 +1,136 @@
+//! Bookkeeping for the loaded [`SerializedFile`]s of an [`Environment`](super::Environment).
+//!
+//! Handles borrow from the cache for as long as the environment is borrowed,
+//! so entries can never be dropped behind a live handle.
+//! Instead, eviction only happens when the environment is borrowed mutably,
+//! which makes every `&mut` point a generation boundary for batch jobs.
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use elsa::sync::FrozenMap;
+use rabex::files::SerializedFile;
+use rustc_hash::FxHashMap;
+
+use super::Data;
+
+struct Entry {
+    size: usize,
+    last_access: u64,
+}
+
+#[derive(Default)]
+struct Usage {
+    entries: FxHashMap<PathBuf, Entry>,
+    total_bytes: usize,
+}
+
+#[derive(Default)]
+pub(super) struct SerializedFileCache {
+    files: FrozenMap<PathBuf, Box<(SerializedFile, Data)>>,
+    usage: Mutex<Usage>,
+    clock: AtomicU64,
+    budget: Option<usize>,
+}
+
+impl SerializedFileCache {
+    pub fn get(&self, path: &Path) -> Option<&(SerializedFile, Data)> {
+        let file = self.files.get(path)?;
+        self.touch(path, file.1.as_ref().len());
+        Some(file)
+    }
+
+    pub fn insert(
+        &self,
+        path: PathBuf,
+        file: SerializedFile,
+        data: Data,
+    ) -> &(SerializedFile, Data) {
+        let size = data.as_ref().len();
+        self.touch(&path, size);
+        self.files.insert(path, Box::new((file, data)))
+    }
+
+    fn touch(&self, path: &Path, size: usize) {
+        let tick = self.clock.fetch_add(1, Ordering::Relaxed);
+        let mut usage = self.usage.lock().unwrap();
+        match usage.entries.get_mut(path) {
+            Some(entry) => entry.last_access = tick,
+            None => {
+                usage.entries.insert(
+                    path.to_owned(),
+                    Entry {
+                        size,
+                        last_access: tick,
+                    },
+                );
+                usage.total_bytes += size;
+            }
+        }
+    }
+
+    pub fn keys_cloned(&self) -> Vec<PathBuf> {
+        self.files.keys_cloned()
+    }
+
+    pub fn paths(&mut self) -> impl Iterator<Item = &PathBuf> {
+        self.files.as_mut().keys()
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.usage.lock().unwrap().total_bytes
+    }
+
+    pub fn budget(&self) -> Option<usize> {
+        self.budget
+    }
+
+    pub fn set_budget(&mut self, budget: Option<usize>) {
+        self.budget = budget;
+    }
+
+    /// Evicts least recently used files until the cache fits into its budget.
+    /// Returns the number of evicted files.
+    pub fn trim(&mut self) -> usize {
+        let Some(budget) = self.budget else {
+            return 0;
+        };
+        let usage = self.usage.get_mut().unwrap();
+        if usage.total_bytes <= budget {
+            return 0;
+        }
+
+        let mut by_age: Vec<_> = usage
+            .entries
+            .iter()
+            .map(|(path, entry)| (entry.last_access, path.clone()))
+            .collect();
+        by_age.sort_unstable();
+
+        let files = self.files.as_mut();
+        let mut evicted = 0;
+        for (_, path) in by_age {
+            if usage.total_bytes <= budget {
+                break;
+            }
+            let entry = usage.entries.remove(&path).unwrap();
+            usage.total_bytes -= entry.size;
+            files.remove(&path);
+            evicted += 1;
+        }
+        evicted
+    }
+
+    pub fn remove(&mut self, path: &Path) -> bool {
+        let usage = self.usage.get_mut().unwrap();
+        if let Some(entry) = usage.entries.remove(path) {
+            usage.total_bytes -= entry.size;
+        }
+        self.files.as_mut().remove(path).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.files.as_mut().clear();
+        *self.usage.get_mut().unwrap() = Usage::default();
+    }
+}
@@ -1,10 +1,16 @@
 pub mod addressables;
+pub mod bundles;
 pub mod component_path;
+pub mod diff;
+pub mod edit;
 pub mod env;
 pub mod handle;
 pub mod qualify;
 pub mod reachable;
+pub mod reference_index;
+pub mod repack;
 pub mod resolver;
+pub mod scan;
 pub mod scene_lookup;
 pub mod trace_pptr;
 pub mod typetree_generator_cache;
@@ -20,7 +20,7 @@ use crate::addressables::ArchivePath;
 use crate::component_path::{Component, ComponentId, ComponentPath, PathSegment};
 use crate::handle::SerializedFileHandle;
 use crate::resolver::EnvResolver;
-use crate::unity::types::{Component as UnityComponent, GameObject, Transform};
+use crate::unity::types::{Component as UnityComponent, GameObject, Named, Transform};
 
 /// A resolved object pointer.
 #[derive(Debug, Clone, Default)]
@@ -162,13 +162,6 @@ fn resolve_in<R: EnvResolver, P: TypeTreeProvider>(
     (path, name)
 }
 
-/// A minimal view reading just `m_Name`.
-#[derive(serde_derive::Deserialize)]
-#[allow(non_snake_case)]
-struct Named {
-    m_Name: String,
-}
-
 /// The non-empty `m_Name` of `target`, if it has one (best-effort).
 fn name_in<R: EnvResolver, P: TypeTreeProvider>(
     cx: &FileCtx<'_, R, P>,
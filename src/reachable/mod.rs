@@ -1,16 +1,19 @@
 //! Compute which objects are reachable from a starting point
 use anyhow::Result;
 use rabex::files::SerializedFile;
+use rabex::objects::ClassId;
 use rabex::objects::PPtr;
 use rabex::objects::pptr::PathId;
-use rabex::typetree::TypeTreeProvider;
+use rabex::typetree::{TypeTreeNode, TypeTreeProvider};
 use std::collections::{BTreeSet, VecDeque};
 use std::io::{Read, Seek};
 
 use crate::resolver::EnvResolver;
+use crate::unity::types::MonoScript;
 use crate::{Environment, trace_pptr};
 
 pub mod prune;
+pub mod transitive;
 
 /// Returns all reachable local objects from the starting point,
 /// only going down the transform hierarchy.
@@ -52,13 +55,60 @@ pub fn reachable_one<R: EnvResolver, P: TypeTreeProvider>(
 ) -> Result<Vec<PPtr>> {
     let info = file.get_object_info(from).unwrap();
 
-    /*let tt = env
-    .tpk
-    .get_typetree_node(info.m_ClassID, unity_version)
-    .with_context(|| format!("No typetree available for {:?}", info.m_ClassID))?;*/
     let tt = file.get_typetree_for(info, &env.tpk)?;
+    // Without an embedded typetree, only the MonoBehaviour header is known
+    let header_only = !file.m_EnableTypeTree || is_header_only(&tt);
+    let script_tt = match info.m_ClassID == ClassId::MonoBehaviour && header_only {
+        true => file
+            .script_type(info)
+            .and_then(|script| script_typetree(env, file, script, from, reader)),
+        false => None,
+    };
+    let tt = script_tt.unwrap_or(&*tt);
 
-    // TODO: use serialized typetree
     reader.seek(std::io::SeekFrom::Start(info.m_Offset as u64))?;
-    trace_pptr::trace_pptrs_endianned(&tt, reader, file.m_Header.m_Endianess)
+    trace_pptr::trace_pptrs_endianned_with(tt, reader, file.m_Header.m_Endianess, env)
+}
+
+/// Generates the typetree of a MonoBehaviour's script.
+/// If that fails, e.g. because the assemblies are missing, the header is still traced.
+fn script_typetree<'e, R: EnvResolver, P: TypeTreeProvider>(
+    env: &'e Environment<R, P>,
+    file: &SerializedFile,
+    script: PPtr,
+    from: PathId,
+    reader: &mut (impl Read + Seek),
+) -> Option<&'e TypeTreeNode> {
+    let script = match env.deref_read(script.typed::<MonoScript>(), file, reader) {
+        Ok(script) => script,
+        Err(e) => {
+            tracing::warn!("Could not read the script of MonoBehaviour {from}: {e:#}");
+            return None;
+        }
+    };
+    // failures are cached and logged once per script by the typetree generator
+    env.generate_typetree(&script.assembly_name(), &script.full_name())
+        .ok()
+        .flatten()
+}
+
+/// Fields of the `MonoBehaviour` base class, including editor-only ones
+const MONOBEHAVIOUR_HEADER: &[&str] = &[
+    "m_ObjectHideFlags",
+    "m_CorrespondingSourceObject",
+    "m_PrefabInstance",
+    "m_PrefabAsset",
+    "m_GameObject",
+    "m_Enabled",
+    "m_EditorHideFlags",
+    "m_Script",
+    "m_Name",
+    "m_EditorClassIdentifier",
+];
+
+/// Whether `tt` is just the MonoBehaviour header from the TPK, and not an embedded typetree with the script's fields
+fn is_header_only(tt: &TypeTreeNode) -> bool {
+    tt.children
+        .iter()
+        .all(|field| MONOBEHAVIOUR_HEADER.contains(&field.m_Name.as_str()))
 }
@@ -2,7 +2,7 @@
 use anyhow::{Context, Result};
 use rabex::files::SerializedFile;
 use rabex::objects::pptr::PathId;
-use rabex::objects::{ClassId, ClassIdType};
+use rabex::objects::{ClassId, ClassIdType, PPtr};
 use rabex::serde_typetree;
 use rabex::typetree::{TypeTreeNode, TypeTreeProvider};
 use rustc_hash::FxHashMap;
@@ -21,6 +21,8 @@ use crate::{Environment, reachable};
 pub struct PruneSceneResult {
     pub reachable: BTreeSet<PathId>,
     pub roots: Vec<(String, Transform)>,
+    /// References from the reachable objects into other files
+    pub externals: BTreeSet<PPtr>,
 }
 
 pub fn prune_scene_handle<'a>(
@@ -84,7 +86,7 @@ fn prune_scene_inner(
     replacements: &mut FxHashMap<PathId, Vec<u8>>,
     disable_roots: bool,
 ) -> Result<PruneSceneResult> {
-    let (mut all_reachable, _) = reachable::reachable(env, file, reader, retain_ids)
+    let (mut all_reachable, externals) = reachable::reachable(env, file, reader, retain_ids)
         .context("Could not determine reachable nodes")?;
 
     let mut ancestors = Vec::new();
@@ -133,6 +135,7 @@ fn prune_scene_inner(
     Ok(PruneSceneResult {
         reachable: all_reachable,
         roots: retain_objects,
+        externals,
     })
 }
 
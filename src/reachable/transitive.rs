@@ -0,0 +1,140 @@
+//! Follow references across serialized files.
+//!
+//! ```no_run
+//! # use rabex_env::Environment;
+//! # use rabex_env::reachable::transitive::reachable_transitive;
+//! # use rabex::objects::ClassId;
+//! # fn run(env: &Environment) -> anyhow::Result<()> {
+//! let reachable = reachable_transitive(env, "level1", [1], |_file, _path_id, class_id| {
+//!     // include scripts and shaders, but don't follow their dependencies
+//!     matches!(class_id, ClassId::MonoScript | ClassId::Shader)
+//! })?;
+//! for (from, to) in &reachable.file_edges {
+//!     println!("{from} -> {to}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::{BTreeSet, VecDeque};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rabex::objects::ClassId;
+use rabex::objects::pptr::PathId;
+use rabex::typetree::TypeTreeProvider;
+use rustc_hash::FxHashMap;
+
+use crate::Environment;
+use crate::handle::SerializedFileHandle;
+use crate::reachable::reachable_one;
+use crate::resolver::EnvResolver;
+
+/// Objects reachable across files, see [`reachable_transitive`].
+///
+/// Files are identified by their path for the starting file, and by their `m_Externals` path name otherwise.
+#[derive(Debug, Default)]
+pub struct TransitiveReachable {
+    /// Every reachable object, including the starting points
+    pub objects: BTreeSet<(String, PathId)>,
+    /// `(from, to)` for every file `from` containing a reachable object referencing an object in `to`
+    pub file_edges: BTreeSet<(String, String)>,
+    /// References into files that could not be loaded, or to objects which don't exist
+    pub unresolved: BTreeSet<(String, PathId)>,
+}
+
+/// Returns all objects reachable from `from` in `file`, following external references into other files.
+///
+/// Like [`reachable`](super::reachable), only the transform hierarchy downwards is followed.
+/// Objects for which `stop(file, path_id, class_id)` returns `true` are included, but their references are not followed.
+pub fn reachable_transitive<R: EnvResolver, P: TypeTreeProvider>(
+    env: &Environment<R, P>,
+    file: impl AsRef<Path>,
+    from: impl IntoIterator<Item = PathId>,
+    stop: impl Fn(&str, PathId, ClassId) -> bool,
+) -> Result<TransitiveReachable> {
+    let file = file.as_ref();
+    let file_name = file.display().to_string();
+
+    let mut files = FxHashMap::default();
+    files.insert(
+        file_name.clone(),
+        Some(
+            env.load_serialized(file)
+                .with_context(|| format!("Failed to load {file_name}"))?,
+        ),
+    );
+
+    let mut result = TransitiveReachable::default();
+    let mut queue = VecDeque::new();
+    for path_id in from {
+        if result.objects.insert((file_name.clone(), path_id)) {
+            queue.push_back((file_name.clone(), path_id));
+        }
+    }
+
+    while let Some((file_name, path_id)) = queue.pop_front() {
+        let file = files[&file_name].as_ref().unwrap();
+        let Some(info) = file.file.get_object_info(path_id) else {
+            result.objects.remove(&(file_name.clone(), path_id));
+            result.unresolved.insert((file_name, path_id));
+            continue;
+        };
+        if stop(&file_name, path_id, info.m_ClassID) {
+            continue;
+        }
+
+        let pptrs =
+            reachable_one(env, file.file, path_id, &mut file.reader()).with_context(|| {
+                format!(
+                    "Failed to trace {:?} {path_id} in {file_name}",
+                    info.m_ClassID
+                )
+            })?;
+
+        let mut targets = Vec::with_capacity(pptrs.len());
+        for pptr in pptrs {
+            if pptr.is_local() {
+                targets.push((file_name.clone(), pptr.m_PathID));
+                continue;
+            }
+            let Some(external) = pptr.file_identifier(file.file) else {
+                result.unresolved.insert((file_name.clone(), pptr.m_PathID));
+                continue;
+            };
+            let target = (external.pathName.clone(), pptr.m_PathID);
+            result
+                .file_edges
+                .insert((file_name.clone(), external.pathName.clone()));
+            targets.push(target);
+        }
+
+        for (target_file, target_path_id) in targets {
+            if !files
+                .entry(target_file.clone())
+                .or_insert_with(|| load(env, &target_file))
+                .is_some()
+            {
+                result.unresolved.insert((target_file, target_path_id));
+                continue;
+            }
+            if result.objects.insert((target_file.clone(), target_path_id)) {
+                queue.push_back((target_file, target_path_id));
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+fn load<'a, R: EnvResolver, P: TypeTreeProvider>(
+    env: &'a Environment<R, P>,
+    path_name: &str,
+) -> Option<SerializedFileHandle<'a, R, P>> {
+    match env.load_serialized(path_name) {
+        Ok(file) => Some(file),
+        Err(e) => {
+            tracing::debug!("Could not load external file {path_name}: {e:#}");
+            None
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Reverse lookup of PPtr references across the whole game.
+//!
+//! ```no_run
+//! # use rabex_env::Environment;
+//! # use rabex_env::reference_index::ReferenceIndex;
+//! # fn run(env: &Environment) -> anyhow::Result<()> {
+//! let index = ReferenceIndex::build(env)?;
+//! for referrer in index.referrers("archive:/CAB-abcd/CAB-abcd", 671) {
+//!     println!("{} #{}", referrer.file, referrer.path_id);
+//! }
+//! index.write_to(std::fs::File::create("references.bin")?)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, ensure};
+use byteorder::{LE, ReadBytesExt, WriteBytesExt};
+use rabex::objects::pptr::PathId;
+use rabex::typetree::TypeTreeProvider;
+use rustc_hash::FxHashMap;
+
+use crate::Environment;
+use crate::resolver::EnvResolver;
+use crate::utils;
+
+const MAGIC: &[u8; 4] = b"RFIX";
+const VERSION: u32 = 1;
+
+/// An object identified by the path of its serialized file and its path id.
+///
+/// File paths are the ones used in `m_Externals`, i.e. relative paths for built-in files
+/// and [`ArchivePath`](crate::addressables::ArchivePath)s for files in bundles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectLocation<'a> {
+    pub file: &'a str,
+    pub path_id: PathId,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct GlobalObject {
+    file: u32,
+    path_id: PathId,
+}
+
+/// Maps every referenced object to the objects referencing it, both from the same and from other files.
+#[derive(Default)]
+pub struct ReferenceIndex {
+    files: Vec<String>,
+    file_ids: FxHashMap<String, u32>,
+    referrers: FxHashMap<GlobalObject, Vec<GlobalObject>>,
+}
+
+impl std::fmt::Debug for ReferenceIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReferenceIndex")
+            .field("files", &self.files.len())
+            .field("targets", &self.referrers.len())
+            .finish()
+    }
+}
+
+impl ReferenceIndex {
+    /// Scans every serialized file returned by [`Environment::serialized_paths`] in parallel.
+    pub fn build<R: EnvResolver, P: TypeTreeProvider + Sync>(
+        env: &Environment<R, P>,
+    ) -> Result<ReferenceIndex> {
+        let paths = env.serialized_paths()?;
+        let references = utils::par_fold_reduce(
+            paths,
+            |acc: &mut Vec<(PathBuf, PathId, String, PathId)>, path| {
+                let file = env
+                    .load_serialized(&path)
+                    .with_context(|| format!("Failed to load {}", path.display()))?;
+                for object in file.objects::<()>() {
+                    let pptrs = object.reachable_one().with_context(|| {
+                        format!(
+                            "Failed to trace {:?} {} in {}",
+                            object.class_id(),
+                            object.path_id(),
+                            path.display()
+                        )
+                    })?;
+                    for pptr in pptrs {
+                        if pptr.m_PathID == 0 {
+                            continue;
+                        }
+                        let target_file = match pptr.is_local() {
+                            true => path.display().to_string(),
+                            false => match pptr.file_identifier(file.file) {
+                                Some(external) => external.pathName.clone(),
+                                None => continue,
+                            },
+                        };
+                        acc.push((path.clone(), object.path_id(), target_file, pptr.m_PathID));
+                    }
+                }
+                Ok(())
+            },
+        )?;
+
+        let mut index = ReferenceIndex::default();
+        for (file, path_id, target_file, target_path_id) in references {
+            let referrer = GlobalObject {
+                file: index.intern(&file.display().to_string()),
+                path_id,
+            };
+            let target = GlobalObject {
+                file: index.intern(&target_file),
+                path_id: target_path_id,
+            };
+            index.referrers.entry(target).or_default().push(referrer);
+        }
+        index.referrers.values_mut().for_each(|referrers| {
+            referrers.sort_unstable();
+            referrers.dedup();
+        });
+
+        Ok(index)
+    }
+
+    fn intern(&mut self, file: &str) -> u32 {
+        if let Some(&id) = self.file_ids.get(file) {
+            return id;
+        }
+        let id = self.files.len() as u32;
+        self.files.push(file.to_owned());
+        self.file_ids.insert(file.to_owned(), id);
+        id
+    }
+
+    fn location(&self, object: GlobalObject) -> ObjectLocation<'_> {
+        ObjectLocation {
+            file: &self.files[object.file as usize],
+            path_id: object.path_id,
+        }
+    }
+
+    /// All objects with a PPtr to `path_id` in `file`.
+    pub fn referrers(
+        &self,
+        file: &str,
+        path_id: PathId,
+    ) -> impl Iterator<Item = ObjectLocation<'_>> {
+        self.file_ids
+            .get(file)
+            .and_then(|&file| self.referrers.get(&GlobalObject { file, path_id }))
+            .into_iter()
+            .flatten()
+            .map(|&referrer| self.location(referrer))
+    }
+
+    /// Every referenced object together with its referrers, in no particular order.
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<Item = (ObjectLocation<'_>, impl Iterator<Item = ObjectLocation<'_>>)> {
+        self.referrers.iter().map(|(&target, referrers)| {
+            let referrers = referrers.iter().map(|&referrer| self.location(referrer));
+            (self.location(target), referrers)
+        })
+    }
+
+    /// Every file appearing as referrer or target.
+    pub fn files(&self) -> impl ExactSizeIterator<Item = &str> {
+        self.files.iter().map(String::as_str)
+    }
+
+    /// Number of distinct referenced objects.
+    pub fn len(&self) -> usize {
+        self.referrers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.referrers.is_empty()
+    }
+}
+
+/// # Persistence
+impl ReferenceIndex {
+    /// Writes the index in a compact binary format, readable by [`ReferenceIndex::read_from`].
+    pub fn write_to(&self, writer: impl Write) -> Result<()> {
+        let mut writer = std::io::BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LE>(VERSION)?;
+
+        writer.write_u32::<LE>(self.files.len() as u32)?;
+        for file in &self.files {
+            writer.write_u32::<LE>(file.len() as u32)?;
+            writer.write_all(file.as_bytes())?;
+        }
+
+        writer.write_u32::<LE>(self.referrers.len() as u32)?;
+        for (target, referrers) in &self.referrers {
+            write_object(&mut writer, *target)?;
+            writer.write_u32::<LE>(referrers.len() as u32)?;
+            for &referrer in referrers {
+                write_object(&mut writer, referrer)?;
+            }
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Reads an index written by [`ReferenceIndex::write_to`].
+    pub fn read_from(reader: impl Read) -> Result<ReferenceIndex> {
+        let mut reader = std::io::BufReader::new(reader);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        ensure!(&magic == MAGIC, "not a reference index");
+        let version = reader.read_u32::<LE>()?;
+        ensure!(
+            version == VERSION,
+            "unsupported reference index version {version}, expected {VERSION}"
+        );
+
+        let mut index = ReferenceIndex::default();
+        let file_count = reader.read_u32::<LE>()?;
+        for _ in 0..file_count {
+            let len = reader.read_u32::<LE>()?;
+            let mut file = vec![0; len as usize];
+            reader.read_exact(&mut file)?;
+            let file = String::from_utf8(file).context("invalid file name in reference index")?;
+            index.intern(&file);
+        }
+
+        let target_count = reader.read_u32::<LE>()?;
+        for _ in 0..target_count {
+            let target = read_object(&mut reader, file_count)?;
+            let referrer_count = reader.read_u32::<LE>()?;
+            let referrers = (0..referrer_count)
+                .map(|_| read_object(&mut reader, file_count))
+                .collect::<Result<Vec<_>>>()?;
+            index.referrers.insert(target, referrers);
+        }
+
+        Ok(index)
+    }
+}
+
+fn write_object(writer: &mut impl Write, object: GlobalObject) -> Result<()> {
+    writer.write_u32::<LE>(object.file)?;
+    writer.write_i64::<LE>(object.path_id)?;
+    Ok(())
+}
+
+fn read_object(reader: &mut impl Read, file_count: u32) -> Result<GlobalObject> {
+    let file = reader.read_u32::<LE>()?;
+    ensure!(file < file_count, "invalid file index in reference index");
+    let path_id = reader.read_i64::<LE>()?;
+    Ok(GlobalObject { file, path_id })
+}
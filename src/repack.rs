@@ -0,0 +1,143 @@
+//! Repack pruned scenes into assetbundles, which can be loaded via `AssetBundle.LoadFromFile` and `SceneManager.LoadScene`.
+//!
+//! ```no_run
+//! # use rabex_env::Environment;
+//! # use rabex_env::repack::{RepackSceneOptions, repack_scene};
+//! # fn run(env: &Environment) -> anyhow::Result<()> {
+//! let options = RepackSceneOptions::new("menu", "Assets/Scenes/Menu.unity");
+//! let bundle = repack_scene(env, "level1", ["Canvas/Title"], &options)?;
+//! std::fs::write("menu.bundle", bundle)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rabex::files::bundlefile::CompressionType;
+use rabex::files::bundlefile::builder::BundleFileBuilder;
+use rabex::files::serializedfile::build_common_offset_map;
+use rabex::files::serializedfile::builder::SerializedFileBuilder;
+use rabex::objects::PPtr;
+use rabex::objects::pptr::PathId;
+use rustc_hash::FxHashMap;
+
+use crate::Environment;
+use crate::reachable::prune::prune_scene_handle;
+use crate::resolver::EnvResolver;
+use crate::unity::types::{AssetBundle, PreloadData};
+
+/// Path id of the [`AssetBundle`] object in the `.sharedAssets` file.
+const ASSET_BUNDLE_PATH_ID: PathId = 1;
+/// Path id of the [`PreloadData`] object in the `.sharedAssets` file.
+const PRELOAD_DATA_PATH_ID: PathId = 2;
+
+pub struct RepackSceneOptions {
+    /// The `m_AssetBundleName` of the bundle
+    pub bundle_name: String,
+    /// The path the scene is loaded by, starting with `Assets/`
+    pub scene_path: String,
+    pub compression: CompressionType,
+    /// Deactivate the retained root objects, so they can be activated after loading
+    pub disable_roots: bool,
+}
+
+impl RepackSceneOptions {
+    pub fn new(bundle_name: &str, scene_path: &str) -> Self {
+        RepackSceneOptions {
+            bundle_name: bundle_name.to_owned(),
+            scene_path: scene_path.to_owned(),
+            compression: CompressionType::None,
+            disable_roots: false,
+        }
+    }
+
+    pub fn with_compression(mut self, compression: CompressionType) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn with_disable_roots(mut self, disable_roots: bool) -> Self {
+        self.disable_roots = disable_roots;
+        self
+    }
+
+    /// The name of the scene's serialized file in the bundle, which is also used as scene hash.
+    ///
+    /// Unity names these `BuildPlayer-<scene>`.
+    fn scene_hash(&self) -> String {
+        let scene_name = Path::new(&self.scene_path)
+            .file_stem()
+            .map_or(self.scene_path.as_str(), |stem| stem.to_str().unwrap());
+        format!("BuildPlayer-{scene_name}")
+    }
+}
+
+/// Prunes `scene` down to the objects reachable from `retain_paths` (see [`crate::reachable::prune`]),
+/// and writes it into a scene assetbundle.
+///
+/// The bundle contains the pruned scene and a `.sharedAssets` file with the [`AssetBundle`] and [`PreloadData`]
+/// objects describing it. References into other files of the game are kept as they are.
+pub fn repack_scene<'a, R: EnvResolver>(
+    env: &Environment<R>,
+    scene: impl AsRef<Path>,
+    retain_paths: impl IntoIterator<Item = &'a str>,
+    options: &RepackSceneOptions,
+) -> Result<Vec<u8>> {
+    let scene = scene.as_ref();
+    let file = env
+        .load_serialized(scene)
+        .with_context(|| format!("Could not load scene {}", scene.display()))?;
+
+    let mut replacements = FxHashMap::default();
+    let pruned = prune_scene_handle(
+        file.reborrow(),
+        retain_paths,
+        &mut replacements,
+        options.disable_roots,
+    )?;
+
+    let mut edit = file.edit()?;
+    edit.retain(|path_id| pruned.reachable.contains(&path_id));
+    edit.replace_all_raw(replacements)?;
+    let scene_data = edit.write()?;
+
+    let unity_version = env.unity_version()?;
+    let common_offset_map = build_common_offset_map(&env.tpk.inner, unity_version);
+    let mut shared_assets =
+        SerializedFileBuilder::new(unity_version, &env.tpk, &common_offset_map, true);
+
+    let mut preload_data = PreloadData {
+        m_Name: String::new(),
+        m_Assets: Vec::with_capacity(pruned.externals.len()),
+        m_Dependencies: Vec::new(),
+        m_ExplicitDataLayout: false,
+    };
+    for pptr in &pruned.externals {
+        let external = pptr
+            .file_identifier(file.file)
+            .with_context(|| format!("Invalid external file id {}", pptr.m_FileID))?;
+        preload_data.m_Assets.push(PPtr {
+            m_FileID: shared_assets.get_or_insert_external(&external.pathName),
+            m_PathID: pptr.m_PathID,
+        });
+    }
+
+    let scene_hash = options.scene_hash();
+    let asset_bundle = AssetBundle::scene(
+        &options.bundle_name,
+        [(options.scene_path.as_str(), scene_hash.as_str())],
+    );
+
+    shared_assets.add_object_at(ASSET_BUNDLE_PATH_ID, &asset_bundle)?;
+    shared_assets.add_object_at(PRELOAD_DATA_PATH_ID, &preload_data)?;
+    let shared_assets_data = shared_assets.write_vec()?;
+
+    let mut bundle = BundleFileBuilder::unityfs(7, unity_version);
+    bundle.add_file(&scene_hash, &scene_data)?;
+    bundle.add_file(&format!("{scene_hash}.sharedAssets"), &shared_assets_data)?;
+
+    let mut out = Cursor::new(Vec::new());
+    bundle.write(&mut out, options.compression)?;
+    Ok(out.into_inner())
+}
@@ -0,0 +1,238 @@
+//! Shared parts of the archive-backed resolvers
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Cursor, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use rabex::files::bundlefile::{BundleFileReader, ExtractionConfig};
+
+use crate::env::Data;
+use crate::resolver::game_files::{find_data_dir, resource_path};
+
+/// The files of the unity data directory inside an archive, relative to the data directory.
+#[derive(Debug)]
+pub(crate) struct ArchiveIndex<E> {
+    /// Data directory inside the archive
+    pub data_dir: PathBuf,
+    entries: BTreeMap<PathBuf, E>,
+}
+
+impl<E> ArchiveIndex<E> {
+    /// Finds the data directory like [`GameFiles::probe_dir`](super::GameFiles::probe_dir)
+    /// and keeps the `files` inside of it.
+    ///
+    /// Archives of just the contents of the data directory are accepted as well.
+    pub fn new(archive: &Path, files: Vec<(PathBuf, E)>) -> Result<Self> {
+        let dirs: BTreeSet<&Path> = files
+            .iter()
+            .flat_map(|(path, _)| path.ancestors().skip(1))
+            .collect();
+
+        let data_dir = match find_data_dir(dirs) {
+            Some(dir) => dir,
+            None if files.iter().any(|(path, _)| {
+                path == Path::new("globalgamemanagers") || path == Path::new(PACKED_LEVELS)
+            }) =>
+            {
+                PathBuf::new()
+            }
+            None => bail!(
+                "Archive '{}' does not contain a unity game. It should have a gamename_Data folder.",
+                archive.display()
+            ),
+        };
+
+        let entries = files
+            .into_iter()
+            .filter_map(|(path, entry)| {
+                let path = path.strip_prefix(&data_dir).ok()?.to_owned();
+                Some((path, entry))
+            })
+            .collect();
+
+        Ok(ArchiveIndex { data_dir, entries })
+    }
+
+    pub fn get(&self, path: &Path) -> Result<&E, std::io::Error> {
+        let resource_path = resource_path(path);
+        let path = resource_path.as_deref().unwrap_or(path);
+        self.entries.get(path).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("File '{}' not found in archive", path.display()),
+            )
+        })
+    }
+
+    pub fn all_files(&self) -> Vec<PathBuf> {
+        self.entries.keys().cloned().collect()
+    }
+
+    pub fn list_under(&self, prefix: &Path) -> Vec<PathBuf> {
+        self.entries
+            .range::<Path, _>(prefix..)
+            .map(|(path, _)| path)
+            .take_while(|path| path.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+}
+
+/// The bundle holding the level files of packed builds
+pub(crate) const PACKED_LEVELS: &str = "data.unity3d";
+
+/// The entries of a packed build's `data.unity3d`, read like [`GameFiles`](super::GameFiles) does.
+///
+/// Files of the archive shadow packed entries of the same path.
+pub(crate) struct PackedLevels(Option<BundleFileReader<Cursor<Data>>>);
+
+impl PackedLevels {
+    /// Reads the `data.unity3d` of the archive, if there is one.
+    pub fn new(archive: &Path, data: Option<Data>) -> Result<Self> {
+        let bundle = data
+            .map(|data| {
+                BundleFileReader::from_reader(Cursor::new(data), &ExtractionConfig::default())
+            })
+            .transpose()
+            .with_context(|| {
+                format!("Could not read {PACKED_LEVELS} in '{}'", archive.display())
+            })?;
+        Ok(PackedLevels(bundle))
+    }
+
+    /// Reads a packed entry. `Ok(None)` if there is no such entry, or the build isn't packed.
+    pub fn read(&self, path: &Path) -> Result<Option<Vec<u8>>, std::io::Error> {
+        let Some(bundle) = &self.0 else {
+            return Ok(None);
+        };
+        let Some(path) = path.to_str() else {
+            return Ok(None);
+        };
+        bundle.read_at(path)
+    }
+
+    /// Reads the packed entry at `path` instead, if `error` says it wasn't found in the archive.
+    pub fn read_missing(
+        &self,
+        path: &Path,
+        error: std::io::Error,
+    ) -> Result<Vec<u8>, std::io::Error> {
+        if error.kind() != ErrorKind::NotFound {
+            return Err(error);
+        }
+        self.read(path)?.ok_or(error)
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files().any(|file| file == path)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.0
+            .iter()
+            .flat_map(|bundle| bundle.files())
+            .map(|file| Path::new(&file.path))
+    }
+
+    /// The `archive_files` and the packed entries, sorted by path
+    pub fn all_files(&self, archive_files: Vec<PathBuf>) -> Vec<PathBuf> {
+        self.list_under(Path::new(""), archive_files)
+    }
+
+    /// The `archive_files` and the packed entries under `prefix`, sorted by path
+    pub fn list_under(&self, prefix: &Path, archive_files: Vec<PathBuf>) -> Vec<PathBuf> {
+        let mut files: BTreeSet<_> = archive_files.into_iter().collect();
+        files.extend(
+            self.files()
+                .filter(|file| file.starts_with(prefix))
+                .map(Path::to_owned),
+        );
+        files.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rabex::files::bundlefile::CompressionType;
+    use rabex::files::bundlefile::builder::BundleFileBuilder;
+
+    use super::*;
+
+    #[test]
+    fn packed_levels() {
+        let mut builder = BundleFileBuilder::unityfs(7, &"6000.0.0f1".parse().unwrap());
+        builder.add_file("level0", &b"level0"[..]).unwrap();
+        builder.add_file("globalgamemanagers", &b"ggm"[..]).unwrap();
+        let mut bundle = Cursor::new(Vec::new());
+        builder.write(&mut bundle, CompressionType::None).unwrap();
+        let bundle = Data::InMemory(bundle.into_inner());
+
+        let packed = PackedLevels::new(Path::new("game.zip"), Some(bundle)).unwrap();
+        assert!(packed.contains(Path::new("level0")));
+        assert_eq!(packed.files().count(), 2);
+        assert_eq!(
+            packed.read(Path::new("level0")).unwrap().as_deref(),
+            Some(&b"level0"[..])
+        );
+
+        let missing = || std::io::Error::from(ErrorKind::NotFound);
+        let data = packed.read_missing(Path::new("globalgamemanagers"), missing());
+        assert_eq!(data.unwrap(), b"ggm");
+        assert!(packed.read_missing(Path::new("level1"), missing()).is_err());
+        assert_eq!(
+            packed.all_files(vec![PathBuf::from("level0"), PathBuf::from("data.unity3d")]),
+            [
+                PathBuf::from("data.unity3d"),
+                PathBuf::from("globalgamemanagers"),
+                PathBuf::from("level0"),
+            ]
+        );
+
+        assert_eq!(
+            packed.list_under(Path::new("level0"), Vec::new()),
+            [PathBuf::from("level0")]
+        );
+
+        let unpacked = PackedLevels::new(Path::new("game.zip"), None).unwrap();
+        assert_eq!(unpacked.read(Path::new("level0")).unwrap(), None);
+    }
+
+    #[test]
+    fn strips_data_dir() {
+        let files = [
+            "Game/Game.exe",
+            "Game/Game_Data/level0",
+            "Game/Game_Data/Resources/unity default resources",
+        ]
+        .map(|path| (PathBuf::from(path), ()));
+        let index = ArchiveIndex::new(Path::new("game.zip"), files.to_vec()).unwrap();
+        assert_eq!(index.data_dir, Path::new("Game/Game_Data"));
+        assert_eq!(
+            index.all_files(),
+            [
+                PathBuf::from("Resources/unity default resources"),
+                PathBuf::from("level0")
+            ]
+        );
+        assert!(
+            index
+                .get(Path::new("Library/unity default resources"))
+                .is_ok()
+        );
+        assert_eq!(index.list_under(Path::new("Resources")).len(), 1);
+
+        let flat = ArchiveIndex::new(
+            Path::new("data.zip"),
+            vec![(PathBuf::from("globalgamemanagers"), ())],
+        )
+        .unwrap();
+        assert_eq!(flat.data_dir, Path::new(""));
+        assert!(
+            ArchiveIndex::new(
+                Path::new("other.zip"),
+                vec![(PathBuf::from("readme.txt"), ())]
+            )
+            .is_err()
+        );
+    }
+}
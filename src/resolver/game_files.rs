@@ -1,7 +1,8 @@
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs::File;
 use std::io::{BufReader, Cursor, ErrorKind};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 use anyhow::{Result, bail, ensure};
 use memmap2::Mmap;
@@ -9,12 +10,14 @@ use rabex::files::bundlefile::{BundleFileReader, ExtractionConfig};
 use walkdir::WalkDir;
 
 use crate::env::Data;
-use crate::resolver::{DataOrFile, EnvResolver};
+use crate::resolver::{DataOrFile, EnvResolver, LazyPathIndex, PathIndex};
 
 #[derive(Debug)]
 pub struct GameFiles {
     pub game_dir: PathBuf,
     pub level_files: LevelFiles,
+    /// Files on disk, see [`GameFiles::invalidate_index`]
+    index: LazyPathIndex,
 }
 
 #[derive(Debug)]
@@ -23,35 +26,80 @@ pub enum LevelFiles {
     Packed(Box<BundleFileReader<Cursor<Mmap>>>),
 }
 
+/// Where a file of [`GameFiles`] is read from, see [`GameFiles::all_files_with_source`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileSource {
+    /// A file in the game directory, including `data.unity3d` itself
+    Disk,
+    /// An entry of the packed `data.unity3d` bundle
+    Packed,
+}
+
 fn find_unity_data_dir(install_dir: &Path) -> Result<Option<PathBuf>> {
-    Ok(std::fs::read_dir(install_dir)?
-        .filter_map(Result::ok)
-        .find_map(|entry| {
-            let path = entry.path();
-            if is_unity_data_dir(&path) {
-                return Some(path);
-            }
-            // On macOS the player ships as a `.app` bundle with the Unity data
-            // dir at `<Game>.app/Contents/Resources/Data`.
-            let is_app_bundle = path
+    let mut dirs = Vec::new();
+    for entry in std::fs::read_dir(install_dir)?.filter_map(Result::ok) {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let app_data_dir = path.join("Contents/Resources/Data");
+        if app_data_dir.is_dir() {
+            dirs.push(app_data_dir);
+        }
+        dirs.push(path);
+    }
+    Ok(find_data_dir(dirs))
+}
+
+fn is_unity_data_dir(dir: &Path) -> bool {
+    is_data_dir_name(dir) && dir.is_dir()
+}
+
+/// Picks the unity data directory out of the directories of a game installation.
+///
+/// That is either a `<Game>_Data` directory, or `<Game>.app/Contents/Resources/Data` for macOS builds,
+/// where the player ships as an app bundle. If several directories match, the least nested one wins.
+///
+/// ```
+/// # use std::path::Path;
+/// # use rabex_env::resolver::game_files::find_data_dir;
+/// let dirs = ["Game", "Game/Game_Data", "Game/Game_Data/StreamingAssets", "Game/MonoBleedingEdge"];
+/// assert_eq!(find_data_dir(dirs).unwrap(), Path::new("Game/Game_Data"));
+/// ```
+pub fn find_data_dir<P: AsRef<Path>>(dirs: impl IntoIterator<Item = P>) -> Option<PathBuf> {
+    dirs.into_iter()
+        .filter(|dir| is_data_dir_name(dir.as_ref()))
+        .map(|dir| dir.as_ref().to_owned())
+        .min_by(|a, b| (a.components().count(), a).cmp(&(b.components().count(), b)))
+}
+
+fn is_data_dir_name(dir: &Path) -> bool {
+    let mut components = dir.components().rev().map(|c| c.as_os_str());
+    let Some(name) = components.next() else {
+        return false;
+    };
+    if name.to_str().is_some_and(|name| name.ends_with("_Data")) {
+        return true;
+    }
+
+    name == "Data"
+        && components.next().is_some_and(|c| c == "Resources")
+        && components.next().is_some_and(|c| c == "Contents")
+        && components.next().is_some_and(|app| {
+            Path::new(app)
                 .extension()
                 .and_then(OsStr::to_str)
-                .is_some_and(|ext| ext.eq_ignore_ascii_case("app"));
-            if is_app_bundle {
-                let data_dir = path.join("Contents/Resources/Data");
-                if data_dir.is_dir() {
-                    return Some(data_dir);
-                }
-            }
-            None
-        }))
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("app"))
+        })
 }
 
-fn is_unity_data_dir(dir: &Path) -> bool {
-    dir.file_name()
-        .and_then(OsStr::to_str)
-        .is_some_and(|name| name.ends_with("_Data"))
-        && dir.is_dir()
+/// Unity reads built-in resources like `Library/unity default resources` from the `Resources` folder.
+pub(crate) fn resource_path(path: &Path) -> Option<PathBuf> {
+    let mut components = path.components();
+    let first = components.next().and_then(|c| c.as_os_str().to_str());
+    let is_resource_dir =
+        first.is_some_and(|s| matches!(s, "library" | "Library" | "resources" | "Resources"));
+    is_resource_dir.then(|| Path::new("Resources").join(components))
 }
 
 impl GameFiles {
@@ -96,9 +144,85 @@ impl GameFiles {
         Ok(GameFiles {
             game_dir: game_dir.to_owned(),
             level_files,
+            index: LazyPathIndex::default(),
         })
     }
 
+    /// Drops the index of files on disk used by [`EnvResolver::list_under`] and [`EnvResolver::exists`],
+    /// so it is rebuilt on next use. Call this after adding or removing files in the game directory.
+    ///
+    /// Reads which notice a file missing from or added to the index invalidate it as well.
+    pub fn invalidate_index(&self) {
+        self.index.invalidate();
+    }
+
+    fn index(&self) -> Result<std::sync::Arc<PathIndex>, std::io::Error> {
+        self.index.get_or_try_build(|| {
+            let mut files = Vec::new();
+            for entry in WalkDir::new(&self.game_dir) {
+                let entry = entry.map_err(|e| {
+                    e.into_io_error()
+                        .unwrap_or_else(|| std::io::Error::other("walkdir error"))
+                })?;
+                if entry.file_type().is_dir() {
+                    continue;
+                }
+                files.push(
+                    entry
+                        .path()
+                        .strip_prefix(&self.game_dir)
+                        .unwrap()
+                        .to_owned(),
+                );
+            }
+            Ok(PathIndex::new(files))
+        })
+    }
+
+    /// All files on disk, recursively, and the entries of a packed `data.unity3d`, sorted by path.
+    ///
+    /// Files on disk shadow packed entries of the same path, like they do when reading.
+    pub fn all_files_with_source(&self) -> Result<Vec<(PathBuf, FileSource)>, std::io::Error> {
+        let mut files = BTreeMap::new();
+        if let LevelFiles::Packed(bundle) = &self.level_files {
+            for file in bundle.files() {
+                files.insert(PathBuf::from(&file.path), FileSource::Packed);
+            }
+        }
+        for path in self.index()?.files() {
+            files.insert(path.to_owned(), FileSource::Disk);
+        }
+        Ok(files.into_iter().collect())
+    }
+
+    /// Keeps the index consistent with what reads observed on disk.
+    /// Directories opened as files don't count, as the index only holds regular files.
+    fn check_index(&self, path: &Path, file: &Result<File, std::io::Error>) {
+        let Some(index) = self.index.get() else {
+            return;
+        };
+        let on_disk = file
+            .as_ref()
+            .is_ok_and(|file| file.metadata().is_ok_and(|metadata| metadata.is_file()));
+        if index.contains(&normalize(path)) != on_disk {
+            self.index.invalidate();
+        }
+    }
+
+    /// Whether `path` is a regular file on disk, checking the file system when the index doesn't know about it
+    fn exists_on_disk(&self, path: &Path) -> Result<bool, std::io::Error> {
+        let path = normalize(path);
+        if self.index()?.contains(&path) {
+            return Ok(true);
+        }
+        // the index may be older than the file
+        let is_file = self.game_dir.join(&path).is_file();
+        if is_file {
+            self.index.invalidate();
+        }
+        Ok(is_file)
+    }
+
     pub fn read(&self, filename: &str) -> Result<Data, std::io::Error> {
         match &self.level_files {
             LevelFiles::Unpacked => {
@@ -130,19 +254,18 @@ impl GameFiles {
     /// The caller decides how to consume it (mmap vs. streaming read).
     fn resolve(&self, path: &Path) -> Result<Resolved, std::io::Error> {
         // TODO: consolidate and move away from the trait implementations?
-        let mut components = path.components();
-        let first = components.next().and_then(|c| c.as_os_str().to_str());
-        let is_resource_dir =
-            first.is_some_and(|s| matches!(s, "library" | "Library" | "resources" | "Resources"));
-        if is_resource_dir {
-            let resource_path = self.game_dir.join("Resources").join(&components);
-            return File::open(resource_path).map(Resolved::File);
+        if let Some(resource_path) = resource_path(path) {
+            let file = File::open(self.game_dir.join(&resource_path));
+            self.check_index(&resource_path, &file);
+            return file.map(Resolved::File);
         }
 
         // PERF: decide on whether to look into packed files based on name?
 
         let fs_path = self.game_dir.join(path);
-        match File::open(&fs_path) {
+        let file = File::open(&fs_path);
+        self.check_index(path, &file);
+        match file {
             Ok(f) => Ok(Resolved::File(f)),
             Err(e) if e.kind() == ErrorKind::NotFound => match &self.level_files {
                 LevelFiles::Unpacked => Err(std::io::Error::new(
@@ -188,57 +311,128 @@ impl EnvResolver for GameFiles {
     }
 
     fn all_files(&self) -> Result<Vec<PathBuf>, std::io::Error> {
-        match &self.level_files {
-            LevelFiles::Unpacked => {
-                let mut all = Vec::new();
-                for entry in std::fs::read_dir(&self.game_dir)? {
-                    let entry = entry?;
-
-                    if entry.file_type()?.is_dir() {
-                        continue;
-                    }
-
-                    all.push(
-                        entry
-                            .path()
-                            .strip_prefix(&self.game_dir)
-                            .unwrap()
-                            .to_owned(),
-                    );
-                }
-                Ok(all)
-            }
-            LevelFiles::Packed(bundle) => {
-                // TODO: non-unity3d files as well
-                Ok(bundle
-                    .files()
-                    .iter()
-                    .map(|file| file.path.clone().into())
-                    .collect())
-            }
+        Ok(self
+            .all_files_with_source()?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect())
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, std::io::Error> {
+        if let Some(resource_path) = resource_path(path) {
+            return self.exists_on_disk(&resource_path);
+        }
+        if self.exists_on_disk(path)? {
+            return Ok(true);
         }
+        Ok(match &self.level_files {
+            LevelFiles::Unpacked => false,
+            LevelFiles::Packed(bundle) => bundle
+                .files()
+                .iter()
+                .any(|file| Path::new(&file.path) == path),
+        })
     }
 
     fn list_under(&self, prefix: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
-        let abs_prefix = self.game_dir.join(prefix);
-        let mut out = Vec::new();
-        for entry in WalkDir::new(&abs_prefix).into_iter() {
-            let entry = entry.map_err(|e| {
-                e.into_io_error()
-                    .unwrap_or_else(|| std::io::Error::other("walkdir error"))
-            })?;
-            if entry.file_type().is_dir() {
-                continue;
-            }
-            out.push(
-                entry
-                    .path()
-                    // TODO: validate this is correct
-                    .strip_prefix(&self.game_dir)
-                    .unwrap()
-                    .to_owned(),
-            );
-        }
-        Ok(out)
+        Ok(self.index()?.list_under(&normalize(prefix)))
+    }
+}
+
+/// Drops `.` components, so paths compare like the ones in the index
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_dir() {
+        assert_eq!(
+            find_data_dir([
+                "Hollow Knight_Data",
+                "Hollow Knight_Data/Managed",
+                "MonoBleedingEdge"
+            ]),
+            Some(PathBuf::from("Hollow Knight_Data"))
+        );
+        assert_eq!(
+            find_data_dir([
+                "Game.app",
+                "Game.app/Contents",
+                "Game.app/Contents/Resources",
+                "Game.app/Contents/Resources/Data",
+                "Game.app/Contents/Resources/Data/StreamingAssets/Mod_Data",
+            ]),
+            Some(PathBuf::from("Game.app/Contents/Resources/Data"))
+        );
+        assert_eq!(find_data_dir(["Contents/Resources/Data", "Data"]), None);
+        assert_eq!(find_data_dir(Vec::<PathBuf>::new()), None);
+    }
+
+    #[test]
+    fn index_invalidation() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let data_dir = tmp.path().join("Game_Data");
+        std::fs::create_dir_all(data_dir.join("StreamingAssets/aa")).unwrap();
+        std::fs::write(data_dir.join("StreamingAssets/aa/a.bundle"), b"UnityFS").unwrap();
+
+        let game_files = GameFiles::probe(tmp.path()).unwrap();
+        let aa = Path::new("StreamingAssets/aa");
+        assert_eq!(game_files.list_under(aa).unwrap().len(), 1);
+
+        std::fs::write(data_dir.join("StreamingAssets/aa/b.bundle"), b"UnityFS").unwrap();
+        assert_eq!(game_files.list_under(aa).unwrap().len(), 1);
+        // reading a file the index doesn't know about rebuilds it
+        game_files.read_path(&aa.join("b.bundle")).unwrap();
+        assert_eq!(game_files.list_under(aa).unwrap().len(), 2);
+
+        // files added after the index was built exist, without an explicit invalidation
+        std::fs::write(data_dir.join("StreamingAssets/aa/c.bundle"), b"UnityFS").unwrap();
+        assert!(game_files.exists(&aa.join("c.bundle")).unwrap());
+        assert_eq!(game_files.list_under(aa).unwrap().len(), 3);
+        std::fs::remove_file(data_dir.join("StreamingAssets/aa/c.bundle")).unwrap();
+        game_files.invalidate_index();
+
+        std::fs::remove_file(data_dir.join("StreamingAssets/aa/a.bundle")).unwrap();
+        game_files.invalidate_index();
+        assert!(!game_files.exists(&aa.join("a.bundle")).unwrap());
+        assert_eq!(
+            game_files.all_files_with_source().unwrap(),
+            [(aa.join("b.bundle"), FileSource::Disk)]
+        );
+    }
+
+    #[test]
+    fn equivalent_paths_keep_the_index() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let data_dir = tmp.path().join("Game_Data");
+        std::fs::create_dir_all(data_dir.join("StreamingAssets")).unwrap();
+        std::fs::write(data_dir.join("level0"), b"level0").unwrap();
+
+        let game_files = GameFiles::probe(tmp.path()).unwrap();
+        let index = game_files.index().unwrap();
+
+        game_files.read_path(Path::new("./level0")).unwrap();
+        let _ = game_files.open_path(Path::new("StreamingAssets"));
+        assert!(game_files.exists(Path::new("./level0")).unwrap());
+        assert!(!game_files.exists(Path::new("StreamingAssets")).unwrap());
+        assert!(std::sync::Arc::ptr_eq(
+            &index,
+            &game_files.index.get().unwrap()
+        ));
+    }
+
+    #[test]
+    fn resources() {
+        assert_eq!(
+            resource_path(Path::new("Library/unity default resources")),
+            Some(PathBuf::from("Resources/unity default resources"))
+        );
+        assert_eq!(resource_path(Path::new("level0")), None);
     }
 }
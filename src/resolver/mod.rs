@@ -7,11 +7,27 @@ use anyhow::Result;
 
 use crate::env::Data;
 
+#[cfg(any(feature = "zip", feature = "tar-zst"))]
+mod archive;
 pub mod game_files;
 mod mem;
-
-pub use game_files::GameFiles;
+mod overlay;
+mod path_index;
+pub mod snapshot;
+#[cfg(feature = "tar-zst")]
+mod tar_zst;
+#[cfg(feature = "zip")]
+mod zip;
+
+pub use game_files::{FileSource, GameFiles};
 pub use mem::MemResolver;
+pub use overlay::OverlayResolver;
+pub use path_index::{LazyPathIndex, PathIndex};
+pub use snapshot::{SnapshotResolver, SnapshotStore};
+#[cfg(feature = "tar-zst")]
+pub use tar_zst::TarZstResolver;
+#[cfg(feature = "zip")]
+pub use zip::ZipResolver;
 
 /// A trait abstracting where the game files are read from.
 /// All paths are interpreted as relative to the `Game_Data/` directory.
@@ -28,9 +44,18 @@ pub trait EnvResolver: Sync {
 
     fn all_files(&self) -> Result<Vec<PathBuf>, std::io::Error>;
 
+    /// Whether `path` exists, without reading it
+    fn exists(&self, path: &Path) -> Result<bool, std::io::Error> {
+        match self.open_path(path) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
     /// List every file under `prefix`
     fn list_under(&self, prefix: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
-        // PERF: O(n) default impl
+        // PERF: O(n) default impl, resolvers can keep a `PathIndex` instead
         Ok(self
             .all_files()?
             .into_iter()
@@ -89,6 +114,10 @@ impl<T: EnvResolver> EnvResolver for &T {
         (**self).all_files()
     }
 
+    fn exists(&self, path: &Path) -> Result<bool, std::io::Error> {
+        (**self).exists(path)
+    }
+
     fn list_under(&self, prefix: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
         (**self).list_under(prefix)
     }
@@ -0,0 +1,237 @@
+use std::collections::BTreeSet;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::env::Data;
+use crate::resolver::EnvResolver;
+
+/// The object-safe part of [`EnvResolver`], so layers of different types can be stacked.
+trait Layer: Sync {
+    fn read_path(&self, path: &Path) -> Result<Data, std::io::Error>;
+    fn exists(&self, path: &Path) -> Result<bool, std::io::Error>;
+    fn all_files(&self) -> Result<Vec<PathBuf>, std::io::Error>;
+    fn list_under(&self, prefix: &Path) -> Result<Vec<PathBuf>, std::io::Error>;
+}
+
+impl<T: EnvResolver> Layer for T {
+    fn read_path(&self, path: &Path) -> Result<Data, std::io::Error> {
+        EnvResolver::read_path(self, path)
+    }
+    fn exists(&self, path: &Path) -> Result<bool, std::io::Error> {
+        EnvResolver::exists(self, path)
+    }
+    fn all_files(&self) -> Result<Vec<PathBuf>, std::io::Error> {
+        EnvResolver::all_files(self)
+    }
+    fn list_under(&self, prefix: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+        EnvResolver::list_under(self, prefix)
+    }
+}
+
+struct OverlayLayer<'a> {
+    resolver: Box<dyn Layer + 'a>,
+    /// Files and directories hidden in the layers below
+    whiteouts: BTreeSet<PathBuf>,
+}
+
+/// An [`EnvResolver`] stacking several resolvers, e.g. the files of a mod on top of the game files.
+///
+/// Files are looked up from the topmost layer down, and the first layer containing the file wins.
+/// [Whiteouts](OverlayResolver::whiteout) hide files or directories of the layers below,
+/// for mods which delete files.
+///
+/// ```
+/// # use std::path::Path;
+/// # use rabex_env::resolver::{EnvResolver, MemResolver, OverlayResolver};
+/// let game = MemResolver::from_iter([("level0", vec![0]), ("level1", vec![1])]);
+/// let m0d = MemResolver::from_iter([("level0", vec![2])]);
+///
+/// let mut overlay = OverlayResolver::new(&game);
+/// overlay.push(m0d).whiteout("level1");
+/// assert_eq!(overlay.read_path(Path::new("level0")).unwrap().as_ref(), [2]);
+/// assert!(overlay.read_path(Path::new("level1")).is_err());
+/// ```
+///
+/// Files are read in full, so [`EnvResolver::open_path`] is no cheaper than [`EnvResolver::read_path`].
+pub struct OverlayResolver<'a> {
+    /// Bottom layer first
+    layers: Vec<OverlayLayer<'a>>,
+}
+
+impl std::fmt::Debug for OverlayResolver<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OverlayResolver")
+            .field("layers", &self.layers.len())
+            .finish()
+    }
+}
+
+impl<'a> OverlayResolver<'a> {
+    /// An overlay with `base`, e.g. the [`GameFiles`](super::GameFiles), as its bottom layer.
+    pub fn new(base: impl EnvResolver + 'a) -> Self {
+        let mut overlay = OverlayResolver { layers: Vec::new() };
+        overlay.push(base);
+        overlay
+    }
+
+    /// Adds `layer` on top of the existing layers.
+    pub fn push(&mut self, layer: impl EnvResolver + 'a) -> &mut Self {
+        self.layers.push(OverlayLayer {
+            resolver: Box::new(layer),
+            whiteouts: BTreeSet::new(),
+        });
+        self
+    }
+
+    /// Hides the file or directory `path` in all layers below the topmost one.
+    pub fn whiteout(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        let top = self.layers.last_mut().expect("overlay has a base layer");
+        top.whiteouts.insert(path.into());
+        self
+    }
+
+    /// Whether `path` is hidden by a whiteout in a layer above `layer`
+    fn is_hidden(&self, path: &Path, layer: usize) -> bool {
+        self.layers[layer + 1..].iter().any(|above| {
+            above
+                .whiteouts
+                .iter()
+                .any(|whiteout| path.starts_with(whiteout))
+        })
+    }
+
+    /// Files of all layers, without the ones hidden by whiteouts
+    fn merged(
+        &self,
+        list: impl Fn(&dyn Layer) -> Result<Vec<PathBuf>, std::io::Error>,
+    ) -> Result<Vec<PathBuf>, std::io::Error> {
+        let mut files = BTreeSet::new();
+        for (i, layer) in self.layers.iter().enumerate() {
+            let layer_files = match list(layer.resolver.as_ref()) {
+                Ok(files) => files,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            files.extend(
+                layer_files
+                    .into_iter()
+                    .filter(|path| !self.is_hidden(path, i)),
+            );
+        }
+        Ok(files.into_iter().collect())
+    }
+}
+
+impl EnvResolver for OverlayResolver<'_> {
+    type Reader<'a>
+        = Cursor<Data>
+    where
+        Self: 'a;
+
+    fn read_path(&self, path: &Path) -> Result<Data, std::io::Error> {
+        for (i, layer) in self.layers.iter().enumerate().rev() {
+            if self.is_hidden(path, i) {
+                break;
+            }
+            match layer.resolver.read_path(path) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                result => return result,
+            }
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            path.display().to_string(),
+        ))
+    }
+
+    fn open_path(&self, path: &Path) -> Result<Self::Reader<'_>, std::io::Error> {
+        self.read_path(path).map(Cursor::new)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, std::io::Error> {
+        for (i, layer) in self.layers.iter().enumerate().rev() {
+            if self.is_hidden(path, i) {
+                break;
+            }
+            if layer.resolver.exists(path)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn all_files(&self) -> Result<Vec<PathBuf>, std::io::Error> {
+        self.merged(|layer| layer.all_files())
+    }
+
+    fn list_under(&self, prefix: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+        self.merged(|layer| layer.list_under(prefix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::MemResolver;
+
+    fn read(resolver: &impl EnvResolver, path: &str) -> Option<Vec<u8>> {
+        resolver
+            .read_path(Path::new(path))
+            .ok()
+            .map(|data| data.as_ref().to_vec())
+    }
+
+    #[test]
+    fn first_match_wins() {
+        let game = MemResolver::from_iter([("level0", vec![0]), ("resources.assets", vec![0])]);
+        let patch = MemResolver::from_iter([("level0", vec![1])]);
+        let m0d = MemResolver::from_iter([("level0", vec![2]), ("StreamingAssets/a", vec![2])]);
+
+        let mut overlay = OverlayResolver::new(game);
+        overlay.push(patch).push(m0d);
+
+        assert_eq!(read(&overlay, "level0"), Some(vec![2]));
+        assert_eq!(read(&overlay, "resources.assets"), Some(vec![0]));
+        assert_eq!(read(&overlay, "StreamingAssets/a"), Some(vec![2]));
+        assert_eq!(read(&overlay, "missing"), None);
+        assert_eq!(
+            overlay.all_files().unwrap(),
+            [
+                PathBuf::from("StreamingAssets/a"),
+                PathBuf::from("level0"),
+                PathBuf::from("resources.assets"),
+            ]
+        );
+    }
+
+    #[test]
+    fn whiteouts() {
+        let game = MemResolver::from_iter([
+            ("level0", vec![0]),
+            ("StreamingAssets/aa/a.bundle", vec![0]),
+            ("StreamingAssets/aa/b.bundle", vec![0]),
+        ]);
+        let m0d = MemResolver::from_iter([("StreamingAssets/aa/c.bundle", vec![1])]);
+        let mut overlay = OverlayResolver::new(&game);
+        overlay.push(m0d).whiteout("StreamingAssets/aa");
+        // files above the whiteout stay visible
+        overlay
+            .push(MemResolver::from_iter([(
+                "StreamingAssets/aa/a.bundle",
+                vec![2],
+            )]))
+            .whiteout("level0");
+
+        assert_eq!(read(&overlay, "level0"), None);
+        assert!(!overlay.exists(Path::new("level0")).unwrap());
+        assert_eq!(read(&overlay, "StreamingAssets/aa/a.bundle"), Some(vec![2]));
+        assert_eq!(read(&overlay, "StreamingAssets/aa/b.bundle"), None);
+        assert_eq!(
+            overlay.list_under(Path::new("StreamingAssets/aa")).unwrap(),
+            [
+                PathBuf::from("StreamingAssets/aa/a.bundle"),
+                PathBuf::from("StreamingAssets/aa/c.bundle"),
+            ]
+        );
+    }
+}
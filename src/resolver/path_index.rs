@@ -0,0 +1,132 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// A sorted set of file paths, for listing files under a prefix and checking for existence
+/// in `O(log n)` instead of walking all files.
+///
+/// ```
+/// # use std::path::{Path, PathBuf};
+/// # use rabex_env::resolver::PathIndex;
+/// let index = PathIndex::new(["level0", "StreamingAssets/aa/a.bundle", "StreamingAssets/aab"].map(PathBuf::from));
+/// assert!(index.contains(Path::new("level0")));
+/// assert_eq!(index.list_under(Path::new("StreamingAssets/aa")), [Path::new("StreamingAssets/aa/a.bundle")]);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct PathIndex {
+    files: BTreeSet<PathBuf>,
+}
+
+impl PathIndex {
+    pub fn new(files: impl IntoIterator<Item = PathBuf>) -> Self {
+        PathIndex {
+            files: files.into_iter().collect(),
+        }
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.contains(path)
+    }
+
+    /// Every file under `prefix`, compared by path components like [`Path::starts_with`].
+    pub fn list_under(&self, prefix: &Path) -> Vec<PathBuf> {
+        self.files
+            .range::<Path, _>(prefix..)
+            .take_while(|path| path.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    pub fn files(&self) -> impl ExactSizeIterator<Item = &Path> {
+        self.files.iter().map(PathBuf::as_path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// A [`PathIndex`] built on first use, which can be [invalidated](LazyPathIndex::invalidate)
+/// when the files change, to be rebuilt on the next use.
+#[derive(Debug, Default)]
+pub struct LazyPathIndex {
+    index: RwLock<Option<Arc<PathIndex>>>,
+}
+
+impl LazyPathIndex {
+    /// The index, if it is already built
+    pub fn get(&self) -> Option<Arc<PathIndex>> {
+        self.index.read().unwrap().clone()
+    }
+
+    pub fn get_or_try_build(
+        &self,
+        build: impl FnOnce() -> Result<PathIndex, std::io::Error>,
+    ) -> Result<Arc<PathIndex>, std::io::Error> {
+        if let Some(index) = self.get() {
+            return Ok(index);
+        }
+
+        let mut guard = self.index.write().unwrap();
+        if let Some(index) = &*guard {
+            return Ok(Arc::clone(index));
+        }
+        let index = Arc::new(build()?);
+        *guard = Some(Arc::clone(&index));
+        Ok(index)
+    }
+
+    pub fn invalidate(&self) {
+        *self.index.write().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_under_compares_components() {
+        let index = PathIndex::new(
+            [
+                "StreamingAssets/aa/StandaloneWindows64/a.bundle",
+                "StreamingAssets/aa/catalog.json",
+                "StreamingAssets/aa_old/catalog.json",
+                "StreamingAssets/aa.txt",
+                "level0",
+            ]
+            .map(PathBuf::from),
+        );
+        assert_eq!(
+            index.list_under(Path::new("StreamingAssets/aa")),
+            [
+                Path::new("StreamingAssets/aa/StandaloneWindows64/a.bundle"),
+                Path::new("StreamingAssets/aa/catalog.json"),
+            ]
+        );
+        assert_eq!(index.list_under(Path::new("")).len(), 5);
+        assert_eq!(index.list_under(Path::new("level0")), [Path::new("level0")]);
+        assert!(index.list_under(Path::new("Managed")).is_empty());
+    }
+
+    #[test]
+    fn invalidate() {
+        let lazy = LazyPathIndex::default();
+        assert!(lazy.get().is_none());
+
+        let index = lazy
+            .get_or_try_build(|| Ok(PathIndex::new([PathBuf::from("level0")])))
+            .unwrap();
+        assert!(index.contains(Path::new("level0")));
+        let cached = lazy.get_or_try_build(|| unreachable!()).unwrap();
+        assert!(Arc::ptr_eq(&index, &cached));
+
+        lazy.invalidate();
+        let rebuilt = lazy.get_or_try_build(|| Ok(PathIndex::default())).unwrap();
+        assert!(rebuilt.is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+//! Content-addressed storage for many versions of a game, see [`SnapshotStore`].
+//!
+//! A store is a directory with the layout
+//! ```text
+//! objects/<sha256[..2]>/<sha256[2..]>   file contents, stored once per hash
+//! versions/<version>.json                a SnapshotManifest per imported version
+//! ```
+//! Most files don't change between builds, so every additional version only costs the changed files.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufReader, Cursor, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, ensure};
+use memmap2::Mmap;
+use rabex::files::bundlefile::{BundleFileReader, ExtractionConfig};
+use serde_derive::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::env::Data;
+use crate::resolver::game_files::resource_path;
+use crate::resolver::{DataOrFile, EnvResolver, GameFiles};
+use crate::utils;
+
+/// The files of one version, relative to its data directory, with `/` as separator.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub files: BTreeMap<String, SnapshotFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotFile {
+    /// Hex-encoded SHA-256 of the contents
+    pub hash: String,
+    pub size: u64,
+}
+
+/// Result of [`SnapshotStore::import`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportStats {
+    pub files: usize,
+    /// Files whose contents weren't in the store yet
+    pub new_objects: usize,
+    pub new_bytes: u64,
+}
+
+/// A deduplicating store of game versions on disk.
+///
+/// ```no_run
+/// # use rabex_env::resolver::GameFiles;
+/// # use rabex_env::resolver::snapshot::SnapshotStore;
+/// let store = SnapshotStore::open("snapshots")?;
+/// store.import("1.5.78", &GameFiles::probe("/games/Hollow Knight")?)?;
+/// let resolver = store.resolver("1.5.78")?;
+/// # anyhow::Ok(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    root: PathBuf,
+}
+
+impl SnapshotStore {
+    /// Opens the store at `root`, creating it if it doesn't exist yet.
+    pub fn open(root: impl Into<PathBuf>) -> Result<SnapshotStore> {
+        let root = root.into();
+        std::fs::create_dir_all(root.join("objects"))
+            .and_then(|()| std::fs::create_dir_all(root.join("versions")))
+            .with_context(|| format!("Could not create snapshot store at '{}'", root.display()))?;
+        Ok(SnapshotStore { root })
+    }
+
+    /// Names of all imported versions, sorted.
+    pub fn versions(&self) -> Result<Vec<String>> {
+        let mut versions = Vec::new();
+        for entry in std::fs::read_dir(self.root.join("versions"))? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json")
+                && let Some(name) = path.file_stem().and_then(|name| name.to_str())
+            {
+                versions.push(name.to_owned());
+            }
+        }
+        versions.sort();
+        Ok(versions)
+    }
+
+    pub fn manifest(&self, version: &str) -> Result<SnapshotManifest> {
+        let path = self.manifest_path(version)?;
+        let manifest = std::fs::read(&path)
+            .with_context(|| format!("Version '{version}' is not in the snapshot store"))?;
+        serde_json::from_slice(&manifest)
+            .with_context(|| format!("Invalid snapshot manifest '{}'", path.display()))
+    }
+
+    /// Stores all files of the data directory of `game_files` as `version`, replacing an existing version of that name.
+    ///
+    /// Files are hashed in parallel, and only contents not already in the store are written.
+    pub fn import(&self, version: &str, game_files: &GameFiles) -> Result<ImportStats> {
+        let manifest_path = self.manifest_path(version)?;
+        let data_dir = &game_files.game_dir;
+
+        let mut paths = Vec::new();
+        for entry in WalkDir::new(data_dir) {
+            let entry = entry?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            let path = entry.path().strip_prefix(data_dir)?;
+            let name = path
+                .components()
+                .map(|c| c.as_os_str().to_str())
+                .collect::<Option<Vec<_>>>()
+                .with_context(|| format!("'{}' is not a valid utf8 path", path.display()))?
+                .join("/");
+            paths.push((name, entry.into_path()));
+        }
+
+        let files = utils::par_fold_reduce(paths, |acc: &mut Vec<_>, (name, path)| {
+            let data = unsafe { Mmap::map(&File::open(&path)?)? };
+            let hash = utils::content_hash(&data);
+            let is_new = self.write_object(&hash, &data)?;
+            let size = data.len() as u64;
+            acc.push((name, SnapshotFile { hash, size }, is_new));
+            Ok(())
+        })?;
+
+        let mut stats = ImportStats {
+            files: files.len(),
+            ..Default::default()
+        };
+        let mut manifest = SnapshotManifest::default();
+        let mut new_objects = BTreeSet::new();
+        for (name, file, is_new) in files {
+            // the same contents may be written by two threads, count them once
+            if is_new && new_objects.insert(file.hash.clone()) {
+                stats.new_objects += 1;
+                stats.new_bytes += file.size;
+            }
+            manifest.files.insert(name, file);
+        }
+
+        write_atomic(&manifest_path, &serde_json::to_vec_pretty(&manifest)?)?;
+        Ok(stats)
+    }
+
+    /// An [`EnvResolver`] serving the files of `version`.
+    pub fn resolver(&self, version: &str) -> Result<SnapshotResolver> {
+        let manifest = self.manifest(version)?;
+        let files: BTreeMap<PathBuf, SnapshotFile> = manifest
+            .files
+            .into_iter()
+            .map(|(name, file)| (PathBuf::from(name), file))
+            .collect();
+
+        let packed = match files.get(Path::new("data.unity3d")) {
+            Some(file) => {
+                let mmap = unsafe { Mmap::map(&File::open(self.object_path(&file.hash))?)? };
+                let bundle = BundleFileReader::from_reader(
+                    Cursor::new(Data::Mmap(mmap)),
+                    &ExtractionConfig::default(),
+                )?;
+                Some(Box::new(bundle))
+            }
+            None => None,
+        };
+
+        Ok(SnapshotResolver {
+            store: self.clone(),
+            files,
+            packed,
+        })
+    }
+
+    fn manifest_path(&self, version: &str) -> Result<PathBuf> {
+        ensure!(
+            !version.is_empty() && !version.starts_with('.') && !version.contains(['/', '\\', ':']),
+            "Invalid snapshot version name '{version}'"
+        );
+        Ok(self.root.join("versions").join(format!("{version}.json")))
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        self.root.join("objects").join(&hash[..2]).join(&hash[2..])
+    }
+
+    /// Returns whether the object was new
+    fn write_object(&self, hash: &str, data: &[u8]) -> Result<bool> {
+        let path = self.object_path(hash);
+        if path.exists() {
+            return Ok(false);
+        }
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        write_atomic(&path, data)?;
+        Ok(true)
+    }
+}
+
+/// Writes to a temporary file first, so readers never see partially written files.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = path.with_extension(format!(
+        "tmp{}-{}",
+        std::process::id(),
+        rayon::current_thread_index().unwrap_or_default()
+    ));
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Could not write '{}'", path.display()))?;
+    Ok(())
+}
+
+/// An [`EnvResolver`] for one version of a [`SnapshotStore`], created by [`SnapshotStore::resolver`].
+///
+/// Behaves like the [`GameFiles`] the version was imported from, including packed `data.unity3d` builds.
+pub struct SnapshotResolver {
+    store: SnapshotStore,
+    files: BTreeMap<PathBuf, SnapshotFile>,
+    packed: Option<Box<BundleFileReader<Cursor<Data>>>>,
+}
+
+impl std::fmt::Debug for SnapshotResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SnapshotResolver")
+            .field("store", &self.store)
+            .field("files", &self.files.len())
+            .finish_non_exhaustive()
+    }
+}
+
+enum Resolved {
+    Object(PathBuf),
+    Packed(Vec<u8>),
+}
+
+impl SnapshotResolver {
+    fn resolve(&self, path: &Path) -> Result<Resolved, std::io::Error> {
+        let resource_path = resource_path(path);
+        let path = resource_path.as_deref().unwrap_or(path);
+        if let Some(file) = self.files.get(path) {
+            return Ok(Resolved::Object(self.store.object_path(&file.hash)));
+        }
+
+        let packed = match (&self.packed, resource_path.is_none()) {
+            (Some(bundle), true) => {
+                let path_str = path
+                    .to_str()
+                    .ok_or_else(|| std::io::Error::other("non-utf8 string"))?;
+                bundle.read_at(path_str)?
+            }
+            _ => None,
+        };
+        packed.map(Resolved::Packed).ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("File '{}' not found in snapshot", path.display()),
+            )
+        })
+    }
+}
+
+impl EnvResolver for SnapshotResolver {
+    type Reader<'a> = DataOrFile;
+
+    fn read_path(&self, path: &Path) -> Result<Data, std::io::Error> {
+        match self.resolve(path)? {
+            Resolved::Object(path) => {
+                let mmap = unsafe { Mmap::map(&File::open(path)?)? };
+                Ok(Data::Mmap(mmap))
+            }
+            Resolved::Packed(data) => Ok(Data::InMemory(data)),
+        }
+    }
+
+    fn open_path(&self, path: &Path) -> Result<Self::Reader<'_>, std::io::Error> {
+        match self.resolve(path)? {
+            Resolved::Object(path) => Ok(DataOrFile::File(BufReader::new(File::open(path)?))),
+            Resolved::Packed(data) => Ok(DataOrFile::Data(Cursor::new(Data::InMemory(data)))),
+        }
+    }
+
+    fn all_files(&self) -> Result<Vec<PathBuf>, std::io::Error> {
+        let mut files: BTreeSet<PathBuf> = self.files.keys().cloned().collect();
+        if let Some(bundle) = &self.packed {
+            files.extend(bundle.files().iter().map(|file| PathBuf::from(&file.path)));
+        }
+        Ok(files.into_iter().collect())
+    }
+
+    fn list_under(&self, prefix: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+        Ok(self
+            .files
+            .range::<Path, _>(prefix..)
+            .map(|(path, _)| path)
+            .take_while(|path| path.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
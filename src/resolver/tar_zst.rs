@@ -0,0 +1,213 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+
+use crate::env::Data;
+use crate::resolver::EnvResolver;
+use crate::resolver::archive::{ArchiveIndex, PACKED_LEVELS, PackedLevels};
+
+/// An [`EnvResolver`] reading a game from a `.tar.zst` archive, without extracting it to disk.
+///
+/// The unity data directory is detected like in [`GameFiles::probe_dir`](super::GameFiles::probe_dir).
+/// A zstd stream can't be seeked into, so the whole archive is decompressed into memory once
+/// when it is opened, and kept there for as long as the resolver lives.
+/// Files are served as shared views into it, without copying.
+/// The level files of packed builds are extracted from `data.unity3d` on every read.
+pub struct TarZstResolver {
+    tar: Arc<Vec<u8>>,
+    index: ArchiveIndex<Range<usize>>,
+    packed: PackedLevels,
+}
+
+impl std::fmt::Debug for TarZstResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TarZstResolver")
+            .field("data_dir", &self.index.data_dir)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TarZstResolver {
+    pub fn open(path: impl AsRef<Path>) -> Result<TarZstResolver> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("Could not open '{}'", path.display()))?;
+        let tar = zstd::decode_all(BufReader::new(file))
+            .with_context(|| format!("Could not decompress '{}'", path.display()))?;
+        TarZstResolver::from_tar(path, tar)
+    }
+
+    /// Reads an already decompressed tar archive. `name` is only used for error messages.
+    pub fn from_tar(name: &Path, tar: Vec<u8>) -> Result<TarZstResolver> {
+        let mut files = Vec::new();
+        {
+            let mut archive = tar::Archive::new(Cursor::new(tar.as_slice()));
+            for entry in archive
+                .entries_with_seek()
+                .with_context(|| format!("Could not read tar archive '{}'", name.display()))?
+            {
+                let entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let start = entry.raw_file_position() as usize;
+                let end = start + entry.size() as usize;
+                files.push((entry.path()?.into_owned(), start..end));
+            }
+        }
+        let index = ArchiveIndex::new(name, files)?;
+        let tar = Arc::new(tar);
+
+        let packed = index
+            .get(Path::new(PACKED_LEVELS))
+            .ok()
+            .map(|range| Data::Shared(Arc::clone(&tar), range.clone()));
+        let packed = PackedLevels::new(name, packed)?;
+
+        Ok(TarZstResolver { tar, index, packed })
+    }
+
+    /// The unity data directory inside the archive
+    pub fn data_dir(&self) -> &Path {
+        &self.index.data_dir
+    }
+
+    fn entry_range(&self, path: &Path) -> Result<Range<usize>, std::io::Error> {
+        let range = self.index.get(path)?;
+        match range.end <= self.tar.len() {
+            true => Ok(range.clone()),
+            false => Err(std::io::Error::other("tar entry out of bounds")),
+        }
+    }
+}
+
+impl EnvResolver for TarZstResolver {
+    type Reader<'a>
+        = Cursor<Cow<'a, [u8]>>
+    where
+        Self: 'a;
+
+    fn read_path(&self, path: &Path) -> Result<Data, std::io::Error> {
+        match self.entry_range(path) {
+            Ok(range) => Ok(Data::Shared(Arc::clone(&self.tar), range)),
+            Err(e) => self.packed.read_missing(path, e).map(Data::InMemory),
+        }
+    }
+
+    fn open_path(&self, path: &Path) -> Result<Self::Reader<'_>, std::io::Error> {
+        let data = match self.entry_range(path) {
+            Ok(range) => Cow::Borrowed(&self.tar[range]),
+            Err(e) => Cow::Owned(self.packed.read_missing(path, e)?),
+        };
+        Ok(Cursor::new(data))
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, std::io::Error> {
+        Ok(self.index.get(path).is_ok() || self.packed.contains(path))
+    }
+
+    fn all_files(&self) -> Result<Vec<PathBuf>, std::io::Error> {
+        Ok(self.packed.all_files(self.index.all_files()))
+    }
+
+    fn list_under(&self, prefix: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+        Ok(self
+            .packed
+            .list_under(prefix, self.index.list_under(prefix)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rabex::files::bundlefile::CompressionType;
+    use rabex::files::bundlefile::builder::BundleFileBuilder;
+
+    use super::*;
+
+    fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for &(path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            builder.append_data(&mut header, path, data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn reads_entries() {
+        let tar = tar(&[
+            ("Game.app/Contents/Info.plist", &b"plist"[..]),
+            ("Game.app/Contents/Resources/Data/level0", &b"level0"[..]),
+            (
+                "Game.app/Contents/Resources/Data/Resources/unity default resources",
+                &b"res"[..],
+            ),
+        ]);
+
+        let resolver = TarZstResolver::from_tar(Path::new("game.tar"), tar).unwrap();
+        assert_eq!(
+            resolver.data_dir(),
+            Path::new("Game.app/Contents/Resources/Data")
+        );
+        assert_eq!(resolver.all_files().unwrap().len(), 2);
+        let level0 = resolver.read_path(Path::new("level0")).unwrap();
+        assert!(matches!(level0, Data::Shared(..)));
+        assert_eq!(level0.as_ref(), b"level0");
+        assert_eq!(
+            resolver
+                .open_path(Path::new("Library/unity default resources"))
+                .unwrap()
+                .into_inner()
+                .as_ref(),
+            b"res"
+        );
+    }
+
+    #[test]
+    fn reads_packed_levels() {
+        let mut bundle = BundleFileBuilder::unityfs(7, &"6000.0.0f1".parse().unwrap());
+        bundle.add_file("globalgamemanagers", &b"ggm"[..]).unwrap();
+        bundle.add_file("level0", &b"packed level0"[..]).unwrap();
+        let mut data = Cursor::new(Vec::new());
+        bundle.write(&mut data, CompressionType::None).unwrap();
+
+        let tar = tar(&[
+            ("Game/Game_Data/data.unity3d", data.into_inner().as_slice()),
+            ("Game/Game_Data/level0", &b"level0"[..]),
+        ]);
+        let resolver = TarZstResolver::from_tar(Path::new("game.tar"), tar).unwrap();
+
+        assert_eq!(
+            resolver.all_files().unwrap(),
+            [
+                PathBuf::from("data.unity3d"),
+                PathBuf::from("globalgamemanagers"),
+                PathBuf::from("level0"),
+            ]
+        );
+        assert_eq!(
+            resolver.list_under(Path::new("")).unwrap(),
+            resolver.all_files().unwrap()
+        );
+        assert!(resolver.exists(Path::new("globalgamemanagers")).unwrap());
+        assert_eq!(
+            resolver
+                .read_path(Path::new("globalgamemanagers"))
+                .unwrap()
+                .as_ref(),
+            b"ggm"
+        );
+        // files of the archive shadow packed entries
+        assert_eq!(
+            resolver.read_path(Path::new("level0")).unwrap().as_ref(),
+            b"level0"
+        );
+        assert!(resolver.open_path(Path::new("level1")).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ::zip::{CompressionMethod, ZipArchive};
+use anyhow::{Context, Result};
+use memmap2::Mmap;
+
+use crate::env::Data;
+use crate::resolver::EnvResolver;
+use crate::resolver::archive::{ArchiveIndex, PACKED_LEVELS, PackedLevels};
+
+/// An [`EnvResolver`] reading a game from a zip archive, without extracting it.
+///
+/// The unity data directory is detected like in [`GameFiles::probe_dir`](super::GameFiles::probe_dir).
+/// Stored (uncompressed) files are served straight from the memory-mapped archive,
+/// compressed files are inflated on every read.
+/// The level files of packed builds are extracted from `data.unity3d` on every read.
+pub struct ZipResolver {
+    data: SharedData,
+    archive: ZipArchive<Cursor<SharedData>>,
+    index: ArchiveIndex<ZipEntry>,
+    packed: PackedLevels,
+}
+
+#[derive(Debug)]
+struct ZipEntry {
+    index: usize,
+    size: u64,
+    /// Offset of the file contents in the archive, if they are stored uncompressed
+    stored_at: Option<u64>,
+}
+
+#[derive(Clone)]
+struct SharedData(Arc<Data>);
+
+impl AsRef<[u8]> for SharedData {
+    fn as_ref(&self) -> &[u8] {
+        (*self.0).as_ref()
+    }
+}
+
+impl std::fmt::Debug for ZipResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ZipResolver")
+            .field("data_dir", &self.index.data_dir)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ZipResolver {
+    pub fn open(path: impl AsRef<Path>) -> Result<ZipResolver> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("Could not open '{}'", path.display()))?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        ZipResolver::from_data(path, Data::Mmap(mmap))
+    }
+
+    /// `name` is only used for error messages
+    pub fn from_data(name: &Path, data: Data) -> Result<ZipResolver> {
+        let data = SharedData(Arc::new(data));
+        let mut archive = ZipArchive::new(Cursor::new(data.clone()))
+            .with_context(|| format!("Could not read zip archive '{}'", name.display()))?;
+
+        let mut files = Vec::with_capacity(archive.len());
+        for index in 0..archive.len() {
+            let file = archive.by_index(index)?;
+            if file.is_dir() {
+                continue;
+            }
+            let Some(path) = file.enclosed_name() else {
+                continue;
+            };
+            let stored_at =
+                (file.compression() == CompressionMethod::Stored).then(|| file.data_start());
+            files.push((
+                path,
+                ZipEntry {
+                    index,
+                    size: file.size(),
+                    stored_at,
+                },
+            ));
+        }
+        let index = ArchiveIndex::new(name, files)?;
+
+        let mut resolver = ZipResolver {
+            data,
+            archive,
+            index,
+            packed: PackedLevels::new(name, None)?,
+        };
+        if let Ok(entry) = resolver.index.get(Path::new(PACKED_LEVELS)) {
+            let packed = resolver.read_entry(entry)?.into_owned();
+            resolver.packed = PackedLevels::new(name, Some(Data::InMemory(packed)))?;
+        }
+        Ok(resolver)
+    }
+
+    /// The unity data directory inside the archive
+    pub fn data_dir(&self) -> &Path {
+        &self.index.data_dir
+    }
+
+    fn read_entry(&self, entry: &ZipEntry) -> Result<Cow<'_, [u8]>, std::io::Error> {
+        if let Some(start) = entry.stored_at {
+            let start = start as usize;
+            let data = self
+                .data
+                .as_ref()
+                .get(start..start + entry.size as usize)
+                .ok_or_else(|| std::io::Error::other("stored zip entry out of bounds"))?;
+            return Ok(Cow::Borrowed(data));
+        }
+
+        // the central directory is shared between clones
+        let mut archive = self.archive.clone();
+        let mut file = archive.by_index(entry.index)?;
+        let mut data = Vec::with_capacity(entry.size as usize);
+        file.read_to_end(&mut data)?;
+        Ok(Cow::Owned(data))
+    }
+}
+
+impl EnvResolver for ZipResolver {
+    type Reader<'a>
+        = Cursor<Cow<'a, [u8]>>
+    where
+        Self: 'a;
+
+    fn read_path(&self, path: &Path) -> Result<Data, std::io::Error> {
+        match self.index.get(path) {
+            Ok(entry) => Ok(Data::InMemory(self.read_entry(entry)?.into_owned())),
+            Err(e) => self.packed.read_missing(path, e).map(Data::InMemory),
+        }
+    }
+
+    fn open_path(&self, path: &Path) -> Result<Self::Reader<'_>, std::io::Error> {
+        let data = match self.index.get(path) {
+            Ok(entry) => self.read_entry(entry)?,
+            Err(e) => Cow::Owned(self.packed.read_missing(path, e)?),
+        };
+        Ok(Cursor::new(data))
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, std::io::Error> {
+        Ok(self.index.get(path).is_ok() || self.packed.contains(path))
+    }
+
+    fn all_files(&self) -> Result<Vec<PathBuf>, std::io::Error> {
+        Ok(self.packed.all_files(self.index.all_files()))
+    }
+
+    fn list_under(&self, prefix: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+        Ok(self
+            .packed
+            .list_under(prefix, self.index.list_under(prefix)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use ::zip::ZipWriter;
+    use ::zip::write::SimpleFileOptions;
+
+    use super::*;
+
+    #[test]
+    fn stored_and_deflated() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        writer.add_directory("Game/Game_Data", stored).unwrap();
+        writer.start_file("Game/Game_Data/level0", stored).unwrap();
+        writer.write_all(b"level0").unwrap();
+        writer
+            .start_file("Game/Game_Data/StreamingAssets/a.bundle", deflated)
+            .unwrap();
+        writer.write_all(&[1; 1000]).unwrap();
+        writer.start_file("Game/Game.exe", deflated).unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        let zip = ZipResolver::from_data(Path::new("game.zip"), Data::InMemory(data)).unwrap();
+        assert_eq!(zip.data_dir(), Path::new("Game/Game_Data"));
+        assert_eq!(
+            zip.all_files().unwrap(),
+            [
+                PathBuf::from("StreamingAssets/a.bundle"),
+                PathBuf::from("level0")
+            ]
+        );
+
+        let level0 = zip.open_path(Path::new("level0")).unwrap();
+        assert!(matches!(level0.get_ref(), Cow::Borrowed(b"level0")));
+        let bundle = zip
+            .read_path(Path::new("StreamingAssets/a.bundle"))
+            .unwrap();
+        assert_eq!(bundle.as_ref(), [1; 1000]);
+        assert!(zip.read_path(Path::new("level1")).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+//! Iterate over every serialized file and object in a game.
+//!
+//! ```no_run
+//! # use rabex_env::Environment;
+//! # use rabex_env::scan::ObjectFilter;
+//! # use rabex::objects::ClassId;
+//! # fn run(env: &Environment) -> anyhow::Result<()> {
+//! let filter = ObjectFilter::class(ClassId::Sprite);
+//! let sprites = env.fold_objects(&filter, true, |count: &mut usize, _path, _sprite| {
+//!     *count += 1;
+//!     Ok(())
+//! })?;
+//! println!("{sprites} sprites");
+//! # Ok(())
+//! # }
+//! ```
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use rabex::files::serializedfile::ObjectRef;
+use rabex::objects::{ClassId, PPtr};
+use rabex::typetree::TypeTreeProvider;
+use rustc_hash::FxHashMap;
+
+use crate::Environment;
+use crate::addressables::ArchivePath;
+use crate::handle::script_filter::ScriptFilter;
+use crate::handle::{ObjectRefHandle, SerializedFileHandle};
+use crate::resolver::EnvResolver;
+use crate::unity::types::MonoScript;
+use crate::utils::{self, Merge};
+
+/// Restricts which objects are yielded by [`Environment::objects`] and [`Environment::fold_objects`].
+#[derive(Default, Clone, Copy)]
+pub struct ObjectFilter<'f> {
+    classes: ClassFilter<'f>,
+    script: Option<&'f (dyn ScriptFilter + Sync)>,
+}
+
+#[derive(Default, Clone, Copy)]
+enum ClassFilter<'f> {
+    #[default]
+    Any,
+    One(ClassId),
+    Many(&'f [ClassId]),
+}
+
+impl<'f> ObjectFilter<'f> {
+    /// Matches every object.
+    pub fn all() -> Self {
+        ObjectFilter::default()
+    }
+
+    /// Matches objects of a single class.
+    pub fn class(class_id: ClassId) -> Self {
+        ObjectFilter {
+            classes: ClassFilter::One(class_id),
+            script: None,
+        }
+    }
+
+    /// Matches objects of any of the given classes.
+    pub fn classes(class_ids: &'f [ClassId]) -> Self {
+        ObjectFilter {
+            classes: ClassFilter::Many(class_ids),
+            script: None,
+        }
+    }
+
+    /// Matches `MonoBehaviour`s whose script matches the filter.
+    pub fn script(filter: &'f (dyn ScriptFilter + Sync)) -> Self {
+        ObjectFilter {
+            classes: ClassFilter::One(ClassId::MonoBehaviour),
+            script: Some(filter),
+        }
+    }
+
+    fn matches_class(&self, class_id: ClassId) -> bool {
+        match self.classes {
+            ClassFilter::Any => true,
+            ClassFilter::One(expected) => class_id == expected,
+            ClassFilter::Many(class_ids) => class_ids.contains(&class_id),
+        }
+    }
+}
+
+impl<R: EnvResolver, P: TypeTreeProvider> Environment<R, P> {
+    /// Paths of every serialized file in the game, which can be passed to [`Environment::load_serialized`].
+    ///
+    /// Includes built-in files (unpacked or inside `data.unity3d`) by their relative path
+    /// and the serialized files of addressables bundles by their [`ArchivePath`].
+    /// Plain assetbundles are not included, as finding their serialized files reads the header of every bundle,
+    /// see [`Environment::bundle_index`].
+    pub fn serialized_paths(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = self.game_files.serialized_files()?;
+
+        if let Some(addressables) = self.addressables()? {
+            paths.extend(bundle_serialized_paths(&addressables.bundle_to_cab));
+        }
+
+        Ok(paths)
+    }
+
+    fn load_for_scan(&self, path: &Path) -> Result<SerializedFileHandle<'_, R, P>> {
+        self.load_serialized(path)
+            .with_context(|| format!("Failed to load {}", path.display()))
+    }
+}
+
+impl<R: EnvResolver, P: TypeTreeProvider + Sync> Environment<R, P> {
+    /// Iterates every object matching `filter` in the game.
+    ///
+    /// Serialized files are only loaded once the iterator reaches them.
+    /// With `parallel` set, they are loaded in batches on the rayon thread pool.
+    pub fn objects<'a>(
+        &'a self,
+        filter: &ObjectFilter<'a>,
+        parallel: bool,
+    ) -> Result<impl Iterator<Item = Result<ObjectRefHandle<'a, (), R, P>>> + 'a> {
+        use rayon::prelude::*;
+
+        let filter = *filter;
+        let paths = self.serialized_paths()?;
+        let batch_size = match parallel {
+            true => rayon::current_num_threads(),
+            false => 1,
+        };
+        let batches: Vec<Vec<PathBuf>> = paths.chunks(batch_size).map(<[_]>::to_vec).collect();
+
+        Ok(batches
+            .into_iter()
+            .flat_map(move |batch| -> Vec<_> {
+                match parallel {
+                    true => batch
+                        .par_iter()
+                        .map(|path| self.load_for_scan(path))
+                        .collect(),
+                    false => batch.iter().map(|path| self.load_for_scan(path)).collect(),
+                }
+            })
+            .flat_map(move |file| {
+                let (objects, error) = match file.and_then(|file| matching_objects(file, filter)) {
+                    Ok(objects) => (Some(objects), None),
+                    Err(e) => (None, Some(Err(e))),
+                };
+                error
+                    .into_iter()
+                    .chain(objects.into_iter().flatten().map(Ok))
+            }))
+    }
+
+    /// Calls `f` for every object matching `filter` in the game, together with the path of its file,
+    /// and merges the accumulated results.
+    ///
+    /// With `parallel` set, files are processed on the rayon thread pool.
+    pub fn fold_objects<Acc>(
+        &self,
+        filter: &ObjectFilter<'_>,
+        parallel: bool,
+        f: impl Fn(&mut Acc, &Path, ObjectRefHandle<'_, (), R, P>) -> Result<()> + Send + Sync,
+    ) -> Result<Acc>
+    where
+        Acc: Merge + Default + Send + Sync,
+    {
+        let paths = self.serialized_paths()?;
+        let fold_file = |acc: &mut Acc, path: PathBuf| -> Result<()> {
+            let file = self.load_for_scan(&path)?;
+            for object in matching_objects(file, *filter)? {
+                f(acc, &path, object)?;
+            }
+            Ok(())
+        };
+
+        match parallel {
+            true => utils::par_fold_reduce(paths, fold_file),
+            false => utils::seq_fold_reduce(paths, fold_file),
+        }
+    }
+}
+
+/// The objects of `file` matching `filter`, without reading more than the script types up front.
+fn matching_objects<'a, R: EnvResolver, P: TypeTreeProvider>(
+    file: SerializedFileHandle<'a, R, P>,
+    filter: ObjectFilter<'a>,
+) -> Result<impl Iterator<Item = ObjectRefHandle<'a, (), R, P>> + 'a> {
+    let scripts = match filter.script {
+        Some(script_filter) => Some(matching_scripts(&file, script_filter)?),
+        None => None,
+    };
+
+    let serialized = file.file;
+    Ok(serialized
+        .objects()
+        .filter(move |info| filter.matches_class(info.m_ClassID))
+        .filter(move |info| match &scripts {
+            Some(scripts) => serialized
+                .script_type(info)
+                .is_some_and(|script| scripts.contains(&script)),
+            None => true,
+        })
+        .map(move |info| {
+            let tt = serialized.get_typetree_for(info, &file.env.tpk);
+            ObjectRefHandle::new(ObjectRef::new(serialized, info, tt), file.reborrow())
+        }))
+}
+
+fn matching_scripts<R: EnvResolver, P: TypeTreeProvider>(
+    file: &SerializedFileHandle<'_, R, P>,
+    filter: &(dyn ScriptFilter + Sync),
+) -> Result<Vec<PPtr>> {
+    let mut scripts = Vec::new();
+    for &script_type in file.file.m_ScriptTypes.as_deref().unwrap_or_default() {
+        let script = PPtr::from(script_type);
+        let script_data =
+            file.env
+                .deref_read(script.typed::<MonoScript>(), file.file, &mut file.reader())?;
+        if filter.matches(&script_data) {
+            scripts.push(script);
+        }
+    }
+    Ok(scripts)
+}
+
+fn bundle_serialized_paths(
+    bundle_to_cab: &FxHashMap<PathBuf, Vec<String>>,
+) -> impl Iterator<Item = PathBuf> {
+    bundle_to_cab
+        .values()
+        .filter_map(|files| {
+            let identifier = files.iter().find(|file| is_bundle_identifier(file))?;
+            let paths = files
+                .iter()
+                .filter(|file| is_bundle_serialized_file(file))
+                .map(move |file| PathBuf::from(ArchivePath::new(identifier, file)));
+            Some(paths)
+        })
+        .flatten()
+}
+
+/// The main serialized file of a bundle has no extension, e.g. `CAB-abcd` or `BuildPlayer-Scene`.
+pub(crate) fn is_bundle_identifier(file: &str) -> bool {
+    Path::new(file).extension().is_none()
+}
+
+/// Serialized files in a bundle, excluding resource files like `.resS` and `.resource`.
+pub(crate) fn is_bundle_serialized_file(file: &str) -> bool {
+    match Path::new(file).extension() {
+        None => true,
+        Some(ext) => ext == "sharedAssets",
+    }
+}
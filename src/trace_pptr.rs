@@ -1,5 +1,9 @@
 //! Find and replace PPtr references in arbitrary objects
-use anyhow::Result;
+//!
+//! Objects with `[SerializeReference]` fields store the referenced values in a `ManagedReferencesRegistry`,
+//! whose layout depends on the concrete types. Their typetrees are looked up through a [`ManagedReferenceResolver`],
+//! usually the [`Environment`](crate::Environment).
+use anyhow::{Result, bail};
 use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
 use rabex::files::serializedfile::Endianness;
 use rabex::objects::PPtr;
@@ -8,24 +12,69 @@ use rabex::typetree::TypeTreeNode;
 use rustc_hash::FxHashMap;
 use std::io::{Cursor, Read, Seek, SeekFrom};
 
+/// Resolves the typetrees of classes stored in `[SerializeReference]` fields.
+pub trait ManagedReferenceResolver {
+    /// Returns the typetree of `namespace.class` in `assembly` (which may lack the `.dll` extension),
+    /// or `None` if the type does not exist.
+    fn resolve(
+        &self,
+        assembly: &str,
+        namespace: &str,
+        class: &str,
+    ) -> Result<Option<&TypeTreeNode>>;
+}
+
+/// Fails on every managed reference.
+pub struct NoManagedReferences;
+
+impl ManagedReferenceResolver for NoManagedReferences {
+    fn resolve(
+        &self,
+        assembly: &str,
+        namespace: &str,
+        class: &str,
+    ) -> Result<Option<&TypeTreeNode>> {
+        bail!(
+            "cannot resolve managed reference type {namespace}.{class} ({assembly}) without assemblies"
+        )
+    }
+}
+
 pub fn trace_pptrs_endianned(
     tt: &TypeTreeNode,
     reader: &mut (impl Read + Seek),
     endianness: Endianness,
+) -> Result<Vec<PPtr>> {
+    trace_pptrs_endianned_with(tt, reader, endianness, &NoManagedReferences)
+}
+
+pub fn trace_pptrs_endianned_with(
+    tt: &TypeTreeNode,
+    reader: &mut (impl Read + Seek),
+    endianness: Endianness,
+    resolver: &dyn ManagedReferenceResolver,
 ) -> Result<Vec<PPtr>> {
     match endianness {
-        Endianness::Little => trace_pptrs::<LittleEndian>(tt, reader),
-        Endianness::Big => trace_pptrs::<BigEndian>(tt, reader),
+        Endianness::Little => trace_pptrs_with::<LittleEndian>(tt, reader, resolver),
+        Endianness::Big => trace_pptrs_with::<BigEndian>(tt, reader, resolver),
     }
 }
 
-#[inline(never)]
 pub fn trace_pptrs<B: ByteOrder>(
     tt: &TypeTreeNode,
     reader: &mut (impl Read + Seek),
+) -> Result<Vec<PPtr>> {
+    trace_pptrs_with::<B>(tt, reader, &NoManagedReferences)
+}
+
+#[inline(never)]
+pub fn trace_pptrs_with<B: ByteOrder>(
+    tt: &TypeTreeNode,
+    reader: &mut (impl Read + Seek),
+    resolver: &dyn ManagedReferenceResolver,
 ) -> Result<Vec<PPtr>> {
     let mut pptrs = Vec::new();
-    visit::<_, B>(reader, tt, &mut |tt, reader| {
+    visit_with::<_, B>(reader, tt, resolver, &mut |tt, reader| {
         if tt.m_Type.starts_with("PPtr<") && tt.m_Name != "m_Father" {
             let file_id = reader.read_i32::<B>()?;
             let path_id = reader.read_i64::<B>()?;
@@ -49,21 +98,47 @@ pub fn trace_pptrs<B: ByteOrder>(
     Ok(pptrs)
 }
 
-#[inline(never)]
 pub fn replace_pptrs_inplace_endianed(
     value: &mut [u8],
     ty: &TypeTreeNode,
     path_id_remap: &FxHashMap<PathId, PathId>,
     file_id_remap: &FxHashMap<FileId, FileId>,
     endianness: Endianness,
+) -> Result<()> {
+    replace_pptrs_inplace_endianed_with(
+        value,
+        ty,
+        path_id_remap,
+        file_id_remap,
+        endianness,
+        &NoManagedReferences,
+    )
+}
+
+#[inline(never)]
+pub fn replace_pptrs_inplace_endianed_with(
+    value: &mut [u8],
+    ty: &TypeTreeNode,
+    path_id_remap: &FxHashMap<PathId, PathId>,
+    file_id_remap: &FxHashMap<FileId, FileId>,
+    endianness: Endianness,
+    resolver: &dyn ManagedReferenceResolver,
 ) -> Result<()> {
     match endianness {
-        Endianness::Little => {
-            replace_pptrs_inplace::<LittleEndian>(value, ty, path_id_remap, file_id_remap)
-        }
-        Endianness::Big => {
-            replace_pptrs_inplace::<BigEndian>(value, ty, path_id_remap, file_id_remap)
-        }
+        Endianness::Little => replace_pptrs_inplace_with::<LittleEndian>(
+            value,
+            ty,
+            path_id_remap,
+            file_id_remap,
+            resolver,
+        ),
+        Endianness::Big => replace_pptrs_inplace_with::<BigEndian>(
+            value,
+            ty,
+            path_id_remap,
+            file_id_remap,
+            resolver,
+        ),
     }
 }
 
@@ -73,7 +148,23 @@ pub fn replace_pptrs_inplace<B: ByteOrder>(
     path_id_remap: &FxHashMap<PathId, PathId>,
     file_id_remap: &FxHashMap<FileId, FileId>,
 ) -> Result<()> {
-    visit::<_, B>(&mut Cursor::new(value), ty, &mut |tt, reader| {
+    replace_pptrs_inplace_with::<B>(
+        value,
+        ty,
+        path_id_remap,
+        file_id_remap,
+        &NoManagedReferences,
+    )
+}
+
+pub fn replace_pptrs_inplace_with<B: ByteOrder>(
+    value: &mut [u8],
+    ty: &TypeTreeNode,
+    path_id_remap: &FxHashMap<PathId, PathId>,
+    file_id_remap: &FxHashMap<FileId, FileId>,
+    resolver: &dyn ManagedReferenceResolver,
+) -> Result<()> {
+    visit_with::<_, B>(&mut Cursor::new(value), ty, resolver, &mut |tt, reader| {
         if tt.m_Type.starts_with("PPtr<") {
             let pos = reader.position() as usize;
 
@@ -105,6 +196,21 @@ pub fn visit<R, B>(
     tt: &TypeTreeNode,
     f: &mut impl FnMut(&TypeTreeNode, &mut R) -> Result<bool, std::io::Error>,
 ) -> Result<(), std::io::Error>
+where
+    R: Read + Seek,
+    B: ByteOrder,
+{
+    visit_with::<R, B>(reader, tt, &NoManagedReferences, f)
+}
+
+/// Walks the value of type `tt` in `reader`, calling `f` on every node before descending into it.
+/// When `f` returns `true`, it must have consumed the node's value and its children are skipped.
+pub fn visit_with<R, B>(
+    reader: &mut R,
+    tt: &TypeTreeNode,
+    resolver: &dyn ManagedReferenceResolver,
+    f: &mut impl FnMut(&TypeTreeNode, &mut R) -> Result<bool, std::io::Error>,
+) -> Result<(), std::io::Error>
 where
     R: Read + Seek,
     B: ByteOrder,
@@ -140,8 +246,8 @@ where
             }
 
             for _ in 0..length {
-                visit::<_, B>(reader, key_type, f)?;
-                visit::<_, B>(reader, value_type, f)?;
+                visit_with::<_, B>(reader, key_type, resolver, f)?;
+                visit_with::<_, B>(reader, value_type, resolver, f)?;
             }
 
             return Ok(());
@@ -151,8 +257,17 @@ where
             reader.seek(SeekFrom::Current(len as i64))?;
             return Ok(());
         }
-        "ReferencedObject" | "ReferencedObjectData" | "ManagedReferencesRegistry" => {
-            return Err(std::io::Error::other("ReferencedObject not implemented"));
+        "ManagedReferencesRegistry" => {
+            return visit_managed_references::<_, B>(reader, tt, resolver, f);
+        }
+        "ReferencedObject" => {
+            visit_referenced_object::<_, B>(reader, tt, resolver, f)?;
+            return Ok(());
+        }
+        "ReferencedObjectData" => {
+            return Err(std::io::Error::other(
+                "ReferencedObjectData outside of a ReferencedObject",
+            ));
         }
         _ => {
             if let [child] = tt.children.as_slice()
@@ -161,7 +276,7 @@ where
                 let item_type = &child.children[1];
                 let length = reader.read_u32::<B>()?;
                 for _ in 0..length {
-                    visit::<_, B>(reader, item_type, f)?;
+                    visit_with::<_, B>(reader, item_type, resolver, f)?;
                 }
                 if tt.requires_align() || child.requires_align() {
                     reader.align4()?;
@@ -175,7 +290,7 @@ where
             }
 
             for child in &tt.children {
-                visit::<_, B>(reader, child, f)?;
+                visit_with::<_, B>(reader, child, resolver, f)?;
             }
 
             if tt.requires_align() {
@@ -194,6 +309,124 @@ where
     Ok(())
 }
 
+/// The type marking the end of a version 1 registry, which has no explicit length.
+const REGISTRY_TERMINUS: (&str, &str, &str) = ("Terminus", "UnityEngine.DMAT", "FAKE_ASM");
+
+fn visit_managed_references<R, B>(
+    reader: &mut R,
+    tt: &TypeTreeNode,
+    resolver: &dyn ManagedReferenceResolver,
+    f: &mut impl FnMut(&TypeTreeNode, &mut R) -> Result<bool, std::io::Error>,
+) -> Result<(), std::io::Error>
+where
+    R: Read + Seek,
+    B: ByteOrder,
+{
+    let [version_node, references @ ..] = tt.children.as_slice() else {
+        return Err(std::io::Error::other("empty ManagedReferencesRegistry"));
+    };
+    let version = reader.read_i32::<B>()?;
+    if version_node.requires_align() {
+        reader.align4()?;
+    }
+
+    match version {
+        // ReferencedObjects until the terminus, with implicit ids
+        1 => {
+            let [object] = references else {
+                return Err(std::io::Error::other(
+                    "expected a single ReferencedObject in ManagedReferencesRegistry",
+                ));
+            };
+            while visit_referenced_object::<_, B>(reader, object, resolver, f)? {}
+        }
+        // vector RefIds
+        _ => {
+            for child in references {
+                visit_with::<_, B>(reader, child, resolver, f)?;
+            }
+        }
+    }
+
+    if tt.requires_align() {
+        reader.align4()?;
+    }
+    Ok(())
+}
+
+/// Visits the data of a `ReferencedObject` with the typetree of its referenced type.
+///
+/// Returns `false` if the object is the terminus of a version 1 registry.
+fn visit_referenced_object<R, B>(
+    reader: &mut R,
+    tt: &TypeTreeNode,
+    resolver: &dyn ManagedReferenceResolver,
+    f: &mut impl FnMut(&TypeTreeNode, &mut R) -> Result<bool, std::io::Error>,
+) -> Result<bool, std::io::Error>
+where
+    R: Read + Seek,
+    B: ByteOrder,
+{
+    let mut ty = None;
+    let mut is_terminus = false;
+    for child in &tt.children {
+        match child.m_Type.as_str() {
+            "ReferencedManagedType" => {
+                let class = read_string::<_, B>(reader)?;
+                let namespace = read_string::<_, B>(reader)?;
+                let assembly = read_string::<_, B>(reader)?;
+                if child.requires_align() {
+                    reader.align4()?;
+                }
+                is_terminus =
+                    (class.as_str(), namespace.as_str(), assembly.as_str()) == REGISTRY_TERMINUS;
+                ty = Some((class, namespace, assembly));
+            }
+            "ReferencedObjectData" => {
+                let Some((class, namespace, assembly)) = &ty else {
+                    return Err(std::io::Error::other(
+                        "ReferencedObjectData without a ReferencedManagedType",
+                    ));
+                };
+                // null references and the terminus have no data
+                if !class.is_empty() && !is_terminus {
+                    let data_tt = resolver
+                        .resolve(assembly, namespace, class)
+                        .map_err(std::io::Error::other)?
+                        .ok_or_else(|| {
+                            std::io::Error::other(format!(
+                                "managed reference type {namespace}.{class} ({assembly}) not found"
+                            ))
+                        })?;
+                    for field in &data_tt.children {
+                        visit_with::<_, B>(reader, field, resolver, f)?;
+                    }
+                    if data_tt.requires_align() {
+                        reader.align4()?;
+                    }
+                }
+                if child.requires_align() {
+                    reader.align4()?;
+                }
+            }
+            _ => visit_with::<_, B>(reader, child, resolver, f)?,
+        }
+    }
+
+    if tt.requires_align() {
+        reader.align4()?;
+    }
+    Ok(!is_terminus)
+}
+
+fn read_string<R: Read + Seek, B: ByteOrder>(reader: &mut R) -> Result<String, std::io::Error> {
+    let length = reader.read_u32::<B>()?;
+    let mut data = vec![0; length as usize];
+    reader.read_exact(&mut data)?;
+    reader.align4()?;
+    String::from_utf8(data).map_err(std::io::Error::other)
+}
+
 trait SeekExt: Seek {
     fn align(&mut self, align: usize) -> Result<(), std::io::Error> {
         let pos = self.stream_position()?;
@@ -211,3 +444,183 @@ trait SeekExt: Seek {
 }
 
 impl<R: Read + Seek + ?Sized> SeekExt for R {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{LE, WriteBytesExt};
+
+    fn node(ty: &str, name: &str, children: Vec<TypeTreeNode>) -> TypeTreeNode {
+        TypeTreeNode {
+            m_Type: ty.into(),
+            m_Name: name.into(),
+            children,
+            ..Default::default()
+        }
+    }
+    fn leaf(ty: &str, name: &str) -> TypeTreeNode {
+        node(ty, name, vec![])
+    }
+    fn pptr(name: &str) -> TypeTreeNode {
+        node(
+            "PPtr<Object>",
+            name,
+            vec![leaf("int", "m_FileID"), leaf("SInt64", "m_PathID")],
+        )
+    }
+    fn array(name: &str, item: TypeTreeNode) -> TypeTreeNode {
+        node(
+            "vector",
+            name,
+            vec![node("Array", "Array", vec![leaf("int", "size"), item])],
+        )
+    }
+    fn managed_type() -> TypeTreeNode {
+        node(
+            "ReferencedManagedType",
+            "type",
+            vec![
+                leaf("string", "class"),
+                leaf("string", "ns"),
+                leaf("string", "asm"),
+            ],
+        )
+    }
+
+    fn behaviour(registry: Vec<TypeTreeNode>) -> TypeTreeNode {
+        node(
+            "MyBehaviour",
+            "Base",
+            vec![
+                pptr("direct"),
+                node("ManagedReferencesRegistry", "references", registry),
+            ],
+        )
+    }
+
+    struct Holder(TypeTreeNode);
+    impl ManagedReferenceResolver for Holder {
+        fn resolve(
+            &self,
+            assembly: &str,
+            namespace: &str,
+            class: &str,
+        ) -> Result<Option<&TypeTreeNode>> {
+            Ok((assembly == "Asm" && namespace.is_empty() && class == "Holder").then_some(&self.0))
+        }
+    }
+    fn holder() -> Holder {
+        Holder(node("Holder", "Base", vec![pptr("target")]))
+    }
+
+    fn write_string(out: &mut Vec<u8>, value: &str) {
+        out.write_u32::<LE>(value.len() as u32).unwrap();
+        out.extend_from_slice(value.as_bytes());
+        out.resize(out.len().next_multiple_of(4), 0);
+    }
+    fn write_type(out: &mut Vec<u8>, (class, ns, asm): (&str, &str, &str)) {
+        write_string(out, class);
+        write_string(out, ns);
+        write_string(out, asm);
+    }
+    fn write_pptr(out: &mut Vec<u8>, path_id: PathId) {
+        out.write_i32::<LE>(0).unwrap();
+        out.write_i64::<LE>(path_id).unwrap();
+    }
+
+    fn path_ids(pptrs: Vec<PPtr>) -> Vec<PathId> {
+        pptrs.iter().map(|pptr| pptr.m_PathID).collect()
+    }
+
+    #[test]
+    fn registry_v2() {
+        let tt = behaviour(vec![
+            leaf("int", "version"),
+            array(
+                "RefIds",
+                node(
+                    "ReferencedObject",
+                    "data",
+                    vec![
+                        leaf("SInt64", "rid"),
+                        managed_type(),
+                        leaf("ReferencedObjectData", "data"),
+                    ],
+                ),
+            ),
+        ]);
+
+        let mut data = Vec::new();
+        write_pptr(&mut data, 5);
+        data.write_i32::<LE>(2).unwrap();
+        data.write_u32::<LE>(2).unwrap();
+        data.write_i64::<LE>(1).unwrap();
+        write_type(&mut data, ("Holder", "", "Asm"));
+        write_pptr(&mut data, 7);
+        // null reference without data
+        data.write_i64::<LE>(-2).unwrap();
+        write_type(&mut data, ("", "", ""));
+
+        let pptrs = trace_pptrs_with::<LE>(&tt, &mut Cursor::new(&data), &holder()).unwrap();
+        assert_eq!(path_ids(pptrs), [5, 7]);
+
+        assert!(trace_pptrs::<LE>(&tt, &mut Cursor::new(&data)).is_err());
+    }
+
+    #[test]
+    fn registry_v1_until_terminus() {
+        let tt = behaviour(vec![
+            leaf("int", "version"),
+            node(
+                "ReferencedObject",
+                "00000000",
+                vec![managed_type(), leaf("ReferencedObjectData", "data")],
+            ),
+        ]);
+
+        let mut data = Vec::new();
+        write_pptr(&mut data, 5);
+        data.write_i32::<LE>(1).unwrap();
+        write_type(&mut data, ("Holder", "", "Asm"));
+        write_pptr(&mut data, 7);
+        write_type(&mut data, ("Holder", "", "Asm"));
+        write_pptr(&mut data, 8);
+        write_type(&mut data, REGISTRY_TERMINUS);
+
+        let mut reader = Cursor::new(&data);
+        let pptrs = trace_pptrs_with::<LE>(&tt, &mut reader, &holder()).unwrap();
+        assert_eq!(path_ids(pptrs), [5, 7, 8]);
+        assert_eq!(reader.position(), data.len() as u64);
+    }
+
+    #[test]
+    fn replace_inside_managed_reference() {
+        let tt = behaviour(vec![
+            leaf("int", "version"),
+            node(
+                "ReferencedObject",
+                "00000000",
+                vec![managed_type(), leaf("ReferencedObjectData", "data")],
+            ),
+        ]);
+        let mut data = Vec::new();
+        write_pptr(&mut data, 5);
+        data.write_i32::<LE>(1).unwrap();
+        write_type(&mut data, ("Holder", "", "Asm"));
+        write_pptr(&mut data, 7);
+        write_type(&mut data, REGISTRY_TERMINUS);
+
+        let path_id_remap = FxHashMap::from_iter([(7, 70)]);
+        replace_pptrs_inplace_with::<LE>(
+            &mut data,
+            &tt,
+            &path_id_remap,
+            &FxHashMap::default(),
+            &holder(),
+        )
+        .unwrap();
+
+        let pptrs = trace_pptrs_with::<LE>(&tt, &mut Cursor::new(&data), &holder()).unwrap();
+        assert_eq!(path_ids(pptrs), [5, 70]);
+    }
+}
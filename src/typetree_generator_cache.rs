@@ -1,33 +1,67 @@
 //! Provider and cache for generated typetrees from assemblies
 use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
 use std::path::Path;
 use std::sync::{Arc, Mutex, OnceLock};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use elsa::sync::FrozenMap;
 use rabex::UnityVersion;
 use rabex::objects::ClassId;
 use rabex::typetree::{TypeTreeNode, TypeTreeProvider};
+use serde_derive::{Deserialize, Serialize};
 
 use crate::Environment;
 use crate::resolver::EnvResolver;
+use crate::utils;
 
 pub struct AssemblyTypeTreeGenerator<'a, R, P> {
     env: &'a Environment<R, P>,
     generator: &'a unity_typetree_gen::AssemblyTypeTreeGenerator,
     base_node: &'a TypeTreeNode,
     cache: &'a FrozenMap<(String, String), Box<TypeTreeNode>>,
+    reference_cache: &'a FrozenMap<(String, String), Box<TypeTreeNode>>,
+    misses: &'a Mutex<HashMap<(String, String), Option<String>>>,
+    reference_misses: &'a Mutex<HashMap<(String, String), Option<String>>>,
     locks: &'a Mutex<HashMap<(String, String), Arc<Mutex<()>>>>,
 }
 
 impl<'a, R: EnvResolver, P: TypeTreeProvider> AssemblyTypeTreeGenerator<'a, R, P> {
+    /// Generates the typetree of the `MonoBehaviour` script `full_name`, including the `MonoBehaviour` header fields.
     pub fn generate(
         &self,
         assembly_name: &str,
         full_name: &str,
+    ) -> Result<Option<&'a TypeTreeNode>> {
+        self.generate_cached(self.cache, self.misses, assembly_name, full_name, true)
+    }
+
+    /// Generates the typetree of a plain serializable class, as stored in `[SerializeReference]` fields.
+    pub fn generate_managed_reference(
+        &self,
+        assembly_name: &str,
+        full_name: &str,
+    ) -> Result<Option<&'a TypeTreeNode>> {
+        self.generate_cached(
+            self.reference_cache,
+            self.reference_misses,
+            assembly_name,
+            full_name,
+            false,
+        )
+    }
+
+    fn generate_cached(
+        &self,
+        cache: &'a FrozenMap<(String, String), Box<TypeTreeNode>>,
+        misses: &Mutex<HashMap<(String, String), Option<String>>>,
+        assembly_name: &str,
+        full_name: &str,
+        monobehaviour: bool,
     ) -> Result<Option<&'a TypeTreeNode>> {
         let key = (assembly_name.to_owned(), full_name.to_owned());
-        if let Some(value) = self.cache.get(&key) {
+        if let Some(value) = cache.get(&key) {
             return Ok(Some(value));
         }
         // Single-flight: only one thread generates a given (assembly, type); concurrent callers
@@ -38,27 +72,52 @@ impl<'a, R: EnvResolver, P: TypeTreeProvider> AssemblyTypeTreeGenerator<'a, R, P
             Arc::clone(locks.entry(key.clone()).or_default())
         };
         let _flight = key_lock.lock().unwrap();
-        if let Some(value) = self.cache.get(&key) {
+        if let Some(value) = cache.get(&key) {
             return Ok(Some(value));
         }
+        // Failures are remembered as well, so that games without (readable) assemblies don't
+        // retry the generation for every object of the same script.
+        if let Some(miss) = misses.lock().unwrap().get(&key) {
+            return match miss {
+                Some(error) => Err(anyhow::anyhow!("{error}")),
+                None => Ok(None),
+            };
+        }
 
         let (namespace, type_name) = full_name.rsplit_once('.').unwrap_or(("", full_name));
-        let value = self
+        let generated = self
             .generator
             .generate(
                 &|name| self.load_managed_assembly(name),
                 assembly_name,
                 namespace,
                 type_name,
-            )?
-            .map(|mut node| {
-                // prepend MonoBehaviour header
-                node.children.splice(0..0, self.base_node.children.clone());
-                node
+            )
+            .map_err(anyhow::Error::from)
+            .map(|node| {
+                node.map(|mut node| {
+                    if monobehaviour {
+                        // prepend MonoBehaviour header
+                        node.children.splice(0..0, self.base_node.children.clone());
+                    }
+                    node
+                })
             });
-        let key = (assembly_name.to_owned(), full_name.to_owned());
 
-        Ok(value.map(|value| self.cache.insert(key, Box::new(value))))
+        match generated {
+            Ok(Some(value)) => Ok(Some(cache.insert(key, Box::new(value)))),
+            Ok(None) => {
+                misses.lock().unwrap().insert(key, None);
+                Ok(None)
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Could not generate typetree for {full_name} in {assembly_name}: {e:#}"
+                );
+                misses.lock().unwrap().insert(key, Some(format!("{e:#}")));
+                Err(e)
+            }
+        }
     }
 
     pub fn monobehaviour_definitions(&self) -> Result<BTreeMap<String, Vec<String>>> {
@@ -93,6 +152,11 @@ struct Backend {
 pub struct TypeTreeGeneratorCache {
     backend: OnceLock<Backend>,
     cache: FrozenMap<(String, String), Box<TypeTreeNode>>,
+    /// Typetrees of `[SerializeReference]` types, which lack the `MonoBehaviour` header.
+    reference_cache: FrozenMap<(String, String), Box<TypeTreeNode>>,
+    /// Types which couldn't be generated, with the error if there was one. Not persisted.
+    misses: Mutex<HashMap<(String, String), Option<String>>>,
+    reference_misses: Mutex<HashMap<(String, String), Option<String>>>,
     /// Per-key locks for single-flight generation (see [`AssemblyTypeTreeGenerator::generate`]).
     locks: Mutex<HashMap<(String, String), Arc<Mutex<()>>>>,
 }
@@ -106,6 +170,9 @@ impl TypeTreeGeneratorCache {
         TypeTreeGeneratorCache {
             backend,
             cache: FrozenMap::default(),
+            reference_cache: FrozenMap::default(),
+            misses: Mutex::default(),
+            reference_misses: Mutex::default(),
             locks: Mutex::new(HashMap::new()),
         }
     }
@@ -113,6 +180,9 @@ impl TypeTreeGeneratorCache {
         TypeTreeGeneratorCache {
             backend: OnceLock::new(),
             cache,
+            reference_cache: FrozenMap::default(),
+            misses: Mutex::default(),
+            reference_misses: Mutex::default(),
             locks: Mutex::new(HashMap::new()),
         }
     }
@@ -120,6 +190,9 @@ impl TypeTreeGeneratorCache {
         TypeTreeGeneratorCache {
             backend: OnceLock::new(),
             cache: FrozenMap::default(),
+            reference_cache: FrozenMap::default(),
+            misses: Mutex::default(),
+            reference_misses: Mutex::default(),
             locks: Mutex::new(HashMap::new()),
         }
     }
@@ -142,6 +215,9 @@ impl TypeTreeGeneratorCache {
             generator: &backend.generator,
             base_node: &backend.base_node,
             cache: &self.cache,
+            reference_cache: &self.reference_cache,
+            misses: &self.misses,
+            reference_misses: &self.reference_misses,
             locks: &self.locks,
         })
     }
@@ -156,6 +232,17 @@ impl TypeTreeGeneratorCache {
         self.cache.insert(key, Box::new(ty))
     }
 
+    /// Like [`TypeTreeGeneratorCache::insert_cache`], for `[SerializeReference]` types without the `MonoBehaviour` header.
+    pub fn insert_reference_cache<'a>(
+        &'a self,
+        assembly_name: &str,
+        full_name: &str,
+        ty: TypeTreeNode,
+    ) -> &'a TypeTreeNode {
+        let key = (assembly_name.to_owned(), full_name.to_owned());
+        self.reference_cache.insert(key, Box::new(ty))
+    }
+
     fn get_or_init(
         &self,
         init: impl FnOnce() -> Result<(UnityVersion, TypeTreeNode)>,
@@ -172,3 +259,180 @@ impl TypeTreeGeneratorCache {
         }))
     }
 }
+
+/// Bumped whenever the layout of the cache file or the generated typetrees change.
+const CACHE_FILE_VERSION: u32 = 1;
+
+/// On-disk representation of a [`TypeTreeGeneratorCache`].
+///
+/// Typetrees are grouped by the assembly they were generated from. Field types can come from any other assembly,
+/// so the whole file is keyed by the hash of all assemblies in `Managed/` at the time of generation.
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    unity_version: String,
+    managed_hash: String,
+    monobehaviours: BTreeMap<String, BTreeMap<String, CachedNode>>,
+    references: BTreeMap<String, BTreeMap<String, CachedNode>>,
+}
+
+/// Serializable mirror of [`TypeTreeNode`], which doesn't implement serde itself.
+#[derive(Serialize, Deserialize)]
+#[allow(non_snake_case)]
+struct CachedNode {
+    m_Type: String,
+    m_Name: String,
+    m_Version: i32,
+    m_TypeFlags: i32,
+    m_ByteSize: i32,
+    m_MetaFlag: Option<i32>,
+    m_RefTypeHash: Option<u64>,
+    m_VariableCount: Option<i32>,
+    children: Vec<CachedNode>,
+}
+
+impl From<&TypeTreeNode> for CachedNode {
+    fn from(node: &TypeTreeNode) -> Self {
+        CachedNode {
+            m_Type: node.m_Type.clone(),
+            m_Name: node.m_Name.clone(),
+            m_Version: node.m_Version,
+            m_TypeFlags: node.m_TypeFlags,
+            m_ByteSize: node.m_ByteSize,
+            m_MetaFlag: node.m_MetaFlag,
+            m_RefTypeHash: node.m_RefTypeHash,
+            m_VariableCount: node.m_VariableCount,
+            children: node.children.iter().map(CachedNode::from).collect(),
+        }
+    }
+}
+
+impl From<CachedNode> for TypeTreeNode {
+    fn from(node: CachedNode) -> Self {
+        TypeTreeNode {
+            m_Type: node.m_Type,
+            m_Name: node.m_Name,
+            m_Version: node.m_Version,
+            m_TypeFlags: node.m_TypeFlags,
+            m_ByteSize: node.m_ByteSize,
+            m_MetaFlag: node.m_MetaFlag,
+            m_RefTypeHash: node.m_RefTypeHash,
+            m_VariableCount: node.m_VariableCount,
+            children: node.children.into_iter().map(TypeTreeNode::from).collect(),
+        }
+    }
+}
+
+/// # Persistence
+impl TypeTreeGeneratorCache {
+    /// Writes all generated typetrees to `path`, so that later runs can skip assembly parsing via [`TypeTreeGeneratorCache::load`].
+    pub fn save<R: EnvResolver, P: TypeTreeProvider>(
+        &self,
+        env: &Environment<R, P>,
+        path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let path = path.as_ref();
+
+        let cache_file = CacheFile {
+            version: CACHE_FILE_VERSION,
+            unity_version: env.unity_version()?.to_string(),
+            managed_hash: managed_hash(env)?,
+            monobehaviours: by_assembly(&self.cache),
+            references: by_assembly(&self.reference_cache),
+        };
+
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, &cache_file)?;
+        writer.flush()?;
+        drop(writer);
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("could not write typetree cache to {}", path.display()))?;
+
+        Ok(())
+    }
+
+    /// Restores typetrees previously written by [`TypeTreeGeneratorCache::save`].
+    ///
+    /// The whole file is ignored when any assembly in `Managed/` changed since it was written,
+    /// or when it was written for a different unity version.
+    /// A missing file is not an error.
+    ///
+    /// Returns the number of restored typetrees.
+    pub fn load<R: EnvResolver, P: TypeTreeProvider>(
+        &self,
+        env: &Environment<R, P>,
+        path: impl AsRef<Path>,
+    ) -> Result<usize> {
+        let path = path.as_ref();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let cache_file: CacheFile = match serde_json::from_reader(BufReader::new(file)) {
+            Ok(cache_file) => cache_file,
+            Err(e) => {
+                tracing::warn!("Ignoring invalid typetree cache {}: {e}", path.display());
+                return Ok(0);
+            }
+        };
+
+        if cache_file.version != CACHE_FILE_VERSION
+            || cache_file.unity_version != env.unity_version()?.to_string()
+            || cache_file.managed_hash != managed_hash(env)?
+        {
+            return Ok(0);
+        }
+
+        let mut restored = 0;
+        for (cache, by_assembly) in [
+            (&self.cache, cache_file.monobehaviours),
+            (&self.reference_cache, cache_file.references),
+        ] {
+            for (assembly, types) in by_assembly {
+                for (full_name, node) in types {
+                    cache.insert((assembly.clone(), full_name), Box::new(node.into()));
+                    restored += 1;
+                }
+            }
+        }
+
+        Ok(restored)
+    }
+}
+
+fn by_assembly(
+    cache: &FrozenMap<(String, String), Box<TypeTreeNode>>,
+) -> BTreeMap<String, BTreeMap<String, CachedNode>> {
+    let mut by_assembly: BTreeMap<String, BTreeMap<String, CachedNode>> = BTreeMap::new();
+    for key in cache.keys_cloned() {
+        let node = cache.get(&key).unwrap();
+        let (assembly, full_name) = key;
+        by_assembly
+            .entry(assembly)
+            .or_default()
+            .insert(full_name, CachedNode::from(node));
+    }
+    by_assembly
+}
+
+/// Combined hash of the names and contents of all files in `Managed/`
+fn managed_hash<R: EnvResolver, P>(env: &Environment<R, P>) -> Result<String> {
+    let mut assemblies = match env.game_files.list_under(Path::new("Managed")) {
+        Ok(assemblies) => assemblies,
+        Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    assemblies.sort();
+
+    let mut hashes = String::new();
+    for assembly in assemblies {
+        let data = env.game_files.read_path(&assembly)?;
+        hashes.push_str(&assembly.to_string_lossy());
+        hashes.push('\0');
+        hashes.push_str(&utils::content_hash(data.as_ref()));
+        hashes.push('\n');
+    }
+    Ok(utils::content_hash(hashes.as_bytes()))
+}
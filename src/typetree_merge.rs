@@ -1,7 +1,11 @@
 //! Merge several [`TypeTreeNode`]s describing the same type across versions.
 //!
 //! The result, [`MergedTypeTree`], mirrors the shape of a typetree but records for every
-//! node *which* sources contain it.
+//! node *which* sources contain it, and how the sources differ in [`NodeChanges`].
+//!
+//! [`MergedTypeTree::merge`] fails on fields whose type changed between sources,
+//! while [`MergedTypeTree::merge_lenient`] records the types and also matches up renamed fields,
+//! for tooling which has to handle every version.
 
 use rabex::typetree::TypeTreeNode;
 
@@ -19,6 +23,33 @@ pub struct MergedTypeTree {
     pub present_in: Vec<usize>,
     /// Children, unified by name across sources, in first-seen order.
     pub children: Vec<MergedTypeTree>,
+    /// How the sources containing this node differ from each other
+    pub changes: NodeChanges,
+}
+
+/// Per-source differences of a [`MergedTypeTree`] node. Each list is empty if all sources agree,
+/// and otherwise has one entry per source in [`present_in`](MergedTypeTree::present_in).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeChanges {
+    /// `m_Type` per source. Only set by [`merge_lenient`](MergedTypeTree::merge_lenient),
+    /// the node's own `m_Type` is the one of the first source.
+    pub types: Vec<(usize, String)>,
+    /// `m_Name` per source, for fields matched as a probable rename by
+    /// [`merge_lenient`](MergedTypeTree::merge_lenient). The node's own `m_Name` is the one of the first source.
+    pub names: Vec<(usize, String)>,
+    /// Whether the field is aligned to 4 bytes after reading, per source
+    pub aligned: Vec<(usize, bool)>,
+    /// Whether any other `m_MetaFlag` bits differ between the sources
+    pub meta_flags_differ: bool,
+}
+
+impl NodeChanges {
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+            && self.names.is_empty()
+            && self.aligned.is_empty()
+            && !self.meta_flags_differ
+    }
 }
 
 /// Typetrees could not be cleanly merged.
@@ -62,49 +93,64 @@ impl MergedTypeTree {
         if sources.is_empty() {
             return Ok(None);
         }
-        merge_nodes(&sources).map(Some)
+        merge_nodes(&sources, false).map(Some)
+    }
+
+    /// Like [`merge`](Self::merge), but never fails.
+    ///
+    /// Fields with a different type between sources record the type of every source in
+    /// [`NodeChanges::types`]. Fields which only exist in disjoint sets of sources, but have the same type
+    /// and position in their parent, are treated as renames and merged, recording the names in [`NodeChanges::names`].
+    ///
+    /// Returns `None` if `sources` is empty.
+    pub fn merge_lenient<'a>(
+        sources: impl IntoIterator<Item = &'a TypeTreeNode>,
+    ) -> Option<MergedTypeTree> {
+        let sources: Vec<(usize, &TypeTreeNode)> = sources.into_iter().enumerate().collect();
+        if sources.is_empty() {
+            return None;
+        }
+        Some(merge_nodes(&sources, true).expect("lenient merging cannot conflict"))
     }
 
     /// Build a [`MergedTypeTree`] from a single source (index `0`).
     pub fn from_single(node: &TypeTreeNode) -> MergedTypeTree {
-        merge_nodes(&[(0, node)]).expect("a single source cannot conflict with itself")
+        merge_nodes(&[(0, node)], false).expect("a single source cannot conflict with itself")
     }
 }
 
-fn merge_nodes(sources: &[(usize, &TypeTreeNode)]) -> Result<MergedTypeTree, TypeConflictError> {
+fn merge_nodes(
+    sources: &[(usize, &TypeTreeNode)],
+    lenient: bool,
+) -> Result<MergedTypeTree, TypeConflictError> {
     let repr = sources[0].1;
+    let mut changes = NodeChanges::default();
     if sources.iter().any(|(_, n)| n.m_Type != repr.m_Type) {
-        return Err(TypeConflictError {
-            field: repr.m_Name.clone(),
-            types: sources.iter().map(|(_, n)| n.m_Type.clone()).collect(),
-        });
-    }
-    let present_in = sources.iter().map(|&(id, _)| id).collect();
-
-    let mut order: Vec<&str> = Vec::new();
-    for (_, source) in sources {
-        for child in &source.children {
-            if !order.contains(&child.m_Name.as_str()) {
-                order.push(&child.m_Name);
-            }
+        if !lenient {
+            return Err(TypeConflictError {
+                field: repr.m_Name.clone(),
+                types: sources.iter().map(|(_, n)| n.m_Type.clone()).collect(),
+            });
         }
+        changes.types = per_source(sources, |n| n.m_Type.clone());
+    }
+    if sources.iter().any(|(_, n)| n.m_Name != repr.m_Name) {
+        changes.names = per_source(sources, |n| n.m_Name.clone());
     }
+    if sources
+        .iter()
+        .any(|(_, n)| n.requires_align() != repr.requires_align())
+    {
+        changes.aligned = per_source(sources, TypeTreeNode::requires_align);
+    }
+    changes.meta_flags_differ = sources
+        .iter()
+        .any(|(_, n)| other_meta_flags(n) != other_meta_flags(repr));
+    let present_in = sources.iter().map(|&(id, _)| id).collect();
 
-    let children = order
+    let children = child_groups(sources, lenient)
         .into_iter()
-        .map(|name| {
-            let variants: Vec<(usize, &TypeTreeNode)> = sources
-                .iter()
-                .filter_map(|&(id, source)| {
-                    source
-                        .children
-                        .iter()
-                        .find(|c| c.m_Name == name)
-                        .map(|c| (id, c))
-                })
-                .collect();
-            merge_nodes(&variants)
-        })
+        .map(|variants| merge_nodes(&variants, lenient))
         .collect::<Result<_, _>>()?;
 
     Ok(MergedTypeTree {
@@ -112,9 +158,100 @@ fn merge_nodes(sources: &[(usize, &TypeTreeNode)]) -> Result<MergedTypeTree, Typ
         m_Name: repr.m_Name.clone(),
         present_in,
         children,
+        changes,
     })
 }
 
+/// `TransferMetaFlags::AlignBytes`, which is recorded separately in [`NodeChanges::aligned`]
+const ALIGN_BYTES_FLAG: i32 = 1 << 14;
+
+fn other_meta_flags(node: &TypeTreeNode) -> i32 {
+    node.m_MetaFlag.unwrap_or(0) & !ALIGN_BYTES_FLAG
+}
+
+fn per_source<T>(
+    sources: &[(usize, &TypeTreeNode)],
+    f: impl Fn(&TypeTreeNode) -> T,
+) -> Vec<(usize, T)> {
+    sources.iter().map(|&(id, node)| (id, f(node))).collect()
+}
+
+/// A child present in some sources, with its position in each source's children.
+struct ChildGroup<'a> {
+    variants: Vec<(usize, usize, &'a TypeTreeNode)>,
+}
+
+impl ChildGroup<'_> {
+    fn has_source(&self, source: usize) -> bool {
+        self.variants.iter().any(|&(id, _, _)| id == source)
+    }
+
+    /// The position and type shared by all variants
+    fn shape(&self) -> Option<(usize, &str)> {
+        let (_, position, node) = self.variants[0];
+        self.variants
+            .iter()
+            .all(|&(_, p, n)| p == position && n.m_Type == node.m_Type)
+            .then_some((position, node.m_Type.as_str()))
+    }
+}
+
+/// The children of `sources` grouped by name in first-seen order, each group in source order.
+///
+/// When `lenient`, groups from disjoint sources with the same position and type are merged as renames.
+fn child_groups<'a>(
+    sources: &[(usize, &'a TypeTreeNode)],
+    lenient: bool,
+) -> Vec<Vec<(usize, &'a TypeTreeNode)>> {
+    let mut groups: Vec<ChildGroup<'a>> = Vec::new();
+    for &(id, source) in sources {
+        for (position, child) in source.children.iter().enumerate() {
+            let group = groups
+                .iter_mut()
+                .find(|group| group.variants[0].2.m_Name == child.m_Name);
+            match group {
+                Some(group) if !group.has_source(id) => group.variants.push((id, position, child)),
+                Some(_) => {}
+                None => groups.push(ChildGroup {
+                    variants: vec![(id, position, child)],
+                }),
+            }
+        }
+    }
+
+    if lenient {
+        let mut i = 0;
+        while i < groups.len() {
+            let renamed = groups[i + 1..].iter().position(|other| {
+                let disjoint = other
+                    .variants
+                    .iter()
+                    .all(|&(id, _, _)| !groups[i].has_source(id));
+                disjoint && other.shape().is_some() && other.shape() == groups[i].shape()
+            });
+            match renamed {
+                Some(offset) => {
+                    let other = groups.remove(i + 1 + offset);
+                    groups[i].variants.extend(other.variants);
+                    groups[i].variants.sort_by_key(|&(id, _, _)| id);
+                }
+                None => i += 1,
+            }
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|group| {
+            group
+                .variants
+                .into_iter()
+                .map(|(id, _, node)| (id, node))
+                .collect()
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -130,6 +267,10 @@ mod tests {
     fn leaf(ty: &str, name: &str) -> TypeTreeNode {
         node(ty, name, vec![])
     }
+    fn with_flags(mut node: TypeTreeNode, flags: i32) -> TypeTreeNode {
+        node.m_MetaFlag = Some(flags);
+        node
+    }
     fn child<'a>(m: &'a MergedTypeTree, name: &str) -> &'a MergedTypeTree {
         m.children.iter().find(|c| c.m_Name == name).unwrap()
     }
@@ -197,4 +338,92 @@ mod tests {
         assert_eq!(err.field, "x");
         assert_eq!(err.types, vec!["int".to_string(), "float".to_string()]);
     }
+
+    #[test]
+    fn lenient_records_differing_types() {
+        let a = node("Fsm", "Base", vec![leaf("int", "x")]);
+        let b = node("Fsm", "Base", vec![leaf("float", "x")]);
+        let m = MergedTypeTree::merge_lenient([&a, &b]).unwrap();
+
+        let x = child(&m, "x");
+        assert_eq!(x.m_Type, "int");
+        assert_eq!(x.present_in, vec![0, 1]);
+        assert_eq!(
+            x.changes.types,
+            vec![(0, "int".to_owned()), (1, "float".to_owned())]
+        );
+        assert!(m.changes.is_empty());
+    }
+
+    #[test]
+    fn lenient_detects_renames() {
+        let a = node(
+            "T",
+            "Base",
+            vec![leaf("int", "shared"), leaf("float", "speed")],
+        );
+        let b = node(
+            "T",
+            "Base",
+            vec![leaf("int", "shared"), leaf("float", "moveSpeed")],
+        );
+        // same position, but a different type: not a rename
+        let c = node(
+            "T",
+            "Base",
+            vec![leaf("int", "shared"), leaf("int", "count")],
+        );
+        let m = MergedTypeTree::merge_lenient([&a, &b, &c]).unwrap();
+
+        let names: Vec<_> = m.children.iter().map(|c| c.m_Name.as_str()).collect();
+        assert_eq!(names, vec!["shared", "speed", "count"]);
+        let speed = child(&m, "speed");
+        assert_eq!(speed.present_in, vec![0, 1]);
+        assert_eq!(
+            speed.changes.names,
+            vec![(0, "speed".to_owned()), (1, "moveSpeed".to_owned())]
+        );
+
+        // the strict merge keeps them apart
+        let m = MergedTypeTree::merge([&a, &b]).unwrap().unwrap();
+        assert_eq!(m.children.len(), 3);
+    }
+
+    #[test]
+    fn alignment_and_other_meta_flags_are_separate() {
+        const HIDE_IN_EDITOR: i32 = 1;
+        let a = node(
+            "T",
+            "Base",
+            vec![
+                with_flags(leaf("int", "align"), 0),
+                with_flags(leaf("int", "hidden"), 0),
+                with_flags(leaf("int", "both"), 0),
+            ],
+        );
+        let b = node(
+            "T",
+            "Base",
+            vec![
+                with_flags(leaf("int", "align"), ALIGN_BYTES_FLAG),
+                with_flags(leaf("int", "hidden"), HIDE_IN_EDITOR),
+                with_flags(leaf("int", "both"), ALIGN_BYTES_FLAG | HIDE_IN_EDITOR),
+            ],
+        );
+        let m = MergedTypeTree::merge([&a, &b]).unwrap().unwrap();
+
+        let align = child(&m, "align");
+        assert_eq!(align.changes.aligned, vec![(0, false), (1, true)]);
+        assert!(!align.changes.meta_flags_differ);
+
+        let hidden = child(&m, "hidden");
+        assert!(hidden.changes.aligned.is_empty());
+        assert!(hidden.changes.meta_flags_differ);
+
+        let both = child(&m, "both");
+        assert_eq!(both.changes.aligned, vec![(0, false), (1, true)]);
+        assert!(both.changes.meta_flags_differ);
+
+        assert!(m.changes.is_empty());
+    }
 }
@@ -52,7 +52,8 @@ impl ClassIdType for PreloadData {
 pub struct AssetBundle {
     pub m_Name: String,
     pub m_PreloadTable: Vec<PPtr>,
-    pub m_Container: IndexMap<String, AssetInfo>,
+    /// Not a map, as assets with sub-objects have one entry per object under the same path
+    pub m_Container: Vec<(String, AssetInfo)>,
     pub m_MainAsset: AssetInfo,
     pub m_RuntimeCompatibility: u32,
     pub m_AssetBundleName: String,
@@ -93,11 +94,21 @@ impl AssetBundle {
     pub fn add_scene(&mut self, path: &str, scene_hash: &str) {
         debug_assert!(self.m_IsStreamedSceneAssetBundle);
         self.m_Container
-            .insert(path.to_owned(), AssetInfo::default());
+            .push((path.to_owned(), AssetInfo::default()));
         self.m_SceneHashes
             .insert(path.to_owned(), scene_hash.to_owned());
     }
 
+    /// The `m_Container` entries for `path`. Unity lowercases container paths, so the comparison ignores case.
+    ///
+    /// Assets with sub-objects (sprites in a texture, meshes in a model) have one entry per object.
+    pub fn container_entries<'a>(&'a self, path: &'a str) -> impl Iterator<Item = &'a AssetInfo> {
+        self.m_Container
+            .iter()
+            .filter(move |(container_path, _)| container_path.eq_ignore_ascii_case(path))
+            .map(|(_, info)| info)
+    }
+
     pub fn add_preloads<I: IntoIterator<Item = PPtr>>(&mut self, preloads: I) -> Range<usize> {
         let preload_index = self.m_PreloadTable.len();
         self.m_PreloadTable.extend(preloads);
@@ -109,7 +120,7 @@ impl AssetBundle {
         AssetBundle {
             m_Name: name.to_owned(),
             m_AssetBundleName: name.to_owned(),
-            m_Container: IndexMap::default(),
+            m_Container: Vec::new(),
             m_IsStreamedSceneAssetBundle: true,
             // TODO: investigate these
             m_RuntimeCompatibility: 1,
@@ -132,6 +143,12 @@ impl AssetBundle {
     }
 }
 
+/// A minimal view reading just `m_Name`, for any named object.
+#[derive(Debug, Deserialize)]
+pub struct Named {
+    pub m_Name: String,
+}
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct AssetInfo {
     pub preloadIndex: i32,
@@ -244,7 +261,8 @@ impl ClassIdType for MonoScript {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ResourceManager {
-    pub m_Container: IndexMap<String, PPtr>,
+    /// Not a map, as assets with sub-objects have one entry per object under the same path
+    pub m_Container: Vec<(String, PPtr)>,
     pub m_DependentAssets: Vec<(PPtr, Vec<PPtr>)>,
 }
 impl ClassIdType for ResourceManager {
@@ -1,9 +1,22 @@
 //! Utilities for efficient parallel data processing
+use std::fmt::Write as _;
+
 use anyhow::Result;
 use rayon::iter::{IntoParallelIterator, ParallelIterator as _};
+use sha2::{Digest, Sha256};
 
 pub use merge::Merge;
 
+/// Hex-encoded SHA-256 of `data`, used to key caches by file contents.
+pub fn content_hash(data: &[u8]) -> String {
+    let digest = Sha256::digest(data);
+    let mut hex = String::with_capacity(digest.len() * 2);
+    for byte in digest {
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
 pub fn seq_fold_reduce<Acc, T>(
     iter: impl IntoIterator<Item = T>,
     f: impl Fn(&mut Acc, T) -> Result<()> + Send + Sync,
@@ -0,0 +1,478 @@
+//! Tests for [`rabex_env::addressables`] against an in-memory addressables build.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use rabex_env::addressables::catalog::{
+    AddressablesCatalog, AssemblyClass, AssetBundleRequestOptions, CommonInfo, Hash128,
+    ObjectInitializationData, ResourceLocation, resource_providers,
+};
+use rabex_env::addressables::dependencies::BundleDependencies;
+use rabex_env::addressables::verify::BundleProblem;
+use rabex_env::rabex::objects::PPtr;
+use rabex_env::unity::types::{AssetBundle, AssetInfo, GameObject, TextAsset};
+use rabex_env_testkit::{
+    Flat, MemEnv, add_go, addressables_settings, asset_bundle_file, build_file, bundle_with_files,
+    bundle_with_serialized, mem_env,
+};
+use serde_json::json;
+
+fn class(name: &str) -> AssemblyClass {
+    AssemblyClass {
+        m_AssemblyName: Arc::new("Unity.ResourceManager, Version=0.0.0.0".to_owned()),
+        m_ClassName: Arc::new(name.to_owned()),
+    }
+}
+
+fn init_data(id: &str) -> ObjectInitializationData {
+    ObjectInitializationData {
+        id: Arc::new(id.to_owned()),
+        object_type: class(id),
+        data: Arc::new(String::new()),
+    }
+}
+
+fn bundle_location(internal_id: &str, bundle_name: &str, crc: u32) -> Arc<ResourceLocation> {
+    Arc::new(ResourceLocation {
+        internal_id: Arc::new(internal_id.to_owned()),
+        provider_id: Arc::new(resource_providers::ASSET_BUNDLE.to_owned()),
+        dependencies: Vec::new(),
+        data: Some(AssetBundleRequestOptions {
+            hash: Hash128::from_u32s([0; 4]),
+            crc,
+            common_info: CommonInfo {
+                timeout: 0,
+                redirect_limit: 32,
+                retry_count: 0,
+                flags: 0,
+            },
+            bundle_name: Arc::new(bundle_name.to_owned()),
+            bundle_size: 0,
+        }),
+        dependency_hash_code: 0,
+        primary_key: Arc::new(internal_id.to_owned()),
+        type_: class("UnityEngine.ResourceManagement.ResourceProviders.IAssetBundleResource"),
+    })
+}
+
+/// A `BundledAssetProvider` location for `key`, in the first of `bundles` and depending on the rest
+fn asset_location(key: &str, bundles: &[&Arc<ResourceLocation>]) -> Arc<ResourceLocation> {
+    Arc::new(ResourceLocation {
+        internal_id: Arc::new(key.to_owned()),
+        provider_id: Arc::new(resource_providers::BUNDLED_ASSET.to_owned()),
+        dependencies: bundles.iter().map(|&bundle| Arc::clone(bundle)).collect(),
+        data: None,
+        dependency_hash_code: 0,
+        primary_key: Arc::new(key.to_owned()),
+        type_: class("UnityEngine.GameObject"),
+    })
+}
+
+fn catalog(locations: impl IntoIterator<Item = Arc<ResourceLocation>>) -> AddressablesCatalog {
+    AddressablesCatalog {
+        locator_id: Arc::new("AddressablesMainContentCatalog".to_owned()),
+        build_result_hash: Arc::new(String::new()),
+        instance_provider_data: init_data("InstanceProvider"),
+        scene_provider_data: init_data("SceneProvider"),
+        resource_provider_data: vec![init_data(resource_providers::ASSET_BUNDLE)],
+        resources: locations
+            .into_iter()
+            .map(|location| (Arc::clone(&location.primary_key), vec![location]))
+            .collect::<HashMap<_, _>>(),
+    }
+}
+
+const RUNTIME_PATH: &str = "{UnityEngine.AddressableAssets.Addressables.RuntimePath}";
+const CONTENT_CATALOG_PROVIDER: &str =
+    "UnityEngine.AddressableAssets.ResourceProviders.ContentCatalogProvider";
+const TEXT_DATA_PROVIDER: &str =
+    "UnityEngine.ResourceManagement.ResourceProviders.TextDataProvider";
+
+fn catalog_location(
+    key: &str,
+    internal_id: &str,
+    provider: &str,
+    dependencies: &[&str],
+) -> serde_json::Value {
+    json!({
+        "m_Keys": [key],
+        "m_InternalId": internal_id,
+        "m_Provider": provider,
+        "m_Dependencies": dependencies,
+        "m_ResourceType": { "m_AssemblyName": "", "m_ClassName": "" },
+    })
+}
+
+/// `settings.json` with custom catalog locations
+fn settings(catalog_locations: Vec<serde_json::Value>, in_bundle: bool) -> Vec<u8> {
+    let mut settings: serde_json::Value =
+        serde_json::from_slice(&addressables_settings("StandaloneLinux64", "catalog.json"))
+            .unwrap();
+    settings["m_CatalogLocations"] = catalog_locations.into();
+    settings["m_IsLocalCatalogInBundle"] = in_bundle.into();
+    serde_json::to_vec(&settings).unwrap()
+}
+
+fn env_with(files: Vec<(String, Vec<u8>)>) -> MemEnv {
+    mem_env(
+        files
+            .into_iter()
+            .chain([("globalgamemanagers".to_owned(), Flat::new(&[]).write().0)]),
+    )
+}
+
+fn bundle_catalog(name: &str) -> AddressablesCatalog {
+    catalog([bundle_location(
+        &format!("{RUNTIME_PATH}/StandaloneLinux64/{name}.bundle"),
+        name,
+        0,
+    )])
+}
+
+fn has_key(env: &MemEnv, name: &str) -> bool {
+    let key = format!("{RUNTIME_PATH}/StandaloneLinux64/{name}.bundle");
+    env.addressables_catalog()
+        .unwrap()
+        .unwrap()
+        .resources
+        .contains_key(&key)
+}
+
+#[test]
+fn catalog_in_bundle() {
+    let catalog = bundle_catalog("bundled");
+    let text_asset = build_file(|sfb| {
+        sfb.add_object(&TextAsset {
+            m_Name: "catalog".to_owned(),
+            m_Script: String::from_utf8(catalog.write_json().unwrap()).unwrap(),
+        })
+        .unwrap();
+    });
+
+    let env = env_with(vec![
+        (
+            "StreamingAssets/aa/settings.json".to_owned(),
+            settings(
+                vec![catalog_location(
+                    "AddressablesMainContentCatalog",
+                    &format!("{RUNTIME_PATH}/catalog.json"),
+                    CONTENT_CATALOG_PROVIDER,
+                    &[],
+                )],
+                true,
+            ),
+        ),
+        (
+            "StreamingAssets/aa/catalog.bundle".to_owned(),
+            bundle_with_serialized("CAB-catalog", &text_asset),
+        ),
+    ]);
+
+    assert_eq!(env.addressables_catalogs().unwrap(), [catalog]);
+}
+
+/// Settings for a local catalog which is updated from `https://cdn.example.com/catalog_remote.hash`
+fn remote_update_env(local_hash: &str) -> MemEnv {
+    let aa = "StreamingAssets/aa";
+    env_with(vec![
+        (
+            format!("{aa}/settings.json"),
+            settings(
+                vec![
+                    catalog_location(
+                        "AddressablesMainContentCatalogRemoteHash",
+                        "https://cdn.example.com/catalog_remote.hash",
+                        TEXT_DATA_PROVIDER,
+                        &[],
+                    ),
+                    catalog_location(
+                        "AddressablesMainContentCatalogCacheHash",
+                        "{UnityEngine.Application.persistentDataPath}/com.unity.addressables/catalog_remote.hash",
+                        TEXT_DATA_PROVIDER,
+                        &[],
+                    ),
+                    catalog_location(
+                        "AddressablesMainContentCatalog",
+                        &format!("{RUNTIME_PATH}/catalog.json"),
+                        CONTENT_CATALOG_PROVIDER,
+                        &[
+                            "AddressablesMainContentCatalogRemoteHash",
+                            "AddressablesMainContentCatalogCacheHash",
+                        ],
+                    ),
+                    catalog_location(
+                        "ModCatalog",
+                        "https://mods.example.com/catalog_mod.json",
+                        CONTENT_CATALOG_PROVIDER,
+                        &[],
+                    ),
+                ],
+                false,
+            ),
+        ),
+        (
+            format!("{aa}/catalog.json"),
+            bundle_catalog("local").write_json().unwrap(),
+        ),
+        (format!("{aa}/catalog.hash"), local_hash.as_bytes().to_vec()),
+    ])
+}
+
+/// Stand-in for the CDN
+fn fetch(url: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    Ok(match url {
+        "https://cdn.example.com/catalog_remote.hash" => Some(b"new".to_vec()),
+        "https://cdn.example.com/catalog_remote.json" => {
+            Some(bundle_catalog("remote").write_json()?)
+        }
+        "https://mods.example.com/catalog_mod.json" => Some(bundle_catalog("mod").write_json()?),
+        _ => None,
+    })
+}
+
+#[test]
+fn remote_catalogs() {
+    let mut env = remote_update_env("old");
+    assert!(has_key(&env, "local"));
+    assert!(!has_key(&env, "mod"));
+
+    // the catalogs loaded above are discarded
+    env.addressables_mut().unwrap().unwrap().catalog_fetcher = Some(Box::new(fetch));
+    assert_eq!(env.addressables_catalogs().unwrap().len(), 2);
+    assert!(has_key(&env, "remote"));
+    assert!(has_key(&env, "mod"));
+    assert!(!has_key(&env, "local"));
+
+    let mut env = remote_update_env("new\n");
+    env.addressables_mut().unwrap().unwrap().catalog_fetcher = Some(Box::new(fetch));
+    assert!(has_key(&env, "local"));
+    assert!(has_key(&env, "mod"));
+    assert!(!has_key(&env, "remote"));
+}
+
+#[test]
+fn verify_reports_broken_bundles() {
+    // the CRC covers the data of all files in the bundle, not just the serialized file
+    let good_file = asset_bundle_file("good");
+    let good_res = b"texture data".as_slice();
+    let good_crc = crc32fast::hash(&[good_file.as_slice(), good_res].concat());
+
+    let catalog = catalog([
+        bundle_location(
+            &format!("{RUNTIME_PATH}/StandaloneLinux64/good.bundle"),
+            "good",
+            good_crc,
+        ),
+        bundle_location(
+            &format!("{RUNTIME_PATH}/StandaloneLinux64/no_crc.bundle"),
+            "no_crc",
+            0,
+        ),
+        bundle_location(
+            &format!("{RUNTIME_PATH}/StandaloneLinux64/corrupted.bundle"),
+            "corrupted",
+            good_crc,
+        ),
+        bundle_location(
+            &format!("{RUNTIME_PATH}/StandaloneLinux64/renamed.bundle"),
+            "renamed",
+            0,
+        ),
+        bundle_location(
+            &format!("{RUNTIME_PATH}/StandaloneLinux64/missing.bundle"),
+            "missing",
+            0,
+        ),
+        bundle_location("https://cdn.example.com/remote.bundle", "remote", 0),
+    ]);
+
+    let aa = "StreamingAssets/aa";
+    let env = mem_env([
+        ("globalgamemanagers".to_owned(), Flat::new(&[]).write().0),
+        (
+            format!("{aa}/settings.json"),
+            addressables_settings("StandaloneLinux64", "catalog.json"),
+        ),
+        (format!("{aa}/catalog.json"), catalog.write_json().unwrap()),
+        (
+            format!("{aa}/StandaloneLinux64/good.bundle"),
+            bundle_with_files(&[("CAB-good", &good_file), ("CAB-good.resS", good_res)]),
+        ),
+        (
+            format!("{aa}/StandaloneLinux64/no_crc.bundle"),
+            bundle_with_serialized("CAB-no_crc", &asset_bundle_file("no_crc")),
+        ),
+        (
+            format!("{aa}/StandaloneLinux64/corrupted.bundle"),
+            bundle_with_serialized("CAB-corrupted", &asset_bundle_file("corrupted")),
+        ),
+        (
+            format!("{aa}/StandaloneLinux64/renamed.bundle"),
+            bundle_with_serialized("CAB-renamed", &asset_bundle_file("other")),
+        ),
+    ]);
+
+    let report = env.addressables().unwrap().unwrap().verify(&env).unwrap();
+    assert_eq!(report.checked, 5);
+    assert_eq!(
+        report.skipped,
+        [PathBuf::from("https://cdn.example.com/remote.bundle")]
+    );
+    assert_eq!(
+        report.problems,
+        [
+            (
+                PathBuf::from("corrupted.bundle"),
+                BundleProblem::CrcMismatch {
+                    expected: good_crc,
+                    actual: crc32fast::hash(&asset_bundle_file("corrupted")),
+                }
+            ),
+            (PathBuf::from("missing.bundle"), BundleProblem::Missing),
+            (
+                PathBuf::from("renamed.bundle"),
+                BundleProblem::NameMismatch {
+                    expected: "renamed".to_owned(),
+                    actual: "other".to_owned(),
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn bundle_dependencies() {
+    let prefabs = bundle_location(
+        &format!("{RUNTIME_PATH}/StandaloneLinux64/prefabs.bundle"),
+        "prefabs",
+        0,
+    );
+    let textures = bundle_location(
+        &format!("{RUNTIME_PATH}/StandaloneLinux64/textures.bundle"),
+        "textures",
+        0,
+    );
+    let enemy = asset_location("Assets/Enemy.prefab", &[&prefabs, &textures]);
+    let catalog = catalog([prefabs, textures, enemy]);
+
+    // references a CAB of another bundle, and one which isn't in the build
+    let prefabs_file = build_file(|sfb| {
+        sfb.get_or_insert_external("archive:/CAB-textures/CAB-textures");
+        sfb.get_or_insert_external("archive:/CAB-gone/CAB-gone");
+        sfb.add_object(&AssetBundle::asset_base("prefabs")).unwrap();
+    });
+
+    let aa = "StreamingAssets/aa";
+    let env = env_with(vec![
+        (
+            format!("{aa}/settings.json"),
+            addressables_settings("StandaloneLinux64", "catalog.json"),
+        ),
+        (format!("{aa}/catalog.json"), catalog.write_json().unwrap()),
+        (
+            format!("{aa}/StandaloneLinux64/prefabs.bundle"),
+            bundle_with_serialized("CAB-prefabs", &prefabs_file),
+        ),
+        (
+            format!("{aa}/StandaloneLinux64/textures.bundle"),
+            bundle_with_serialized("CAB-textures", &asset_bundle_file("textures")),
+        ),
+    ]);
+
+    let dependencies = BundleDependencies::build(&env).unwrap();
+    let (prefabs, textures) = (
+        PathBuf::from("prefabs.bundle"),
+        PathBuf::from("textures.bundle"),
+    );
+    assert_eq!(
+        dependencies.dependencies_of(&prefabs).collect::<Vec<_>>(),
+        [textures.as_path()]
+    );
+    assert_eq!(dependencies.dependencies_of(&textures).count(), 0);
+    assert_eq!(
+        dependencies.bundles_for_key("Assets/Enemy.prefab"),
+        [textures.clone(), prefabs.clone()]
+    );
+    assert!(dependencies.missing.is_empty());
+    assert_eq!(
+        dependencies.missing_cabs,
+        BTreeSet::from(["CAB-gone".to_owned()])
+    );
+    assert!(dependencies.cycles().is_empty());
+}
+
+#[test]
+fn load_addressable_by_key() {
+    let assets = bundle_location(
+        &format!("{RUNTIME_PATH}/StandaloneLinux64/assets.bundle"),
+        "assets",
+        0,
+    );
+    let catalog = catalog([
+        asset_location("Assets/Prefabs/Enemy.prefab", &[&assets]),
+        asset_location("Assets/Sprites/atlas.png[icon]", &[&assets]),
+        assets,
+    ]);
+
+    let text_asset = |name: &str| TextAsset {
+        m_Name: name.to_owned(),
+        m_Script: format!("{name} data"),
+    };
+    let assets_file = build_file(|sfb| {
+        let enemy = sfb.get_next_path_id();
+        add_go(sfb, enemy, "Enemy", &[]);
+        // sub-objects share the container path of their asset
+        let frame = sfb.add_object(&text_asset("frame")).unwrap();
+        let icon = sfb.add_object(&text_asset("icon")).unwrap();
+
+        let mut asset_bundle = AssetBundle::asset_base("assets");
+        asset_bundle.m_Container = [
+            ("assets/prefabs/enemy.prefab", enemy),
+            ("assets/sprites/atlas.png", frame),
+            ("assets/sprites/atlas.png", icon),
+        ]
+        .into_iter()
+        .map(|(path, path_id)| {
+            let info = AssetInfo {
+                asset: PPtr::local(path_id),
+                ..Default::default()
+            };
+            (path.to_owned(), info)
+        })
+        .collect();
+        sfb.add_object(&asset_bundle).unwrap();
+    });
+
+    let aa = "StreamingAssets/aa";
+    let env = env_with(vec![
+        (
+            format!("{aa}/settings.json"),
+            addressables_settings("StandaloneLinux64", "catalog.json"),
+        ),
+        (format!("{aa}/catalog.json"), catalog.write_json().unwrap()),
+        (
+            format!("{aa}/StandaloneLinux64/assets.bundle"),
+            bundle_with_serialized("CAB-assets", &assets_file),
+        ),
+    ]);
+
+    let enemy = env
+        .load_addressable::<GameObject>("Assets/Prefabs/Enemy.prefab")
+        .unwrap();
+    assert_eq!(enemy.read().unwrap().m_Name, "Enemy");
+
+    let icon = env
+        .load_addressable::<TextAsset>("Assets/Sprites/atlas.png[icon]")
+        .unwrap();
+    assert_eq!(icon.read().unwrap().m_Script, "icon data");
+
+    assert!(
+        env.load_addressable::<TextAsset>("Assets/Sprites/atlas.png[missing]")
+            .is_err()
+    );
+    assert!(
+        env.load_addressable::<GameObject>("Assets/Prefabs/Missing.prefab")
+            .is_err()
+    );
+}
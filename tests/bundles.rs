@@ -0,0 +1,122 @@
+//! Tests for plain assetbundles outside of addressables.
+
+use std::path::{Path, PathBuf};
+
+use rabex_env::unity::types::AssetBundle;
+use rabex_env_testkit::{Flat, MemEnv, asset_bundle_file, bundle_with_serialized, mem_env};
+
+/// A game with two bundles in `StreamingAssets`, and files which aren't bundles next to them
+fn game() -> MemEnv {
+    let (ggm, _) = Flat::new(&[]).write();
+    let (level0, _) = Flat::new(&["Player"]).write();
+    mem_env([
+        ("globalgamemanagers", ggm),
+        ("level0", level0),
+        (
+            "StreamingAssets/enemies.bundle",
+            bundle_with_serialized("CAB-enemies", &asset_bundle_file("enemies")),
+        ),
+        (
+            "StreamingAssets/music/boss",
+            bundle_with_serialized("CAB-boss", &asset_bundle_file("boss")),
+        ),
+        ("StreamingAssets/fake.bundle", b"UnityFake".to_vec()),
+        ("StreamingAssets/config.json", b"{}".to_vec()),
+    ])
+}
+
+#[test]
+fn bundle_index() {
+    let env = game();
+
+    let mut paths = env.bundle_paths().unwrap();
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            PathBuf::from("StreamingAssets/enemies.bundle"),
+            PathBuf::from("StreamingAssets/music/boss"),
+        ]
+    );
+
+    let index = env.bundle_index().unwrap();
+    assert_eq!(index.bundle_paths().count(), 2);
+    assert_eq!(
+        index.bundle_of("CAB-boss"),
+        Some(Path::new("StreamingAssets/music/boss"))
+    );
+    assert_eq!(index.bundle_of("CAB-missing"), None);
+}
+
+#[test]
+fn load_bundle() {
+    let env = game();
+
+    let bundle = env.load_bundle("StreamingAssets/enemies.bundle").unwrap();
+    let files: Vec<_> = bundle
+        .files()
+        .into_iter()
+        .map(|file| file.path.as_str())
+        .collect();
+    assert_eq!(files, ["CAB-enemies"]);
+
+    // same extension, but not a bundle
+    assert!(env.load_bundle("StreamingAssets/fake.bundle").is_err());
+    assert!(env.load_bundle("StreamingAssets/missing.bundle").is_err());
+}
+
+#[test]
+fn load_bundle_content() {
+    let env = game();
+
+    let file = env
+        .load_bundle_content("StreamingAssets/enemies.bundle")
+        .unwrap();
+    let asset_bundle = file.find_object_of::<AssetBundle>().unwrap().unwrap();
+    assert_eq!(asset_bundle.m_Name, "enemies");
+
+    // cached under its archive path, where PPtrs from other files find it
+    let cached = env
+        .load_serialized("archive:/CAB-enemies/CAB-enemies")
+        .unwrap();
+    assert!(std::ptr::eq(cached.file, file.file));
+
+    assert!(
+        env.load_bundle_content("StreamingAssets/fake.bundle")
+            .is_err()
+    );
+}
+
+#[test]
+fn load_all_serialized_files() {
+    let (ggm, _) = Flat::new(&[]).write();
+    let (shared, _) = Flat::new(&["Camera"]).write();
+    let env = mem_env([
+        ("globalgamemanagers", ggm),
+        ("sharedassets0.assets", shared),
+        (
+            "StreamingAssets/enemies.bundle",
+            bundle_with_serialized("CAB-enemies", &asset_bundle_file("enemies")),
+        ),
+    ]);
+
+    let all = env.load_all_serialized_files().unwrap();
+    let keys: Vec<_> = all.keys().map(String::as_str).collect();
+    assert_eq!(
+        keys,
+        [
+            "archive:/CAB-enemies/CAB-enemies",
+            "globalgamemanagers",
+            "sharedassets0.assets",
+        ]
+    );
+
+    // bundle contents are cached under the same key
+    let bundled = &all["archive:/CAB-enemies/CAB-enemies"];
+    let cached = env
+        .load_serialized("archive:/CAB-enemies/CAB-enemies")
+        .unwrap();
+    assert!(std::ptr::eq(cached.file, bundled.file));
+    let asset_bundle = bundled.find_object_of::<AssetBundle>().unwrap().unwrap();
+    assert_eq!(asset_bundle.m_Name, "enemies");
+}
@@ -0,0 +1,68 @@
+//! Tests for the serialized file cache budget of [`rabex_env::Environment`].
+
+use std::path::Path;
+
+use rabex_env_testkit::{Flat, MemEnv, mem_env};
+
+fn env_with_files(names: &[&str]) -> MemEnv {
+    mem_env(
+        names
+            .iter()
+            .map(|name| (*name, Flat::new(&["A", "B"]).write().0)),
+    )
+}
+
+fn loaded(env: &mut MemEnv) -> Vec<String> {
+    let mut files: Vec<_> = env
+        .loaded_files()
+        .map(|path| path.display().to_string())
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn trim_without_budget_keeps_everything() {
+    let mut env = env_with_files(&["a", "b"]);
+    env.load_serialized("a").unwrap();
+    env.load_serialized("b").unwrap();
+
+    assert_eq!(env.trim_cache(), 0);
+    assert_eq!(loaded(&mut env), ["a", "b"]);
+}
+
+#[test]
+fn trim_evicts_least_recently_used() {
+    let mut env = env_with_files(&["a", "b", "c"]);
+    env.load_serialized("a").unwrap();
+    let file_size = env.cache_size();
+    env.load_serialized("b").unwrap();
+    env.load_serialized("c").unwrap();
+    // touch `a` again, so `b` is now the oldest
+    env.load_serialized("a").unwrap();
+    assert_eq!(env.cache_size(), 3 * file_size);
+
+    env.set_cache_budget(Some(2 * file_size));
+    assert_eq!(env.trim_cache(), 1);
+    assert_eq!(loaded(&mut env), ["a", "c"]);
+    assert_eq!(env.cache_size(), 2 * file_size);
+
+    // evicted files are transparently reloaded
+    env.load_serialized("b").unwrap();
+    assert_eq!(loaded(&mut env), ["a", "b", "c"]);
+}
+
+#[test]
+fn evict_and_clear() {
+    let mut env = env_with_files(&["a", "b"]);
+    env.load_serialized("a").unwrap();
+    env.load_serialized("b").unwrap();
+
+    assert!(env.evict(Path::new("a")));
+    assert!(!env.evict(Path::new("a")));
+    assert_eq!(loaded(&mut env), ["b"]);
+
+    env.clear_cache();
+    assert!(loaded(&mut env).is_empty());
+    assert_eq!(env.cache_size(), 0);
+}
@@ -0,0 +1,81 @@
+//! Tests for [`rabex_env::diff`] between two in-memory game versions.
+
+use rabex_env::diff::{self, ObjectChange};
+use rabex_env::rabex::objects::TypedPPtr;
+use rabex_env::unity::types::Transform;
+use rabex_env_testkit::{Flat, MemEnv, add_go, build_file, mem_env};
+use serde_json::json;
+
+/// One root GameObject per `(name, x position)`, with path ids assigned in order.
+fn scene(objects: &[(&str, f32)]) -> Vec<u8> {
+    build_file(|sfb| {
+        for &(name, x) in objects {
+            let go = sfb.get_next_path_id();
+            let transform = sfb.get_next_path_id();
+            add_go(sfb, go, name, &[transform]);
+            sfb.add_object_at(
+                transform,
+                &Transform {
+                    m_GameObject: TypedPPtr::local(go),
+                    m_LocalRotation: (0.0, 0.0, 0.0, 1.0),
+                    m_LocalPosition: (x, 0.0, 0.0),
+                    m_LocalScale: (1.0, 1.0, 1.0),
+                    m_Children: Vec::new(),
+                    m_Father: TypedPPtr::null(),
+                },
+            )
+            .unwrap();
+        }
+    })
+}
+
+fn env(level0: Vec<u8>) -> MemEnv {
+    mem_env([
+        ("globalgamemanagers", Flat::new(&[]).write().0),
+        ("level0", level0),
+    ])
+}
+
+#[test]
+fn matches_objects_by_component_path() {
+    let old = env(scene(&[("Player", 0.0), ("Enemy", 0.0), ("Coin", 0.0)]));
+    // every path id changes, but only Enemy moved
+    let new = env(scene(&[("Boss", 0.0), ("Enemy", 1.0), ("Player", 0.0)]));
+
+    let diff = diff::diff_environments(&old, &new).unwrap();
+    assert!(diff.added_files.is_empty());
+    assert!(diff.removed_files.is_empty());
+    assert_eq!(diff.files.len(), 1);
+    assert_eq!(diff.files[0].file, "level0");
+
+    let changes: Vec<_> = diff.files[0]
+        .changes
+        .iter()
+        .map(|change| {
+            let kind = match change {
+                ObjectChange::Added { .. } => "added",
+                ObjectChange::Removed { .. } => "removed",
+                ObjectChange::Changed { .. } => "changed",
+            };
+            (change.id().to_string(), kind)
+        })
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            ("Boss".to_owned(), "added"),
+            ("Boss@Transform".to_owned(), "added"),
+            ("Coin".to_owned(), "removed"),
+            ("Coin@Transform".to_owned(), "removed"),
+            ("Enemy@Transform".to_owned(), "changed"),
+        ]
+    );
+
+    let ObjectChange::Changed { fields, .. } = &diff.files[0].changes[4] else {
+        unreachable!()
+    };
+    assert_eq!(fields.len(), 1);
+    assert_eq!(fields[0].path, "m_LocalPosition.x");
+    assert_eq!(fields[0].old, Some(json!(0.0)));
+    assert_eq!(fields[0].new, Some(json!(1.0)));
+}
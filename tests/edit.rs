@@ -0,0 +1,97 @@
+//! Tests for [`rabex_env::edit::SerializedFileEdit`].
+
+use rabex_env::rabex::objects::ClassId;
+use rabex_env::resolver::MemResolver;
+use rabex_env::unity::types::GameObject;
+use rabex_env_testkit::{Flat, file_referencing_external, with_handle};
+
+fn reopen<T>(
+    bytes: Vec<u8>,
+    f: impl FnOnce(&rabex_env::handle::SerializedFileHandle<'_, MemResolver>) -> T,
+) -> T {
+    with_handle("edited", bytes, f)
+}
+
+#[test]
+fn unchanged_roundtrip() {
+    let (bytes, go_ids) = Flat::new(&["A", "B"]).write();
+    let written = with_handle("level0", bytes, |file| {
+        file.edit().unwrap().write().unwrap()
+    });
+
+    reopen(written, |file| {
+        assert_eq!(file.objects::<()>().len(), 4);
+        let names: Vec<_> = go_ids
+            .iter()
+            .map(|&id| {
+                file.object_at::<GameObject>(id)
+                    .unwrap()
+                    .read()
+                    .unwrap()
+                    .m_Name
+            })
+            .collect();
+        assert_eq!(names, ["A", "B"]);
+    });
+}
+
+#[test]
+fn replace_delete_and_add() {
+    let (bytes, go_ids) = Flat::new(&["A", "B"]).write();
+    let (a, b) = (go_ids[0], go_ids[1]);
+
+    let (written, added) = with_handle("level0", bytes, |file| {
+        let mut edit = file.edit().unwrap();
+
+        let mut go = file.object_at::<GameObject>(a).unwrap().read().unwrap();
+        go.m_Name = "A but longer".to_owned();
+        go.m_IsActive = false;
+        edit.replace(a, &go).unwrap();
+
+        assert!(edit.delete(b));
+        assert!(edit.delete(b + 1));
+        assert!(!edit.delete(b));
+
+        go.m_Name = "C".to_owned();
+        let added = edit.add(a, &go).unwrap();
+        assert!(edit.replace_raw(b, Vec::new()).is_err());
+
+        (edit.write().unwrap(), added)
+    });
+
+    reopen(written, |file| {
+        let ids: Vec<_> = file.objects::<()>().map(|o| o.path_id()).collect();
+        assert_eq!(ids, [a, a + 1, added]);
+
+        let go = file.object_at::<GameObject>(a).unwrap().read().unwrap();
+        assert_eq!(go.m_Name, "A but longer");
+        assert!(!go.m_IsActive);
+
+        let added = file.object_at::<GameObject>(added).unwrap();
+        assert_eq!(added.class_id(), ClassId::GameObject);
+        assert_eq!(added.read().unwrap().m_Name, "C");
+    });
+}
+
+#[test]
+fn add_external() {
+    let (bytes, file_id) = file_referencing_external("sharedassets0.assets");
+    let (written, existing, added) = with_handle("level0", bytes, |file| {
+        let mut edit = file.edit().unwrap();
+        let existing = edit.add_external("sharedassets0.assets");
+        let added = edit.add_external("sharedassets1.assets");
+        (edit.write().unwrap(), existing, added)
+    });
+    assert_eq!(existing, file_id);
+    assert_ne!(added, file_id);
+
+    reopen(written, |file| {
+        let externals: Vec<_> = file
+            .file
+            .m_Externals
+            .iter()
+            .map(|external| external.pathName.as_str())
+            .collect();
+        assert_eq!(externals, ["sharedassets0.assets", "sharedassets1.assets"]);
+    });
+}
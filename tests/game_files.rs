@@ -0,0 +1,51 @@
+//! Tests for listing the files of [`rabex_env::resolver::GameFiles`] on disk.
+
+use std::path::{Path, PathBuf};
+
+use rabex_env::resolver::{EnvResolver, FileSource, GameFiles};
+use rabex_env_testkit::{Flat, bundle_with_serialized};
+
+#[test]
+fn all_files_of_packed_game() {
+    let tmp = tempfile::TempDir::new().unwrap();
+    let data_dir = tmp.path().join("Game_Data");
+    std::fs::create_dir_all(data_dir.join("StreamingAssets")).unwrap();
+    std::fs::create_dir_all(data_dir.join("Resources")).unwrap();
+    let level0 = Flat::new(&[]).write().0;
+    std::fs::write(
+        data_dir.join("data.unity3d"),
+        bundle_with_serialized("level0", &level0),
+    )
+    .unwrap();
+    std::fs::write(data_dir.join("resources.assets"), &level0).unwrap();
+    std::fs::write(data_dir.join("StreamingAssets/loose.assets"), &level0).unwrap();
+    std::fs::write(data_dir.join("Resources/unity_builtin_extra"), &level0).unwrap();
+
+    let game_files = GameFiles::probe(tmp.path()).unwrap();
+    assert_eq!(
+        game_files.all_files_with_source().unwrap(),
+        [
+            (
+                PathBuf::from("Resources/unity_builtin_extra"),
+                FileSource::Disk
+            ),
+            (
+                PathBuf::from("StreamingAssets/loose.assets"),
+                FileSource::Disk
+            ),
+            (PathBuf::from("data.unity3d"), FileSource::Disk),
+            (PathBuf::from("level0"), FileSource::Packed),
+            (PathBuf::from("resources.assets"), FileSource::Disk),
+        ]
+    );
+    assert_eq!(
+        game_files.serialized_files().unwrap(),
+        [
+            Path::new("StreamingAssets/loose.assets"),
+            Path::new("level0"),
+            Path::new("resources.assets"),
+        ]
+    );
+    assert_eq!(game_files.level_files().unwrap(), [0]);
+    assert!(game_files.exists(Path::new("level0")).unwrap());
+}
@@ -3,15 +3,11 @@
 //! Scenes are built in memory (see `fixtures`) and re-opened through a real `Environment`, so the
 //! resolver runs against a genuine `SerializedFileHandle`.
 
-use rabex_env::Environment;
 use rabex_env::qualify::Qualifier;
 use rabex_env::rabex::objects::PPtr;
 use rabex_env::rabex::objects::pptr::PathId;
-use rabex_env::rabex::tpk::TpkTypeTreeBlob;
-use rabex_env::rabex::typetree::typetree_cache::sync::TypeTreeCache;
-use rabex_env::resolver::MemResolver;
 use rabex_env_testkit::{
-    Flat, add_go, add_scripted_mb, add_transform, build_file, file_referencing_external,
+    Flat, add_go, add_scripted_mb, add_transform, build_file, file_referencing_external, mem_env,
     named_asset_file, scene_with_script_component, with_handle,
 };
 
@@ -146,11 +142,7 @@ fn external_name_survives_a_dangling_sibling_ref() {
     let (ext_bytes, asset_id) = named_asset_file("dust_roach_clip");
     let (scene_bytes, ext_fid) = file_referencing_external("ext.assets");
 
-    let mut resolver = MemResolver::new();
-    resolver.insert("scene", scene_bytes);
-    resolver.insert("ext.assets", ext_bytes);
-    let tpk = TypeTreeCache::new(TpkTypeTreeBlob::embedded());
-    let env = Environment::new(resolver, tpk);
+    let env = mem_env([("scene", scene_bytes), ("ext.assets", ext_bytes)]);
     let handle = env.load_serialized("scene").unwrap();
 
     let mut q = Qualifier::new(&handle);
@@ -0,0 +1,201 @@
+//! Tests for [`rabex_env::reachable::transitive`].
+
+use std::collections::BTreeSet;
+
+use rabex_env::rabex::UnityVersion;
+use rabex_env::rabex::objects::pptr::PathId;
+use rabex_env::rabex::objects::{ClassId, PPtr, TypedPPtr};
+use rabex_env::rabex::typetree::{TypeTreeNode, TypeTreeProvider};
+use rabex_env::reachable::transitive::reachable_transitive;
+use rabex_env::unity::types::{ComponentPair, GameObject, MonoScript};
+use rabex_env_testkit::{
+    Builder, Flat, MemEnv, TEST_UNITY_VERSION, add_go, add_transform, build_file,
+    build_stripped_file, mem_env, named_asset_file,
+};
+use serde_derive::Serialize;
+
+/// A GameObject with a Transform and a "component" pointing at `path_id` in `external`.
+/// Returns `(bytes, gameobject, transform)`.
+fn scene_referencing(external: &str, path_id: PathId) -> (Vec<u8>, PathId, PathId) {
+    let (mut go_id, mut transform_id) = (0, 0);
+    let bytes = build_file(|sfb| {
+        let file_id = sfb.get_or_insert_external(external);
+        go_id = sfb.get_next_path_id();
+        transform_id = sfb.get_next_path_id();
+        let go = GameObject {
+            m_Component: vec![
+                ComponentPair {
+                    component: PPtr::local(transform_id),
+                },
+                ComponentPair {
+                    component: PPtr {
+                        m_FileID: file_id,
+                        m_PathID: path_id,
+                    },
+                },
+            ],
+            m_Layer: 0,
+            m_Name: "Root".to_owned(),
+            m_Tag: 0,
+            m_IsActive: true,
+        };
+        sfb.add_object_at(go_id, &go).unwrap();
+        add_transform(sfb, transform_id, go_id, None, &[]);
+    });
+    (bytes, go_id, transform_id)
+}
+
+fn objects(items: &[(&str, PathId)]) -> BTreeSet<(String, PathId)> {
+    items
+        .iter()
+        .map(|&(file, path_id)| (file.to_owned(), path_id))
+        .collect()
+}
+
+#[test]
+fn follows_external_references() {
+    let (shared, asset) = named_asset_file("Asset");
+    let (scene, go, transform) = scene_referencing("sharedassets0.assets", asset);
+    let env = mem_env([("level0", scene), ("sharedassets0.assets", shared)]);
+
+    let reachable = reachable_transitive(&env, "level0", [go], |_, _, _| false).unwrap();
+    assert_eq!(
+        reachable.objects,
+        objects(&[
+            ("level0", go),
+            ("level0", transform),
+            ("sharedassets0.assets", asset)
+        ])
+    );
+    assert_eq!(
+        reachable.file_edges,
+        BTreeSet::from([("level0".to_owned(), "sharedassets0.assets".to_owned())])
+    );
+    assert!(reachable.unresolved.is_empty());
+
+    let stopped = reachable_transitive(&env, "level0", [go], |_, _, class_id| {
+        class_id == ClassId::GameObject
+    })
+    .unwrap();
+    assert_eq!(stopped.objects, objects(&[("level0", go)]));
+    assert!(stopped.file_edges.is_empty());
+}
+
+#[test]
+fn missing_files_are_unresolved() {
+    let (scene, go, transform) = scene_referencing("missing.assets", 1);
+    let env = mem_env([("level0", scene)]);
+
+    let reachable = reachable_transitive(&env, "level0", [go], |_, _, _| false).unwrap();
+    assert_eq!(
+        reachable.objects,
+        objects(&[("level0", go), ("level0", transform)])
+    );
+    assert_eq!(reachable.unresolved, objects(&[("missing.assets", 1)]));
+}
+
+/// A MonoBehaviour of a script with a `target` field, which isn't part of the MonoBehaviour header
+#[derive(Serialize)]
+#[allow(non_snake_case)]
+struct Follower {
+    m_GameObject: TypedPPtr<GameObject>,
+    m_Enabled: u8,
+    m_Script: TypedPPtr<MonoScript>,
+    m_Name: String,
+    target: TypedPPtr<GameObject>,
+}
+
+/// The typetree of [`Follower`], as generated from the script assembly
+fn follower_typetree(tpk: &impl TypeTreeProvider) -> TypeTreeNode {
+    let unity_version: UnityVersion = TEST_UNITY_VERSION.parse().unwrap();
+    let mut tt = tpk
+        .get_typetree_node(ClassId::MonoBehaviour, &unity_version)
+        .unwrap()
+        .into_owned();
+    let mut target = tt
+        .children
+        .iter()
+        .find(|field| field.m_Name == "m_GameObject")
+        .unwrap()
+        .clone();
+    target.m_Name = "target".to_owned();
+    tt.children.push(target);
+    tt
+}
+
+/// A GameObject with a `Follower` component pointing at a second GameObject,
+/// with or without embedded typetrees. Returns `(bytes, gameobject, target)`.
+fn follower_scene(stripped: bool) -> (Vec<u8>, PathId, PathId) {
+    let (mut go_id, mut target_id) = (0, 0);
+    let build = |sfb: &mut Builder<'_>| {
+        let script_id = sfb
+            .add_object(&MonoScript {
+                m_Name: "Follower".to_owned(),
+                m_ExecutionOrder: 0,
+                m_PropertiesHash: [0; 16],
+                m_ClassName: "Follower".to_owned(),
+                m_Namespace: String::new(),
+                m_AssemblyName: "Assembly-CSharp.dll".to_owned(),
+            })
+            .unwrap();
+        go_id = sfb.get_next_path_id();
+        target_id = sfb.get_next_path_id();
+
+        let tt = follower_typetree(sfb.typetree_provider);
+        let mb_type = sfb.add_monobehaviour_type(PPtr::local(script_id), Some(tt));
+        let mb_id = sfb
+            .add_monobehaviour_with_type(
+                &Follower {
+                    m_GameObject: TypedPPtr::local(go_id),
+                    m_Enabled: 1,
+                    m_Script: TypedPPtr::local(script_id),
+                    m_Name: String::new(),
+                    target: TypedPPtr::local(target_id),
+                },
+                mb_type,
+            )
+            .unwrap();
+        add_go(sfb, go_id, "Follower", &[mb_id]);
+        add_go(sfb, target_id, "Target", &[]);
+    };
+    let bytes = match stripped {
+        true => build_stripped_file(build),
+        false => build_file(build),
+    };
+    (bytes, go_id, target_id)
+}
+
+fn reachable_ids(env: &MemEnv, go: PathId) -> BTreeSet<PathId> {
+    let reachable = reachable_transitive(env, "level0", [go], |_, _, _| false).unwrap();
+    reachable
+        .objects
+        .into_iter()
+        .map(|(_, path_id)| path_id)
+        .collect()
+}
+
+#[test]
+fn follows_script_fields_of_embedded_typetree() {
+    let (scene, go, target) = follower_scene(false);
+    // no assemblies to generate from, so this only works off the embedded typetree
+    let env = mem_env([("level0", scene)]);
+
+    assert!(reachable_ids(&env, go).contains(&target));
+}
+
+#[test]
+fn follows_script_fields_of_stripped_typetree() {
+    let (scene, go, target) = follower_scene(true);
+    let (ggm, _) = Flat::new(&[]).write();
+    let env = mem_env([("level0", scene), ("globalgamemanagers", ggm)]);
+
+    // without assemblies, only the MonoBehaviour header can be traced
+    assert!(!reachable_ids(&env, go).contains(&target));
+
+    env.typetree_generator.insert_cache(
+        "Assembly-CSharp.dll",
+        "Follower",
+        follower_typetree(&env.tpk),
+    );
+    assert!(reachable_ids(&env, go).contains(&target));
+}
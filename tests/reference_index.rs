@@ -0,0 +1,51 @@
+//! Tests for [`rabex_env::reference_index::ReferenceIndex`].
+
+use rabex_env::reference_index::{ObjectLocation, ReferenceIndex};
+use rabex_env_testkit::{Flat, mem_env};
+
+#[test]
+fn gameobject_and_transform_reference_each_other() {
+    let (bytes, go_ids) = Flat::new(&["A", "B"]).write();
+    let env = mem_env([("level0", bytes)]);
+
+    let index = ReferenceIndex::build(&env).unwrap();
+    assert_eq!(index.files().collect::<Vec<_>>(), ["level0"]);
+
+    for go in go_ids {
+        // Flat writes each Transform right after its GameObject
+        let transform = go + 1;
+        assert_eq!(
+            index.referrers("level0", transform).collect::<Vec<_>>(),
+            [ObjectLocation {
+                file: "level0",
+                path_id: go
+            }]
+        );
+        assert_eq!(
+            index.referrers("level0", go).collect::<Vec<_>>(),
+            [ObjectLocation {
+                file: "level0",
+                path_id: transform
+            }]
+        );
+    }
+    assert_eq!(index.referrers("level1", 1).count(), 0);
+}
+
+#[test]
+fn roundtrips_through_binary_format() {
+    let (bytes, go_ids) = Flat::new(&["A"]).write();
+    let env = mem_env([("level0", bytes)]);
+    let index = ReferenceIndex::build(&env).unwrap();
+
+    let mut out = Vec::new();
+    index.write_to(&mut out).unwrap();
+    let read = ReferenceIndex::read_from(out.as_slice()).unwrap();
+
+    assert_eq!(read.len(), index.len());
+    assert_eq!(
+        read.referrers("level0", go_ids[0]).collect::<Vec<_>>(),
+        index.referrers("level0", go_ids[0]).collect::<Vec<_>>(),
+    );
+    assert!(ReferenceIndex::read_from(&b"nope"[..]).is_err());
+}
@@ -0,0 +1,60 @@
+//! Tests for [`rabex_env::repack::repack_scene`].
+
+use std::io::Cursor;
+
+use rabex_env::rabex::files::bundlefile::{BundleFileReader, ExtractionConfig};
+use rabex_env::repack::{RepackSceneOptions, repack_scene};
+use rabex_env::unity::types::{AssetBundle, GameObject};
+use rabex_env_testkit::{Flat, mem_env};
+
+#[test]
+fn repacks_retained_objects() {
+    let (scene, go_ids) = Flat::new(&["A", "B"]).write();
+    let env = mem_env([
+        ("globalgamemanagers", Flat::new(&[]).write().0),
+        ("level0", scene),
+    ]);
+
+    let options = RepackSceneOptions::new("scene", "Assets/Scenes/Test.unity");
+    let bundle = repack_scene(&env, "level0", ["B"], &options).unwrap();
+
+    let bundle =
+        BundleFileReader::from_reader(Cursor::new(bundle), &ExtractionConfig::default()).unwrap();
+    let mut files: Vec<_> = bundle
+        .files()
+        .into_iter()
+        .map(|file| file.path.as_str())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["BuildPlayer-Test", "BuildPlayer-Test.sharedAssets"]);
+
+    let repacked = mem_env([
+        ("globalgamemanagers", Flat::new(&[]).write().0),
+        (
+            "scene",
+            bundle.read_at("BuildPlayer-Test").unwrap().unwrap(),
+        ),
+        (
+            "shared",
+            bundle
+                .read_at("BuildPlayer-Test.sharedAssets")
+                .unwrap()
+                .unwrap(),
+        ),
+    ]);
+
+    let scene = repacked.load_serialized("scene").unwrap();
+    let ids: Vec<_> = scene.objects::<()>().map(|o| o.path_id()).collect();
+    assert_eq!(ids, [go_ids[1], go_ids[1] + 1]);
+    let go = scene.object_at::<GameObject>(go_ids[1]).unwrap();
+    assert_eq!(go.read().unwrap().m_Name, "B");
+
+    let shared = repacked.load_serialized("shared").unwrap();
+    let asset_bundle = shared.find_object_of::<AssetBundle>().unwrap().unwrap();
+    assert_eq!(asset_bundle.m_AssetBundleName, "scene");
+    assert!(asset_bundle.m_IsStreamedSceneAssetBundle);
+    assert_eq!(
+        asset_bundle.m_SceneHashes["Assets/Scenes/Test.unity"],
+        "BuildPlayer-Test"
+    );
+}
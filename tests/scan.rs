@@ -0,0 +1,101 @@
+//! Tests for [`rabex_env::Environment::objects`] and [`rabex_env::Environment::fold_objects`].
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use rabex_env::rabex::objects::ClassId;
+use rabex_env::rabex::objects::pptr::PathId;
+use rabex_env::scan::ObjectFilter;
+use rabex_env::unity::types::GameObject;
+use rabex_env_testkit::{Flat, MemEnv, mem_env, scene_with_script_component};
+
+/// Two levels with a scripted GameObject each, and a shared file without scripts.
+/// Returns the env and the path id of the `HeroController` MonoBehaviour.
+fn game() -> (MemEnv, PathId) {
+    let (ggm, _) = Flat::new(&[]).write();
+    let (level0, _, _) = scene_with_script_component("Enemy", "PlayMakerFSM");
+    let (level1, _, hero) = scene_with_script_component("Player", "HeroController");
+    let (shared, _) = Flat::new(&["Camera"]).write();
+    let env = mem_env([
+        ("globalgamemanagers", ggm),
+        ("level0", level0),
+        ("level1", level1),
+        ("sharedassets0.assets", shared),
+    ]);
+    (env, hero)
+}
+
+#[test]
+fn class_filter() {
+    let (env, _) = game();
+    let filter = ObjectFilter::class(ClassId::GameObject);
+
+    for parallel in [false, true] {
+        let names: BTreeSet<_> = env
+            .objects(&filter, parallel)
+            .unwrap()
+            .map(|object| {
+                let object = object.unwrap();
+                assert_eq!(object.class_id(), ClassId::GameObject);
+                object.cast_owned::<GameObject>().read().unwrap().m_Name
+            })
+            .collect();
+        assert_eq!(
+            names,
+            BTreeSet::from(["Camera".into(), "Enemy".into(), "Player".into()])
+        );
+
+        let count = env
+            .fold_objects(&filter, parallel, |count: &mut usize, _, _| {
+                *count += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+}
+
+#[test]
+fn script_filter() {
+    let (env, hero) = game();
+    let script = "HeroController";
+    let filter = ObjectFilter::script(&script);
+
+    let found: Vec<_> = env
+        .objects(&filter, false)
+        .unwrap()
+        .map(|object| object.unwrap().path_id())
+        .collect();
+    assert_eq!(found, [hero]);
+
+    let found = env
+        .fold_objects(
+            &filter,
+            true,
+            |found: &mut Vec<(PathBuf, PathId)>, path, object| {
+                found.push((path.to_owned(), object.path_id()));
+                Ok(())
+            },
+        )
+        .unwrap();
+    assert_eq!(found, [(PathBuf::from("level1"), hero)]);
+}
+
+#[test]
+fn unreadable_file_is_an_item_error() {
+    let (ggm, _) = Flat::new(&[]).write();
+    let (level0, _) = Flat::new(&["Player"]).write();
+    let env = mem_env([
+        ("globalgamemanagers", ggm),
+        ("level0", level0),
+        ("level1", b"not a serialized file".to_vec()),
+    ]);
+
+    let filter = ObjectFilter::class(ClassId::GameObject);
+    let (objects, errors): (Vec<_>, Vec<_>) = env
+        .objects(&filter, false)
+        .unwrap()
+        .partition(Result::is_ok);
+    assert_eq!(objects.len(), 1);
+    assert_eq!(errors.len(), 1);
+}
@@ -0,0 +1,70 @@
+//! Tests for importing game versions into a [`rabex_env::resolver::SnapshotStore`].
+
+use std::path::Path;
+
+use rabex_env::resolver::snapshot::ImportStats;
+use rabex_env::resolver::{EnvResolver, GameFiles, SnapshotStore};
+use rabex_env::unity::types::GameObject;
+use rabex_env_testkit::{Flat, add_go, build_file, tpk_env};
+
+fn level(name: &str) -> Vec<u8> {
+    build_file(|sfb| {
+        let go = sfb.get_next_path_id();
+        add_go(sfb, go, name, &[]);
+    })
+}
+
+fn write_game(dir: &Path, level0: &[u8]) -> GameFiles {
+    let data_dir = dir.join("Game_Data");
+    std::fs::create_dir_all(data_dir.join("StreamingAssets")).unwrap();
+    std::fs::write(
+        data_dir.join("globalgamemanagers"),
+        Flat::new(&[]).write().0,
+    )
+    .unwrap();
+    std::fs::write(data_dir.join("level0"), level0).unwrap();
+    std::fs::write(data_dir.join("StreamingAssets/config.json"), b"{}").unwrap();
+    GameFiles::probe(dir).unwrap()
+}
+
+#[test]
+fn deduplicates_versions() {
+    let tmp = tempfile::TempDir::new().unwrap();
+    let store = SnapshotStore::open(tmp.path().join("store")).unwrap();
+
+    let old = write_game(&tmp.path().join("old"), &level("Player"));
+    let stats = store.import("1.0", &old).unwrap();
+    assert_eq!(stats.files, 3);
+    assert_eq!(stats.new_objects, 3);
+
+    let new_level0 = level("Boss");
+    let new = write_game(&tmp.path().join("new"), &new_level0);
+    let stats = store.import("1.1", &new).unwrap();
+    assert_eq!(
+        stats,
+        ImportStats {
+            files: 3,
+            new_objects: 1,
+            new_bytes: new_level0.len() as u64,
+        }
+    );
+    assert_eq!(store.versions().unwrap(), ["1.0", "1.1"]);
+    assert!(store.import("../escape", &new).is_err());
+
+    let resolver = store.resolver("1.1").unwrap();
+    assert_eq!(
+        resolver.list_under(Path::new("StreamingAssets")).unwrap(),
+        [Path::new("StreamingAssets/config.json")]
+    );
+    assert_eq!(
+        resolver.read_path(Path::new("level0")).unwrap().as_ref(),
+        new_level0
+    );
+
+    for (version, name) in [("1.0", "Player"), ("1.1", "Boss")] {
+        let env = tpk_env(store.resolver(version).unwrap());
+        let level0 = env.load_serialized("level0").unwrap();
+        let go = level0.find_object_of::<GameObject>().unwrap().unwrap();
+        assert_eq!(go.m_Name, name);
+    }
+}
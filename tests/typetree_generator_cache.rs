@@ -0,0 +1,92 @@
+//! Tests for persisting [`rabex_env::typetree_generator_cache::TypeTreeGeneratorCache`].
+
+use rabex_env::rabex::objects::ClassId;
+use rabex_env::rabex::typetree::{TypeTreeNode, TypeTreeProvider};
+use rabex_env_testkit::{Flat, MemEnv, TEST_UNITY_VERSION, mem_env};
+
+/// A game with a globalgamemanagers and the given `Managed/` assemblies
+fn game(assemblies: &[(&str, &[u8])]) -> MemEnv {
+    let (ggm, _) = Flat::new(&[]).write();
+    let mut files = vec![("globalgamemanagers".to_owned(), ggm)];
+    files.extend(
+        assemblies
+            .iter()
+            .map(|(name, data)| (format!("Managed/{name}"), data.to_vec())),
+    );
+    mem_env(files)
+}
+
+fn monobehaviour_tt(env: &MemEnv) -> TypeTreeNode {
+    env.tpk
+        .get_typetree_node(ClassId::MonoBehaviour, &TEST_UNITY_VERSION.parse().unwrap())
+        .unwrap()
+        .into_owned()
+}
+
+#[test]
+fn save_load_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("typetrees.json");
+    let assemblies: &[(&str, &[u8])] = &[
+        ("Assembly-CSharp.dll", b"scripts"),
+        ("UnityEngine.CoreModule.dll", b"engine"),
+    ];
+
+    let env = game(assemblies);
+    let tt = monobehaviour_tt(&env);
+    let generator = &env.typetree_generator;
+    generator.insert_cache("Assembly-CSharp.dll", "Game.Player", tt.clone());
+    generator.insert_reference_cache("Assembly-CSharp.dll", "Game.Stats", tt.clone());
+    generator.save(&env, &path).unwrap();
+
+    let env = game(assemblies);
+    assert_eq!(env.typetree_generator.load(&env, &path).unwrap(), 2);
+    // the assemblies aren't valid, so these can only come from the cache
+    let player = env
+        .generate_typetree("Assembly-CSharp.dll", "Game.Player")
+        .unwrap()
+        .unwrap();
+    assert_eq!(player.children.len(), tt.children.len());
+    assert!(
+        env.generate_managed_reference_typetree("Assembly-CSharp.dll", "Game.Stats")
+            .unwrap()
+            .is_some()
+    );
+}
+
+#[test]
+fn changed_assembly_invalidates() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("typetrees.json");
+
+    let env = game(&[
+        ("Assembly-CSharp.dll", b"scripts"),
+        ("UnityEngine.CoreModule.dll", b"engine"),
+    ]);
+    let tt = monobehaviour_tt(&env);
+    env.typetree_generator
+        .insert_cache("Assembly-CSharp.dll", "Game.Player", tt);
+    env.typetree_generator.save(&env, &path).unwrap();
+
+    // a field type may come from any assembly, not only the one defining the script
+    let env = game(&[
+        ("Assembly-CSharp.dll", b"scripts"),
+        ("UnityEngine.CoreModule.dll", b"engine v2"),
+    ]);
+    assert_eq!(env.typetree_generator.load(&env, &path).unwrap(), 0);
+
+    let env = game(&[("Assembly-CSharp.dll", b"scripts")]);
+    assert_eq!(env.typetree_generator.load(&env, &path).unwrap(), 0);
+}
+
+#[test]
+fn failed_generation_is_remembered() {
+    let env = game(&[]);
+    let first = env
+        .generate_typetree("Assembly-CSharp.dll", "Game.Player")
+        .unwrap_err();
+    let second = env
+        .generate_typetree("Assembly-CSharp.dll", "Game.Player")
+        .unwrap_err();
+    assert_eq!(format!("{second:#}"), format!("{first:#}"));
+}